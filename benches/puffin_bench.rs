@@ -2,11 +2,12 @@
 //! This module contains benchmark tests for the Puffin Language.
 //! Specifically, these tests are meant to measure the speed of the interpreter,
 //! rather than the speed of the parser or AST generator.
-//! Because of this, these tests parse and build the AST as setup, only
+//! Because of this, these tests parse the program as setup, only
 //! measuring the actual execution of the program itself.
+//! The exception is `parse_hashmap`, which measures the parser.
 
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use puffin::{Parser, PuffinParser, ast, interpreter};
+use puffin::interpreter;
 
 
 /// Recursively compute the 15th number in the fibonacci sequence
@@ -25,8 +26,7 @@ pub fn fib_15_recursive(c: &mut Criterion) {
     return fib(15);
     ";
 
-    let mut parsed = PuffinParser::parse(puffin::Rule::program, &program).unwrap();
-    let prog_ast = ast::build_program(parsed.next().unwrap()).unwrap();
+    let prog_ast = puffin::parse(program).unwrap();
 
     c.bench_function("fib 15", |b| b.iter(|| {
         interpreter::eval(black_box(&prog_ast))
@@ -51,8 +51,7 @@ pub fn fact_1_150_iterative(c: &mut Criterion) {
     return res;
     ";
 
-    let mut parsed = PuffinParser::parse(puffin::Rule::program, &program).unwrap();
-    let prog_ast = ast::build_program(parsed.next().unwrap()).unwrap();
+    let prog_ast = puffin::parse(program).unwrap();
 
     c.bench_function("fact 1-150", |b| b.iter(|| {
         interpreter::eval(black_box(&prog_ast))
@@ -100,8 +99,7 @@ pub fn first_500_primes(c: &mut Criterion) {
 
     return res;
     ";
-    let mut parsed = PuffinParser::parse(puffin::Rule::program, &program).unwrap();
-    let prog_ast = ast::build_program(parsed.next().unwrap()).unwrap();
+    let prog_ast = puffin::parse(program).unwrap();
 
     c.bench_function("first 500 primes", |b| b.iter(|| {
         interpreter::eval(black_box(&prog_ast))
@@ -225,8 +223,7 @@ pub fn puffin_hashmap_struct(c: &mut Criterion) {
         h.remove(i);
     }
     "#;
-    let mut parsed = PuffinParser::parse(puffin::Rule::program, &program).unwrap();
-    let prog_ast = ast::build_program(parsed.next().unwrap()).unwrap();
+    let prog_ast = puffin::parse(program).unwrap();
 
     c.bench_function("puffin hashmap 0:1000", |b| b.iter(|| {
         interpreter::eval(black_box(&prog_ast))
@@ -234,5 +231,14 @@ pub fn puffin_hashmap_struct(c: &mut Criterion) {
 
 }
 
-criterion_group!(benches, fib_15_recursive, fact_1_150_iterative, first_500_primes, puffin_hashmap_struct);
+/// Parse the hashmap sample program into its AST
+pub fn parse_hashmap(c: &mut Criterion) {
+    let program = include_str!("../samples/hashmap.puf");
+
+    c.bench_function("parse hashmap", |b| b.iter(|| {
+        puffin::parse(black_box(program))
+    }));
+}
+
+criterion_group!(benches, fib_15_recursive, fact_1_150_iterative, first_500_primes, puffin_hashmap_struct, parse_hashmap);
 criterion_main!(benches);
\ No newline at end of file
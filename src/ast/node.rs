@@ -1,19 +1,34 @@
 //! Author: Rafael Bayer (2021)
 //! This module contains definitons for all AST nodes.
 //! The root node is the struct Program, 
-//! which contains a vector of all the programs statements
+//! which contains a vector of all the programs statements.
+//! Statements, expressions, and blocks carry the span of source they were parsed from,
+//! and the program keeps its comments, so it can be formatted back into source.
+//! Function arguments, return values, and assignments may be annotated with a `Type`.
 
+use std::fmt::Display;
+
+use crate::parser::Span;
 
 /// AST Root node, the program contains several statements
 #[derive(Debug, Clone, PartialEq)]
 pub struct Program {
     pub program: Vec<Statement>,
+    pub comments: Vec<Comment>,
+}
+
+/// Comment in the source, including the leading `//`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comment {
+    pub text: String,
+    pub span: Span,
 }
 
 /// Statement Node, contains one of several kinds of statements
 #[derive(Debug, Clone, PartialEq)]
 pub struct Statement {
     pub statement: StatementKind,
+    pub span: Span,
 }
 
 /// StatementKind, variants represent types of puffin statements
@@ -21,8 +36,15 @@ pub struct Statement {
 pub enum StatementKind {
     /// Explicit Return Statement, returns an expression
     Return(Exp),
-    /// Assigment statement, assigns lhs to rhs
-    Assign { lhs: Assignable, rhs: Exp },
+    /// Assigment statement, assigns lhs to rhs.
+    /// Augmented assignments (`a op= b`) are expanded to `a = a op b`, keeping the operator in aug
+    /// An assignment to a name can be annotated with the type of the value (`a: num = 5`)
+    Assign {
+        lhs: Assignable,
+        rhs: Exp,
+        aug: Option<InfixOp>,
+        annotation: Option<Type>,
+    },
     /// Expression statement
     Exp(Exp),
     /// Nest statement, conditional or loop
@@ -43,30 +65,40 @@ pub enum AssignableKind {
     StructureField { field: String },
 }
 
+/// Expression Node, a tree of operators applied to values
 #[derive(Debug, Clone, PartialEq)]
 pub struct Exp {
-    pub exp: Vec<TermKind>,
+    pub exp: ExpKind,
+    pub span: Span,
 }
 
-type Precedence = usize;
-
+/// ExpKind, variants represent the parts of an expression tree.
+/// Operator precedence and associativity are resolved by the parser
 #[derive(Debug, Clone, PartialEq)]
-pub enum TermKind {
-    Operator(OperatorKind, Associativity, Precedence),
+pub enum ExpKind {
+    /// Value, such as a literal, name, or parenthesized expression
     Value(ValueKind),
-}
-
-#[derive(Debug, Clone, PartialEq)]
-pub enum Associativity {
-    Left,
-    Right,
+    /// Unary (prefix) operator applied to an operand
+    Unary(Unop, Box<Exp>),
+    /// Infix operator applied to a left and right operand
+    Infix(InfixOp, Box<Exp>, Box<Exp>),
+    /// Postfix operator applied to an operand
+    Postfix(Box<Exp>, PostOp),
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum ValueKind {
     Paren(Box<Exp>),
     Structure(Vec<Field>),
-    FunctionDef { args: Vec<String>, block: Block },
+    /// Function definition, lambdas (`fn(a) => a`) are expanded to a block returning their expression
+    FunctionDef {
+        args: Vec<String>,
+        /// Span of the name of each argument
+        arg_spans: Vec<Span>,
+        signature: Signature,
+        block: Block,
+        lambda: bool,
+    },
     Num(f64),
     String(String),
     ArrayInit(ArrayInitKind),
@@ -74,6 +106,71 @@ pub enum ValueKind {
     Null,
 }
 
+/// Type annotations of a function, `fn(a: num, b) -> string`.
+/// Has an entry for every argument, None where an argument isn't annotated
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Signature {
+    pub args: Vec<Option<Type>>,
+    pub ret: Option<Type>,
+}
+
+impl Signature {
+    /// Returns true if nothing in this signature is annotated
+    pub fn is_empty(&self) -> bool {
+        self.ret.is_none() && self.args.iter().all(Option::is_none)
+    }
+}
+
+/// Type of a value, as named by the `typeof` builtin
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Type {
+    Null,
+    Num,
+    String,
+    Array,
+    Struct,
+    Closure,
+    Builtin,
+    Type,
+}
+
+impl Type {
+    /// Returns the type named `name`, if any
+    pub fn from_name(name: &str) -> Option<Type> {
+        Some(match name {
+            "null" => Type::Null,
+            "num" => Type::Num,
+            "string" => Type::String,
+            "array" => Type::Array,
+            "struct" => Type::Struct,
+            "closure" => Type::Closure,
+            "builtin" => Type::Builtin,
+            "type" => Type::Type,
+            _ => return None,
+        })
+    }
+
+    /// Returns the name of this type
+    pub fn name(&self) -> &'static str {
+        match self {
+            Type::Null => "null",
+            Type::Num => "num",
+            Type::String => "string",
+            Type::Array => "array",
+            Type::Struct => "struct",
+            Type::Closure => "closure",
+            Type::Builtin => "builtin",
+            Type::Type => "type",
+        }
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum ArrayInitKind {
     Sized(Box<Exp>),
@@ -89,6 +186,7 @@ pub struct Field {
 #[derive(Debug, Clone, PartialEq)]
 pub struct Block {
     pub block: Vec<Statement>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -110,13 +208,6 @@ pub enum CondNestKind {
     },
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub enum OperatorKind {
-    Unary(Unop),
-    Infix(InfixOp),
-    Postfix(PostOp),
-}
-
 #[derive(Debug, Clone, PartialEq)]
 pub enum LoopNestKind {
     While {
@@ -166,3 +257,48 @@ pub enum Unop {
     Not,
     Neg,
 }
+
+impl InfixOp {
+    /// Precedence of the operator, operators of higher precedence bind tighter.
+    /// All infix operators are left associative
+    pub fn precedence(&self) -> usize {
+        match self {
+            InfixOp::Or => 0,
+            InfixOp::And => 1,
+            InfixOp::Eq | InfixOp::Ne => 2,
+            InfixOp::Lt | InfixOp::Gt | InfixOp::Le | InfixOp::Ge => 3,
+            InfixOp::Plus | InfixOp::Minus => 4,
+            InfixOp::Mul | InfixOp::Div | InfixOp::Mod => 5,
+        }
+    }
+}
+
+impl Display for InfixOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let op = match self {
+            InfixOp::Mul => "*",
+            InfixOp::Mod => "%",
+            InfixOp::Div => "/",
+            InfixOp::Plus => "+",
+            InfixOp::Minus => "-",
+            InfixOp::Lt => "<",
+            InfixOp::Gt => ">",
+            InfixOp::Le => "<=",
+            InfixOp::Ge => ">=",
+            InfixOp::Eq => "==",
+            InfixOp::Ne => "!=",
+            InfixOp::And => "&&",
+            InfixOp::Or => "||",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+impl Display for Unop {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Unop::Not => write!(f, "!"),
+            Unop::Neg => write!(f, "-"),
+        }
+    }
+}
@@ -0,0 +1,269 @@
+//! Author: Rafael Bayer (2021)
+//! The visit module walks the AST of a program, for the tools that analyze it without running it.
+//!
+//! A `Visitor` is called for every statement, expression, and function definition,
+//! and for every name bound in a scope. Names are scoped the way the interpreter's `Environment`
+//! scopes them: each function has its own scope, blocks don't, and a function sees every name
+//! bound in the scopes it was defined in, even those bound after its definition. So every name
+//! bound in a scope is passed to `Visitor::bind` before the statements of the scope are visited.
+//!
+//! Each method of a `Visitor` walks the children of its node by default. Visitors override
+//! the nodes they need, and call the matching `walk_` function to continue into the children.
+
+use super::node::*;
+use crate::parser::Span;
+
+/// Function definition
+#[derive(Debug, Clone, Copy)]
+pub struct Function<'a> {
+    /// name the definition is assigned to, if it is assigned to a name
+    pub name: Option<&'a str>,
+    /// span of the whole definition
+    pub span: Span,
+    pub args: &'a [String],
+    pub arg_spans: &'a [Span],
+    pub signature: &'a Signature,
+    pub block: &'a Block,
+    /// fields of the structure the definition is a field of, if it is one
+    pub structure: Option<&'a [Field]>,
+}
+
+impl<'a> Function<'a> {
+    /// Returns the Function defined by an expression, if it is a function definition
+    pub fn new(exp: &'a Exp) -> Option<Function<'a>> {
+        match &exp.exp {
+            ExpKind::Value(ValueKind::FunctionDef {
+                args,
+                arg_spans,
+                signature,
+                block,
+                ..
+            }) => Some(Function {
+                name: None,
+                span: exp.span,
+                args,
+                arg_spans,
+                signature,
+                block,
+                structure: None,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the function is a receiver, which binds `self` to its structure
+    pub fn is_receiver(&self) -> bool {
+        self.structure.is_some() && self.args.first().map(String::as_str) == Some("self")
+    }
+}
+
+/// Name bound in a scope
+#[derive(Debug, Clone, Copy)]
+pub enum Binding<'a> {
+    /// argument of the function the scope belongs to
+    Argument {
+        name: &'a str,
+        span: Span,
+        annotation: Option<Type>,
+    },
+    /// `name = rhs`, anywhere in the scope
+    Assign {
+        name: &'a str,
+        statement: &'a Statement,
+        rhs: &'a Exp,
+        annotation: Option<Type>,
+    },
+    /// `for (name in array)`, anywhere in the scope
+    Loop { name: &'a str, statement: &'a Statement },
+}
+
+/// Visitor of the AST of a program, see the module documentation
+pub trait Visitor<'a>: Sized {
+    /// Visits the statements of a block, or the program
+    fn statements(&mut self, statements: &'a [Statement]) {
+        walk_statements(self, statements);
+    }
+
+    fn statement(&mut self, statement: &'a Statement) {
+        walk_statement(self, statement);
+    }
+
+    fn exp(&mut self, exp: &'a Exp) {
+        walk_exp(self, exp);
+    }
+
+    /// Visits a function definition, its arguments and body are in a new scope
+    fn function(&mut self, function: Function<'a>) {
+        walk_function(self, function);
+    }
+
+    /// Called for each name bound in the current scope, in source order
+    fn bind(&mut self, _binding: Binding<'a>) {}
+}
+
+/// Binds the names of the top level of a program, then visits its statements
+pub fn walk_program<'a, V: Visitor<'a>>(visitor: &mut V, program: &'a Program) {
+    walk_bindings(visitor, &program.program);
+    visitor.statements(&program.program);
+}
+
+/// Binds the arguments of a function and the names bound in its body, then visits its body
+pub fn walk_function<'a, V: Visitor<'a>>(visitor: &mut V, function: Function<'a>) {
+    let args = function.args.iter().zip(function.arg_spans).zip(&function.signature.args);
+    for ((name, span), annotation) in args {
+        visitor.bind(Binding::Argument {
+            name,
+            span: *span,
+            annotation: *annotation,
+        });
+    }
+    walk_bindings(visitor, &function.block.block);
+    visitor.statements(&function.block.block);
+}
+
+/// Binds the names assigned by `statements` in the current scope,
+/// without entering the functions they define
+pub fn walk_bindings<'a, V: Visitor<'a>>(visitor: &mut V, statements: &'a [Statement]) {
+    for statement in statements {
+        match &statement.statement {
+            // assigning to a field or index needs the name to be bound already
+            StatementKind::Assign {
+                lhs,
+                rhs,
+                annotation,
+                ..
+            } if lhs.assignable.is_empty() => visitor.bind(Binding::Assign {
+                name: &lhs.name,
+                statement,
+                rhs,
+                annotation: *annotation,
+            }),
+            StatementKind::Nest(NestKind::CondNest(CondNestKind::If { then, .. })) => {
+                walk_bindings(visitor, &then.block);
+            }
+            StatementKind::Nest(NestKind::CondNest(CondNestKind::IfElse { then, or_else, .. })) => {
+                walk_bindings(visitor, &then.block);
+                walk_bindings(visitor, &or_else.block);
+            }
+            StatementKind::Nest(NestKind::LoopNest(LoopNestKind::While { block, .. })) => {
+                walk_bindings(visitor, &block.block);
+            }
+            StatementKind::Nest(NestKind::LoopNest(LoopNestKind::ForIn { name, block, .. })) => {
+                visitor.bind(Binding::Loop { name, statement });
+                walk_bindings(visitor, &block.block);
+            }
+            StatementKind::Nest(NestKind::LoopNest(LoopNestKind::For { init, adv, block, .. })) => {
+                walk_bindings(visitor, std::slice::from_ref(init.as_ref()));
+                walk_bindings(visitor, std::slice::from_ref(adv.as_ref()));
+                walk_bindings(visitor, &block.block);
+            }
+            StatementKind::Assign { .. } | StatementKind::Return(_) | StatementKind::Exp(_) => {}
+        }
+    }
+}
+
+pub fn walk_statements<'a, V: Visitor<'a>>(visitor: &mut V, statements: &'a [Statement]) {
+    for statement in statements {
+        visitor.statement(statement);
+    }
+}
+
+pub fn walk_statement<'a, V: Visitor<'a>>(visitor: &mut V, statement: &'a Statement) {
+    match &statement.statement {
+        StatementKind::Return(exp) | StatementKind::Exp(exp) => visitor.exp(exp),
+        StatementKind::Assign { lhs, rhs, .. } => {
+            for sub in &lhs.assignable {
+                if let AssignableKind::ArrayIndex { index } = sub {
+                    visitor.exp(index);
+                }
+            }
+            // functions assigned to a name are named by it
+            match Function::new(rhs) {
+                Some(function) if lhs.assignable.is_empty() => visitor.function(Function {
+                    name: Some(&lhs.name),
+                    ..function
+                }),
+                _ => visitor.exp(rhs),
+            }
+        }
+        StatementKind::Nest(NestKind::CondNest(CondNestKind::If { cond, then })) => {
+            visitor.exp(cond);
+            visitor.statements(&then.block);
+        }
+        StatementKind::Nest(NestKind::CondNest(CondNestKind::IfElse {
+            cond,
+            then,
+            or_else,
+        })) => {
+            visitor.exp(cond);
+            visitor.statements(&then.block);
+            visitor.statements(&or_else.block);
+        }
+        StatementKind::Nest(NestKind::LoopNest(LoopNestKind::While { cond, block })) => {
+            visitor.exp(cond);
+            visitor.statements(&block.block);
+        }
+        StatementKind::Nest(NestKind::LoopNest(LoopNestKind::ForIn { array, block, .. })) => {
+            visitor.exp(array);
+            visitor.statements(&block.block);
+        }
+        StatementKind::Nest(NestKind::LoopNest(LoopNestKind::For {
+            init,
+            cond,
+            adv,
+            block,
+        })) => {
+            visitor.statement(init);
+            visitor.exp(cond);
+            visitor.statement(adv);
+            visitor.statements(&block.block);
+        }
+    }
+}
+
+pub fn walk_exp<'a, V: Visitor<'a>>(visitor: &mut V, exp: &'a Exp) {
+    match &exp.exp {
+        ExpKind::Value(value) => match value {
+            ValueKind::Paren(inner) => visitor.exp(inner),
+            ValueKind::Structure(fields) => {
+                for field in fields {
+                    match Function::new(&field.exp) {
+                        Some(function) => visitor.function(Function {
+                            structure: Some(fields),
+                            ..function
+                        }),
+                        None => visitor.exp(&field.exp),
+                    }
+                }
+            }
+            ValueKind::FunctionDef { .. } => {
+                if let Some(function) = Function::new(exp) {
+                    visitor.function(function);
+                }
+            }
+            ValueKind::ArrayInit(ArrayInitKind::Sized(size)) => visitor.exp(size),
+            ValueKind::ArrayInit(ArrayInitKind::Range(from, to)) => {
+                visitor.exp(from);
+                visitor.exp(to);
+            }
+            ValueKind::Num(_) | ValueKind::String(_) | ValueKind::Name(_) | ValueKind::Null => {}
+        },
+        ExpKind::Unary(_, operand) => visitor.exp(operand),
+        ExpKind::Infix(_, left, right) => {
+            visitor.exp(left);
+            visitor.exp(right);
+        }
+        ExpKind::Postfix(operand, op) => {
+            visitor.exp(operand);
+            match op {
+                PostOp::Subscript(index) => visitor.exp(index),
+                PostOp::Call(args) => {
+                    for arg in args {
+                        visitor.exp(arg);
+                    }
+                }
+                PostOp::Dot(_) => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,15 @@
+//! Author: Rafael Bayer (2021)
+//! Entrypoint of the Puffin Debug Adapter Binary,
+//! which speaks the Debug Adapter Protocol over stdin and stdout
+
+use std::{
+    io::{self, BufReader},
+    process,
+};
+
+fn main() {
+    if let Err(err) = puffin::debugger::dap::serve(BufReader::new(io::stdin()), io::stdout()) {
+        eprintln!("puffin-dap: {}", err);
+        process::exit(1);
+    }
+}
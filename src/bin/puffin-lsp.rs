@@ -0,0 +1,13 @@
+//! Author: Rafael Bayer (2021)
+//! Entrypoint of the Puffin Language Server Binary,
+//! which speaks the Language Server Protocol over stdin and stdout
+
+use std::{io, process};
+
+fn main() {
+    let stdin = io::stdin();
+    if let Err(err) = puffin::lsp::serve(stdin.lock(), io::stdout()) {
+        eprintln!("puffin-lsp: {}", err);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,424 @@
+//! Author: Rafael Bayer (2021)
+//! The checker module finds mistakes in a program without running it.
+//!
+//! Names are resolved the way the interpreter's `Environment` does:
+//! each function call has its own scope, blocks don't, and a function sees every
+//! name bound in the scopes it was defined in, even those bound after its definition.
+//! Errors are mistakes that fail when reached, like using a name that is never bound,
+//! calling a known function with the wrong number of arguments, or rebinding a builtin.
+//! Warnings are likely mistakes, like unreachable code or unused variables.
+//!
+//! Where the type of a value can be inferred, it is checked against the annotations of
+//! the names and functions it is assigned to, passed to, or returned from.
+
+use std::{collections::HashMap, fmt::Display};
+
+use crate::ast::{
+    node::*,
+    visit::{self, Function, Visitor},
+};
+use crate::interpreter::{
+    value::{builtin, Value},
+    InterpreterError,
+};
+use crate::parser::{ParseError, Span};
+
+/// How serious a diagnostic is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "Warning"),
+            Severity::Error => write!(f, "Error"),
+        }
+    }
+}
+
+/// Problem found by the checker, pointing at a span of the source
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+}
+
+impl Diagnostic {
+    /// Renders this diagnostic alongside its line of `source`, like a parse error
+    pub fn render(&self, source: &str) -> String {
+        let err = ParseError::new(self.message.clone(), self.span, source);
+        format!("{}: {}", self.severity, err)
+    }
+}
+
+/// Checks a program, returning the diagnostics found in source order
+pub fn check_program(program: &Program) -> Vec<Diagnostic> {
+    let mut globals = Scope::default();
+    let builtins = builtin::get_builtins()
+        .into_iter()
+        .map(|(name, value)| {
+            let arity = match &value {
+                Value::Builtin(builtin) => builtin.arity(),
+                _ => None,
+            };
+            (name, value.type_of(), arity)
+        })
+        .chain(Some((builtin::ARGS.to_string(), Type::Array, None)));
+    for (name, ty, arity) in builtins {
+        globals.bindings.insert(
+            name,
+            Binding {
+                kind: BindingKind::Builtin,
+                span: Span::default(),
+                assignments: 1,
+                arity,
+                function: None,
+                ty: Some(ty),
+                used: true,
+            },
+        );
+    }
+
+    let mut checker = Checker {
+        scopes: vec![globals],
+        diagnostics: Vec::new(),
+    };
+    visit::walk_program(&mut checker, program);
+    checker.pop_scope();
+
+    checker.diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+    checker.diagnostics
+}
+
+/// Names bound by a function call, or the program
+#[derive(Debug, Default)]
+struct Scope {
+    bindings: HashMap<String, Binding>,
+    /// name of the function, if it is assigned to one
+    name: Option<String>,
+    /// annotated type of the function's return value
+    ret: Option<Type>,
+}
+
+#[derive(Debug)]
+struct Binding {
+    kind: BindingKind,
+    /// where the name is first bound
+    span: Span,
+    /// number of places the name is bound in its scope
+    assignments: usize,
+    /// minimum and maximum number of arguments, if the name is bound to a known function
+    arity: Option<(usize, usize)>,
+    /// arguments and signature, if the name is bound to a known function definition
+    function: Option<(Vec<String>, Signature)>,
+    /// type of every value bound to the name, if known
+    ty: Option<Type>,
+    used: bool,
+}
+
+#[derive(Debug, PartialEq)]
+enum BindingKind {
+    Builtin,
+    Argument,
+    /// name bound by a `for (name in array)` loop
+    Loop,
+    Variable,
+}
+
+/// Function being called
+struct Callee {
+    name: Option<String>,
+    arity: Option<(usize, usize)>,
+    function: Option<(Vec<String>, Signature)>,
+}
+
+struct Checker {
+    /// scopes of the functions being checked, innermost last
+    scopes: Vec<Scope>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Visitor<'a> for Checker {
+    /// checks a list of statements, warning about statements after one that always returns
+    fn statements(&mut self, statements: &'a [Statement]) {
+        let mut returned = false;
+        for statement in statements {
+            if returned {
+                self.warning("unreachable code".to_string(), statement.span);
+                returned = false;
+            }
+            self.statement(statement);
+            returned |= always_returns(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &'a Statement) {
+        match &statement.statement {
+            StatementKind::Return(exp) => {
+                self.exp(exp);
+                let scope = self.scopes.last().unwrap();
+                let target = match &scope.name {
+                    Some(name) => format!("return value of `{}`", name),
+                    None => "return value".to_string(),
+                };
+                self.check_type(exp, scope.ret, target);
+            }
+            StatementKind::Assign {
+                lhs,
+                rhs,
+                annotation,
+                ..
+            } => {
+                if !lhs.assignable.is_empty() {
+                    self.use_name(&lhs.name, statement.span);
+                }
+                visit::walk_statement(self, statement);
+                self.check_type(rhs, *annotation, format!("`{}`", lhs.name));
+            }
+            _ => visit::walk_statement(self, statement),
+        }
+    }
+
+    fn exp(&mut self, exp: &'a Exp) {
+        match &exp.exp {
+            ExpKind::Value(ValueKind::Name(name)) => self.use_name(name, exp.span),
+            ExpKind::Postfix(operand, PostOp::Call(args)) => {
+                self.exp(operand);
+                self.call(operand, args, exp.span);
+                for arg in args {
+                    self.exp(arg);
+                }
+            }
+            _ => visit::walk_exp(self, exp),
+        }
+    }
+
+    /// checks a function definition in a new scope
+    fn function(&mut self, function: Function<'a>) {
+        let signature = function.signature;
+        self.scopes.push(Scope {
+            bindings: HashMap::new(),
+            name: function.name.map(str::to_string),
+            ret: signature.ret,
+        });
+        visit::walk_function(self, function);
+        self.pop_scope();
+
+        // without a return on every path, the function may return null
+        let block = function.block;
+        match signature.ret {
+            Some(ret) if ret != Type::Null && !block.block.iter().any(always_returns) => {
+                let message = format!("function may end without returning a {}", ret);
+                self.warning(message, block.span);
+            }
+            _ => {}
+        }
+    }
+
+    fn bind(&mut self, binding: visit::Binding<'a>) {
+        match binding {
+            visit::Binding::Argument { name, span, annotation } => {
+                self.add_binding(name, BindingKind::Argument, span, None, annotation);
+            }
+            visit::Binding::Assign {
+                name,
+                statement,
+                rhs,
+                annotation,
+            } => {
+                let function = Function::new(rhs)
+                    .map(|function| (function.args.to_vec(), function.signature.clone()));
+                let ty = annotation.or_else(|| self.infer(rhs));
+                self.add_binding(name, BindingKind::Variable, statement.span, function, ty);
+            }
+            visit::Binding::Loop { name, statement } => {
+                self.add_binding(name, BindingKind::Loop, statement.span, None, None);
+            }
+        }
+    }
+}
+
+impl Checker {
+    fn add_binding(
+        &mut self,
+        name: &str,
+        kind: BindingKind,
+        span: Span,
+        function: Option<(Vec<String>, Signature)>,
+        ty: Option<Type>,
+    ) {
+        let scope = self.scopes.last_mut().unwrap();
+        match scope.bindings.get_mut(name) {
+            // only the global scope has builtins
+            Some(binding) if binding.kind == BindingKind::Builtin => {
+                let message = InterpreterError::BuiltinRebinding(name.to_string()).to_string();
+                self.error(message, span);
+            }
+            Some(binding) => {
+                binding.assignments += 1;
+                if binding.ty != ty {
+                    binding.ty = None;
+                }
+            }
+            None => {
+                let binding = Binding {
+                    kind,
+                    span,
+                    assignments: 1,
+                    arity: function.as_ref().map(|(args, _)| (args.len(), args.len())),
+                    function,
+                    ty,
+                    used: false,
+                };
+                scope.bindings.insert(name.to_string(), binding);
+            }
+        }
+    }
+
+    /// checks the number and types of arguments of a call to `callee`, if it is a known function
+    fn call(&mut self, callee: &Exp, actuals: &[Exp], span: Span) {
+        let callee = match self.known_function(callee) {
+            Some(callee) => callee,
+            None => return,
+        };
+
+        let got = actuals.len();
+        if let Some((min, max)) = callee.arity {
+            if got < min || got > max {
+                let err = InterpreterError::ArgMismatch {
+                    expected: (min, max),
+                    got,
+                    callee: callee.name,
+                };
+                self.error(err.to_string(), span);
+                return;
+            }
+        }
+
+        if let Some((args, signature)) = callee.function {
+            for ((arg, ty), actual) in args.iter().zip(&signature.args).zip(actuals) {
+                self.check_type(actual, *ty, format!("argument `{}`", arg));
+            }
+        }
+    }
+
+    /// returns what is known about the function `callee` evaluates to, if anything
+    fn known_function(&self, callee: &Exp) -> Option<Callee> {
+        match &callee.exp {
+            ExpKind::Value(ValueKind::Name(name)) => match self.lookup(name) {
+                Some(binding) if binding.assignments == 1 => Some(Callee {
+                    name: Some(name.clone()),
+                    arity: binding.arity,
+                    function: binding.function.clone(),
+                }),
+                _ => None,
+            },
+            ExpKind::Value(ValueKind::FunctionDef { args, signature, .. }) => Some(Callee {
+                name: None,
+                arity: Some((args.len(), args.len())),
+                function: Some((args.clone(), signature.clone())),
+            }),
+            ExpKind::Value(ValueKind::Paren(inner)) => self.known_function(inner),
+            _ => None,
+        }
+    }
+
+    /// reports an error if `exp` has a known type other than its annotation.
+    /// `target` describes what was annotated
+    fn check_type(&mut self, exp: &Exp, annotation: Option<Type>, target: String) {
+        if let (Some(expected), Some(got)) = (annotation, self.infer(exp)) {
+            if expected != got {
+                let err = InterpreterError::TypeMismatch { target, expected, got };
+                self.error(err.to_string(), exp.span);
+            }
+        }
+    }
+
+    /// infers the type of an expression, if it can be known without running it
+    fn infer(&self, exp: &Exp) -> Option<Type> {
+        match &exp.exp {
+            ExpKind::Value(value) => match value {
+                ValueKind::Paren(inner) => self.infer(inner),
+                ValueKind::Structure(_) => Some(Type::Struct),
+                ValueKind::FunctionDef { .. } => Some(Type::Closure),
+                ValueKind::Num(_) => Some(Type::Num),
+                ValueKind::String(_) => Some(Type::String),
+                ValueKind::ArrayInit(_) => Some(Type::Array),
+                ValueKind::Name(name) => self.lookup(name).and_then(|binding| binding.ty),
+                ValueKind::Null => Some(Type::Null),
+            },
+            ExpKind::Unary(..) => Some(Type::Num),
+            // `+` adds numbers or concatenates strings, depending on the left operand
+            ExpKind::Infix(InfixOp::Plus, left, _) => match self.infer(left) {
+                Some(Type::String) => Some(Type::String),
+                Some(Type::Num) => Some(Type::Num),
+                _ => None,
+            },
+            ExpKind::Infix(..) => Some(Type::Num),
+            ExpKind::Postfix(callee, PostOp::Call(_)) => self
+                .known_function(callee)
+                .and_then(|callee| callee.function)
+                .and_then(|(_, signature)| signature.ret),
+            ExpKind::Postfix(..) => None,
+        }
+    }
+
+    /// marks a name as used, reporting an error if no scope binds it
+    fn use_name(&mut self, name: &str, span: Span) {
+        match self.scopes.iter_mut().rev().find_map(|scope| scope.bindings.get_mut(name)) {
+            Some(binding) => binding.used = true,
+            None => {
+                let message = InterpreterError::UnboundName(name.to_string()).to_string();
+                self.error(message, span);
+            }
+        }
+    }
+
+    /// returns the binding of a name in the innermost scope that binds it
+    fn lookup(&self, name: &str) -> Option<&Binding> {
+        self.scopes.iter().rev().find_map(|scope| scope.bindings.get(name))
+    }
+
+    /// leaves the current scope, warning about variables that were never used.
+    /// Top-level variables aren't reported, since they are used by tests and the REPL
+    fn pop_scope(&mut self) {
+        let scope = self.scopes.pop().unwrap();
+        let top_level = self.scopes.is_empty();
+        for (name, binding) in scope.bindings {
+            if binding.kind == BindingKind::Variable && !binding.used && !top_level {
+                self.warning(format!("unused variable `{}`", name), binding.span);
+            }
+        }
+    }
+
+    fn error(&mut self, message: String, span: Span) {
+        self.diagnostics.push(Diagnostic {
+            severity: Severity::Error,
+            message,
+            span,
+        });
+    }
+
+    fn warning(&mut self, message: String, span: Span) {
+        self.diagnostics.push(Diagnostic {
+            severity: Severity::Warning,
+            message,
+            span,
+        });
+    }
+}
+
+/// returns true if a statement returns on every path
+fn always_returns(statement: &Statement) -> bool {
+    match &statement.statement {
+        StatementKind::Return(_) => true,
+        StatementKind::Nest(NestKind::CondNest(CondNestKind::IfElse { then, or_else, .. })) => {
+            then.block.iter().any(always_returns) && or_else.block.iter().any(always_returns)
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,251 @@
+//! Author: Rafael Bayer (2021)
+//! The cli module parses the command line of the `puffin` binary into a `Command`.
+//!
+//! `puffin program.puf` is shorthand for `puffin run program.puf`,
+//! and `puffin` without arguments starts the REPL.
+
+use std::{fs, io::{self, Read}};
+
+use crate::Config;
+
+/// Usage of the `puffin` binary
+pub const HELP: &str = "\
+Puffin Language Interpreter
+
+Usage:
+    puffin                                 start the REPL
+    puffin [run] <source> [flags] [args]   run a program
+    puffin repl                            start the REPL
+    puffin debug <source> [flags] [args]   run a program in the debugger
+    puffin check <source>                  check a program for mistakes without running it
+    puffin test [flags] [<path>...]        run the test_ functions of programs, in files or
+                                           directories of .puf files (by default, the current one)
+    puffin ast <source>                    print the AST of a program
+    puffin tokens <source>                 print the tokens of a program
+    puffin fmt [--check] <source>...       format programs, files are rewritten in place
+
+Sources:
+    <path>                                 read the program from a file
+    -                                      read the program from stdin
+    -e <code>                              run <code> as the program
+
+Run and debug flags:
+    -parse                                 show the tokens before execution
+    -ast                                   show the AST before execution
+    --seed <n>                             seed the random number generator
+    --check-types                          check type annotations while running
+    --max-steps <n>                        stop the program after <n> steps
+    --max-depth <n>                        stop the program at <n> nested calls (default 1000)
+    --max-memory <n>                       stop the program after allocating a total of <n> array
+                                           elements, structure fields and string bytes
+    --timeout <seconds>                    stop the program after <seconds>
+    --profile <path>                       print a profile of the program to stderr,
+                                           and write its folded stacks to <path>
+    --coverage <path>                      write an LCOV report of the lines and branches
+                                           executed to <path>
+    --trace                                print each statement, call, and assignment to stderr
+    --trace-fn <name>                      only trace calls of <name> and the calls they make,
+                                           can be given more than once
+
+    Other arguments after the source are passed to the program as ARGS,
+    use `--` to pass arguments starting with `-`.
+
+Test flags:
+    --filter <name>                        only run tests whose names contain <name>
+    --coverage <path>                      write an LCOV report of the lines and branches
+                                           the tests executed to <path>
+
+    Before each test, only the top-level assignments of its program run,
+    in a new global environment.
+
+Fmt flags:
+    --check                                don't write, fail if a program isn't formatted
+
+Options:
+    -h, --help                             print this message, also after a command
+    -V, --version                          print the version
+";
+
+/// Version of the `puffin` binary
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Command given to the `puffin` binary
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Run a program
+    Run(Config),
+    /// Run a program in the debugger
+    Debug(Config),
+    /// Start the REPL
+    Repl,
+    /// Parse and check a program without running it
+    Check(Source),
+    /// Print the AST of a program
+    Ast(Source),
+    /// Print the tokens of a program
+    Tokens(Source),
+    /// Run the tests of the programs in files and directories
+    Test {
+        paths: Vec<String>,
+        filter: Option<String>,
+        coverage: Option<String>,
+    },
+    /// Format programs, or check that they are formatted
+    Fmt { sources: Vec<Source>, check: bool },
+    /// Print usage
+    Help,
+    /// Print the version
+    Version,
+}
+
+impl Command {
+    /// Create a Command from `std::env::args()`.
+    /// Note: expects that first argument is the puffin interpreter executable.
+    /// Example args: `["./puffin", "check", "program.puf"]`
+    pub fn new(args: &[String]) -> Result<Command, String> {
+        let command = match args.get(1) {
+            Some(command) => command.as_str(),
+            None => return Ok(Command::Repl),
+        };
+
+        // help after a command isn't a source, but arguments after a program's source are its own
+        let rest = &args[2.min(args.len())..];
+        let help = |arg: &String| arg == "-h" || arg == "--help";
+        match command {
+            "run" | "debug" if rest.first().is_some_and(help) => return Ok(Command::Help),
+            "repl" | "check" | "ast" | "tokens" | "test" | "fmt" if rest.iter().any(help) => {
+                return Ok(Command::Help)
+            }
+            _ => {}
+        }
+
+        match command {
+            "run" => Ok(Command::Run(Config::new(&args[1..])?)),
+            "debug" => Ok(Command::Debug(Config::new(&args[1..])?)),
+            "repl" => expect_none(&args[2..]).map(|_| Command::Repl),
+            "check" => Ok(Command::Check(Source::only(&args[2..])?)),
+            "ast" => Ok(Command::Ast(Source::only(&args[2..])?)),
+            "tokens" => Ok(Command::Tokens(Source::only(&args[2..])?)),
+            "test" => test(&args[2..]),
+            "fmt" => fmt(&args[2..]),
+            "-h" | "--help" | "help" => Ok(Command::Help),
+            "-V" | "--version" => Ok(Command::Version),
+            "-" | "-e" => Ok(Command::Run(Config::new(args)?)),
+            option if option.starts_with('-') => Err(format!("Unknown option: {}", option)),
+            // shorthand for run
+            _ => Ok(Command::Run(Config::new(args)?)),
+        }
+    }
+}
+
+/// Source of a Puffin program
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    /// Program in a file
+    File(String),
+    /// Program read from stdin
+    Stdin,
+    /// Program given on the command line
+    Inline(String),
+}
+
+impl Source {
+    /// Parses a source from the start of `args`, returning the source
+    /// and the number of arguments used
+    pub fn parse(args: &[String]) -> Result<(Source, usize), String> {
+        match args.first().map(String::as_str) {
+            None => Err("Required Arguments: filename".to_string()),
+            Some("-") => Ok((Source::Stdin, 1)),
+            Some("-e") => match args.get(1) {
+                Some(code) => Ok((Source::Inline(code.clone()), 2)),
+                None => Err("Missing code for -e".to_string()),
+            },
+            Some(path) => Ok((Source::File(path.to_string()), 1)),
+        }
+    }
+
+    /// Parses `args` as exactly one source
+    fn only(args: &[String]) -> Result<Source, String> {
+        let (source, used) = Source::parse(args)?;
+        expect_none(&args[used..])?;
+        Ok(source)
+    }
+
+    /// Returns the name of this source for messages
+    pub fn name(&self) -> &str {
+        match self {
+            Source::File(path) => path,
+            Source::Stdin => "<stdin>",
+            Source::Inline(_) => "<inline>",
+        }
+    }
+
+    /// Reads the program from this source
+    pub fn read(&self) -> io::Result<String> {
+        match self {
+            Source::File(path) => fs::read_to_string(path),
+            Source::Stdin => {
+                let mut contents = String::new();
+                io::stdin().read_to_string(&mut contents)?;
+                Ok(contents)
+            }
+            Source::Inline(code) => Ok(code.clone()),
+        }
+    }
+}
+
+/// Parses the arguments of `puffin test`
+fn test(args: &[String]) -> Result<Command, String> {
+    let mut paths = Vec::new();
+    let mut filter = None;
+    let mut coverage = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--filter" => filter = Some(args.next().ok_or("Missing name for --filter")?.clone()),
+            "--coverage" => coverage = Some(args.next().ok_or("Missing path for --coverage")?.clone()),
+            option if option.starts_with('-') => return Err(format!("Unknown option: {}", option)),
+            path => paths.push(path.to_string()),
+        }
+    }
+
+    if paths.is_empty() {
+        paths.push(".".to_string());
+    }
+    Ok(Command::Test {
+        paths,
+        filter,
+        coverage,
+    })
+}
+
+/// Parses the arguments of `puffin fmt`
+fn fmt(mut args: &[String]) -> Result<Command, String> {
+    let mut sources = Vec::new();
+    let mut check = false;
+    while !args.is_empty() {
+        if args[0] == "--check" {
+            check = true;
+            args = &args[1..];
+            continue;
+        }
+        if args[0].starts_with('-') && args[0] != "-" && args[0] != "-e" {
+            return Err(format!("Unknown option: {}", args[0]));
+        }
+        let (source, used) = Source::parse(args)?;
+        sources.push(source);
+        args = &args[used..];
+    }
+
+    if sources.is_empty() {
+        return Err("Required Arguments: filename".to_string());
+    }
+    Ok(Command::Fmt { sources, check })
+}
+
+fn expect_none(args: &[String]) -> Result<(), String> {
+    match args.first() {
+        Some(arg) => Err(format!("Unexpected argument: {}", arg)),
+        None => Ok(()),
+    }
+}
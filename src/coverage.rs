@@ -0,0 +1,145 @@
+//! Author: Rafael Bayer (2021)
+//! The coverage module defines `Coverage`, which records the lines and branches of a program
+//! that were executed.
+//!
+//! Coverage is a set of interpreter `Hooks`. Every line with a statement, and every `if`,
+//! is found in the AST before the program runs, so the ones that never executed are reported too.
+//! Each `if` has two branches: its block is taken, or it isn't (running the `else` block, if any).
+//! The report is written in the LCOV format, read by coverage tools.
+
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    fmt::Write,
+    rc::Rc,
+};
+
+use crate::{
+    ast::{
+        node::*,
+        visit::{self, Visitor},
+    },
+    interpreter::{hooks::Hooks, value::Environment, InterpreterError},
+};
+
+/// Executions of the branches of an `if`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Branch {
+    /// Line of the condition
+    pub line: usize,
+    /// Number of times the block was taken
+    pub taken: u64,
+    /// Number of times the block wasn't taken
+    pub not_taken: u64,
+}
+
+/// Coverage, records the lines and branches of a program that were executed
+pub struct Coverage {
+    /// executions of the statements of each line
+    lines: RefCell<BTreeMap<usize, u64>>,
+    /// branches of each `if`, by the start of its condition
+    branches: RefCell<BTreeMap<usize, Branch>>,
+}
+
+impl Coverage {
+    /// Returns the Coverage of `program`, which hasn't run
+    pub fn new(program: &Program) -> Coverage {
+        let mut finder = Finder::default();
+        visit::walk_program(&mut finder, program);
+        Coverage {
+            lines: RefCell::new(finder.lines),
+            branches: RefCell::new(finder.branches),
+        }
+    }
+
+    /// Returns the number of statements executed on each line with statements, by line
+    pub fn lines(&self) -> Vec<(usize, u64)> {
+        self.lines.borrow().iter().map(|(line, hits)| (*line, *hits)).collect()
+    }
+
+    /// Returns the branches of each `if`, in the order they appear in the source
+    pub fn branches(&self) -> Vec<Branch> {
+        self.branches.borrow().values().copied().collect()
+    }
+
+    /// Returns the coverage as an LCOV record for the program in the file `source`
+    pub fn lcov(&self, source: &str) -> String {
+        let mut lcov = String::new();
+        writeln!(lcov, "TN:\nSF:{}", source).unwrap();
+
+        let branches = self.branches();
+        for (block, branch) in branches.iter().enumerate() {
+            // branches of an `if` whose condition was never evaluated are written as `-`
+            let evaluated = branch.taken + branch.not_taken > 0;
+            for (number, count) in [branch.taken, branch.not_taken].iter().enumerate() {
+                let count = if evaluated { count.to_string() } else { "-".to_string() };
+                writeln!(lcov, "BRDA:{},{},{},{}", branch.line, block, number, count).unwrap();
+            }
+        }
+        let hit = branches
+            .iter()
+            .map(|branch| (branch.taken > 0) as usize + (branch.not_taken > 0) as usize)
+            .sum::<usize>();
+        writeln!(lcov, "BRF:{}\nBRH:{}", branches.len() * 2, hit).unwrap();
+
+        let lines = self.lines();
+        for (line, hits) in &lines {
+            writeln!(lcov, "DA:{},{}", line, hits).unwrap();
+        }
+        let hit = lines.iter().filter(|(_, hits)| *hits > 0).count();
+        writeln!(lcov, "LF:{}\nLH:{}", lines.len(), hit).unwrap();
+
+        lcov.push_str("end_of_record\n");
+        lcov
+    }
+}
+
+impl Hooks for Coverage {
+    fn statement(
+        &self,
+        statement: &Statement,
+        _env: &Rc<RefCell<Environment>>,
+    ) -> Result<(), InterpreterError> {
+        *self.lines.borrow_mut().entry(statement.span.line).or_default() += 1;
+        Ok(())
+    }
+
+    fn branch(&self, cond: &Exp, taken: bool) {
+        let mut branches = self.branches.borrow_mut();
+        let branch = branches.entry(cond.span.start).or_insert(Branch {
+            line: cond.span.line,
+            ..Branch::default()
+        });
+        if taken {
+            branch.taken += 1;
+        } else {
+            branch.not_taken += 1;
+        }
+    }
+}
+
+/// finds the lines with statements, and the `if`s, of a program
+#[derive(Default)]
+struct Finder {
+    lines: BTreeMap<usize, u64>,
+    branches: BTreeMap<usize, Branch>,
+}
+
+impl Visitor<'_> for Finder {
+    fn statement(&mut self, statement: &Statement) {
+        self.lines.insert(statement.span.line, 0);
+        if let StatementKind::Nest(NestKind::CondNest(
+            CondNestKind::If { cond, .. } | CondNestKind::IfElse { cond, .. },
+        )) = &statement.statement
+        {
+            self.branches.insert(
+                cond.span.start,
+                Branch {
+                    line: cond.span.line,
+                    ..Branch::default()
+                },
+            );
+        }
+        visit::walk_statement(self, statement);
+    }
+}
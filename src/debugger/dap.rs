@@ -0,0 +1,546 @@
+//! Author: Rafael Bayer (2021)
+//! The dap module serves the debugger over the Debug Adapter Protocol,
+//! for editors to debug Puffin programs.
+//!
+//! The adapter launches a single program, and runs it on the thread serving the protocol
+//! (the values of a program can't be shared between threads). A reader thread receives
+//! requests, so the client can pause, set breakpoints, or disconnect while the program runs.
+//! Other requests wait until the program stops. Without a client, the program runs
+//! until it ends, or is interrupted where it would stop.
+//! The program's output is sent to the client as `output` events.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, VecDeque},
+    fs,
+    io::{self, BufRead, Cursor, Write},
+    path::Path,
+    rc::Rc,
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+};
+
+use serde_json::{json, Value as Json};
+
+use super::{Mode, State, Stop};
+use crate::{
+    ast::node::{Program, Statement},
+    interpreter::{
+        self,
+        hooks::{Call, Hooks},
+        runtime::Runtime,
+        value::Environment,
+        InterpreterError, Value,
+    },
+    lsp::{read_message, write_message},
+};
+
+/// The only thread of a program
+const THREAD_ID: i64 = 1;
+
+/// Serves the debug adapter protocol, reading requests from `input` and writing to `output`,
+/// until the client disconnects or closes the input
+pub fn serve<R, W>(input: R, output: W) -> io::Result<()>
+where
+    R: BufRead + Send + 'static,
+    W: Write + 'static,
+{
+    let (sender, requests) = mpsc::channel();
+    thread::spawn(move || {
+        let mut input = input;
+        while let Ok(Some(request)) = read_message(&mut input) {
+            if sender.send(request).is_err() {
+                break;
+            }
+        }
+    });
+
+    let adapter = Rc::new(Adapter {
+        requests,
+        pending: RefCell::new(VecDeque::new()),
+        transport: Rc::new(RefCell::new(Transport {
+            output: Box::new(output),
+            seq: 0,
+        })),
+        state: State::new(false),
+        launch: RefCell::new(None),
+        path: RefCell::new(String::new()),
+        configured: Cell::new(false),
+        stopped: Cell::new(false),
+        paused: Cell::new(false),
+        entered: Cell::new(false),
+        handles: RefCell::new(Vec::new()),
+    });
+
+    // configure, until the client launches a program and finishes setting breakpoints
+    let launch = loop {
+        match adapter.next_request()? {
+            Some(request) => {
+                if adapter.handle(request)? == Flow::Disconnect {
+                    return Ok(());
+                }
+            }
+            None => return Ok(()),
+        }
+        if adapter.configured.get() {
+            if let Some(launch) = adapter.launch.borrow_mut().take() {
+                break launch;
+            }
+        }
+    };
+
+    // run, the hooks handle requests while the program executes
+    let runtime = Runtime::new()
+        .with_args(launch.args.clone())
+        .with_stdin(Cursor::new(Vec::new()))
+        .with_stdout(adapter.output("stdout"))
+        .with_stderr(adapter.output("stderr"))
+        .with_hooks(adapter.clone());
+    let result = interpreter::eval_with(&launch.program, runtime);
+    let exit_code = match result {
+        Ok(value) => {
+            if !matches!(value, Value::Null) {
+                adapter.event("output", json!({"category": "stdout", "output": format!("{}\n", value)}))?;
+            }
+            0
+        }
+        Err(InterpreterError::Exit(code)) => code,
+        Err(InterpreterError::Interrupted) => return adapter.event("terminated", json!({})),
+        Err(err) => {
+            let output = format!("Runtime Error: {}\n", err);
+            adapter.event("output", json!({"category": "stderr", "output": output}))?;
+            1
+        }
+    };
+    adapter.event("exited", json!({ "exitCode": exit_code }))?;
+    adapter.event("terminated", json!({}))?;
+
+    // answer the client until it disconnects
+    adapter.stopped.set(false);
+    while let Some(request) = adapter.next_request()? {
+        if adapter.handle(request)? == Flow::Disconnect {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Program launched by the client
+struct Launch {
+    program: Program,
+    args: Vec<String>,
+}
+
+/// Output to the client, numbering every message
+struct Transport {
+    output: Box<dyn Write>,
+    seq: i64,
+}
+
+impl Transport {
+    fn send(&mut self, mut message: Json) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        write_message(&mut self.output, &message)
+    }
+}
+
+/// Program output, sent to the client as `output` events
+struct Output {
+    transport: Rc<RefCell<Transport>>,
+    category: &'static str,
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let output = String::from_utf8_lossy(buf);
+        self.transport.borrow_mut().send(json!({
+            "type": "event",
+            "event": "output",
+            "body": {"category": self.category, "output": output},
+        }))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// What to do after handling a request
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Flow {
+    /// keep handling requests
+    Wait,
+    /// resume the stopped program
+    Resume,
+    /// stop the program and the adapter
+    Disconnect,
+}
+
+/// Something the client can expand into variables, while the program is stopped
+enum Handle {
+    Scope(Rc<RefCell<Environment>>),
+    Array(Rc<RefCell<Vec<Value>>>),
+    Structure(Rc<RefCell<HashMap<String, Value>>>),
+}
+
+struct Adapter {
+    requests: Receiver<Json>,
+    /// requests received while running, waiting for the program to stop
+    pending: RefCell<VecDeque<Json>>,
+    transport: Rc<RefCell<Transport>>,
+    state: State,
+    launch: RefCell<Option<Launch>>,
+    /// path of the launched program
+    path: RefCell<String>,
+    configured: Cell<bool>,
+    stopped: Cell<bool>,
+    /// the client asked to pause the running program
+    paused: Cell<bool>,
+    /// the program has reached its first statement
+    entered: Cell<bool>,
+    /// variable references given to the client, valid while the program is stopped
+    handles: RefCell<Vec<Handle>>,
+}
+
+impl Adapter {
+    /// returns the next request to handle, waiting for one.
+    /// returns None if the client closed the input
+    fn next_request(&self) -> io::Result<Option<Json>> {
+        if let Some(request) = self.pending.borrow_mut().pop_front() {
+            return Ok(Some(request));
+        }
+        Ok(self.requests.recv().ok())
+    }
+
+    /// handles the requests received while the program runs,
+    /// leaving the ones that need a stopped program for later
+    fn poll(&self) -> Result<(), InterpreterError> {
+        loop {
+            let request = match self.requests.try_recv() {
+                Ok(request) => request,
+                // without a client, the program runs until it ends or would stop
+                Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => return Ok(()),
+            };
+            let command = request["command"].as_str().unwrap_or_default();
+            // requests are answered in order, unless they control the program
+            let control = matches!(command, "pause" | "disconnect" | "terminate");
+            let anytime = matches!(command, "setBreakpoints" | "threads");
+            if control || (anytime && self.pending.borrow().is_empty()) {
+                if self.handle(request)? == Flow::Disconnect {
+                    return Err(InterpreterError::Interrupted);
+                }
+            } else {
+                self.pending.borrow_mut().push_back(request);
+            }
+        }
+    }
+
+    /// stops the program, handling requests until the client resumes it
+    fn stop(&self, reason: &str) -> Result<(), InterpreterError> {
+        self.stopped.set(true);
+        self.event(
+            "stopped",
+            json!({"reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true}),
+        )?;
+
+        let flow = loop {
+            let request = match self.next_request()? {
+                Some(request) => request,
+                None => break Flow::Disconnect,
+            };
+            match self.handle(request)? {
+                Flow::Wait => continue,
+                flow => break flow,
+            }
+        };
+
+        self.stopped.set(false);
+        self.handles.borrow_mut().clear();
+        match flow {
+            Flow::Disconnect => Err(InterpreterError::Interrupted),
+            _ => Ok(()),
+        }
+    }
+
+    fn handle(&self, request: Json) -> io::Result<Flow> {
+        let command = request["command"].as_str().unwrap_or_default();
+        let args = &request["arguments"];
+        let depth = self.state.depth();
+        let mut flow = Flow::Wait;
+
+        let body = match command {
+            "initialize" => {
+                self.respond(&request, Ok(json!({"supportsConfigurationDoneRequest": true})))?;
+                return self.event("initialized", json!({})).map(|_| Flow::Wait);
+            }
+            "launch" => self.launch(args),
+            "setBreakpoints" => {
+                let lines: Vec<usize> = args["breakpoints"]
+                    .as_array()
+                    .map(|breakpoints| {
+                        breakpoints
+                            .iter()
+                            .filter_map(|breakpoint| breakpoint["line"].as_u64())
+                            .map(|line| line as usize)
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                *self.state.breakpoints().borrow_mut() = lines.iter().copied().collect();
+                let breakpoints: Vec<Json> = lines
+                    .iter()
+                    .map(|line| json!({"verified": true, "line": line}))
+                    .collect();
+                Ok(json!({ "breakpoints": breakpoints }))
+            }
+            "setExceptionBreakpoints" => Ok(json!({})),
+            "configurationDone" => {
+                self.configured.set(true);
+                Ok(json!({}))
+            }
+            "threads" => Ok(json!({"threads": [{"id": THREAD_ID, "name": "main"}]})),
+            "stackTrace" => Ok(self.stack_trace()),
+            "scopes" => self.scopes(args),
+            "variables" => self.variables(args),
+            "evaluate" => self.evaluate(args),
+            "continue" | "next" | "stepIn" | "stepOut" if self.stopped.get() => {
+                self.state.resume(match command {
+                    "continue" => Mode::Continue,
+                    "next" => Mode::Next(depth),
+                    "stepIn" => Mode::Step,
+                    _ => Mode::Out(depth),
+                });
+                flow = Flow::Resume;
+                Ok(json!({"allThreadsContinued": true}))
+            }
+            "continue" | "next" | "stepIn" | "stepOut" => Err("the program isn't stopped".to_string()),
+            "pause" => {
+                if !self.stopped.get() {
+                    self.paused.set(true);
+                    self.state.resume(Mode::Step);
+                }
+                Ok(json!({}))
+            }
+            "disconnect" | "terminate" => {
+                flow = Flow::Disconnect;
+                Ok(json!({}))
+            }
+            _ => Err(format!("unsupported request `{}`", command)),
+        };
+        self.respond(&request, body)?;
+        Ok(flow)
+    }
+
+    fn launch(&self, args: &Json) -> Result<Json, String> {
+        let path = args["program"]
+            .as_str()
+            .ok_or("expected the path of the program to launch")?;
+        let source = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+        let program = crate::parse(&source).map_err(|err| err.to_string())?;
+        let program_args = args["args"]
+            .as_array()
+            .map(|args| args.iter().filter_map(Json::as_str).map(str::to_string).collect())
+            .unwrap_or_default();
+
+        if args["stopOnEntry"].as_bool().unwrap_or_default() {
+            self.state.resume(Mode::Step);
+        }
+        *self.path.borrow_mut() = path.to_string();
+        *self.launch.borrow_mut() = Some(Launch {
+            program,
+            args: program_args,
+        });
+        Ok(json!({}))
+    }
+
+    fn stack_trace(&self) -> Json {
+        if !self.stopped.get() {
+            return json!({"stackFrames": [], "totalFrames": 0});
+        }
+
+        let path = self.path.borrow().clone();
+        let name = Path::new(&path)
+            .file_name()
+            .map_or(path.clone(), |name| name.to_string_lossy().into_owned());
+        let frames = self.state.frames();
+        let stack_frames: Vec<Json> = frames
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(i, frame)| {
+                json!({
+                    "id": i + 1,
+                    "name": frame.name,
+                    "line": frame.line,
+                    "column": 1,
+                    "source": {"name": name, "path": path},
+                })
+            })
+            .collect();
+        json!({"stackFrames": stack_frames, "totalFrames": frames.len()})
+    }
+
+    /// returns a scope for the environment of a frame, and each of its parents
+    fn scopes(&self, args: &Json) -> Result<Json, String> {
+        let mut env = Some(self.frame_env(args)?);
+        let mut scopes = Vec::new();
+        while let Some(current) = env {
+            let parent = current.borrow().parent().cloned();
+            let name = match (&parent, scopes.is_empty()) {
+                (None, _) => "Globals",
+                (Some(_), true) => "Locals",
+                (Some(_), false) => "Captured",
+            };
+            let reference = self.handle_of(Handle::Scope(current));
+            scopes.push(json!({
+                "name": name,
+                "variablesReference": reference,
+                "expensive": false,
+            }));
+            env = parent;
+        }
+        Ok(json!({ "scopes": scopes }))
+    }
+
+    fn variables(&self, args: &Json) -> Result<Json, String> {
+        let reference = args["variablesReference"].as_u64().unwrap_or_default() as usize;
+        let named: Vec<(String, Value)> = {
+            let handles = self.handles.borrow();
+            match reference.checked_sub(1).and_then(|i| handles.get(i)) {
+                Some(Handle::Scope(env)) => env
+                    .borrow()
+                    .variables()
+                    .into_iter()
+                    .map(|(name, value)| (name.clone(), value.clone()))
+                    .collect(),
+                Some(Handle::Array(array)) => array
+                    .borrow()
+                    .iter()
+                    .enumerate()
+                    .map(|(i, value)| (i.to_string(), value.clone()))
+                    .collect(),
+                Some(Handle::Structure(structure)) => {
+                    let mut fields: Vec<(String, Value)> = structure
+                        .borrow()
+                        .iter()
+                        .map(|(name, value)| (name.clone(), value.clone()))
+                        .collect();
+                    fields.sort_by(|a, b| a.0.cmp(&b.0));
+                    fields
+                }
+                None => return Err(format!("unknown variables reference {}", reference)),
+            }
+        };
+
+        let variables: Vec<Json> = named
+            .into_iter()
+            .map(|(name, value)| self.variable(name, value))
+            .collect();
+        Ok(json!({ "variables": variables }))
+    }
+
+    /// evaluates a name in the environment of a frame
+    fn evaluate(&self, args: &Json) -> Result<Json, String> {
+        let name = args["expression"].as_str().unwrap_or_default().trim();
+        let env = self.frame_env(args)?;
+        let value = env.borrow().get(name).map_err(|err| err.to_string())?;
+        let variable = self.variable(name.to_string(), value);
+        Ok(json!({
+            "result": variable["value"],
+            "type": variable["type"],
+            "variablesReference": variable["variablesReference"],
+        }))
+    }
+
+    /// describes a value to the client, arrays and structures can be expanded
+    fn variable(&self, name: String, value: Value) -> Json {
+        let reference = match &value {
+            Value::Array(array) => self.handle_of(Handle::Array(array.clone())),
+            Value::Structure(structure) => self.handle_of(Handle::Structure(structure.clone())),
+            _ => 0,
+        };
+        json!({
+            "name": name,
+            "value": value.to_string().trim_end(),
+            "type": value.type_of().name(),
+            "variablesReference": reference,
+        })
+    }
+
+    /// returns the environment of the frame in `args`, or of the current frame
+    fn frame_env(&self, args: &Json) -> Result<Rc<RefCell<Environment>>, String> {
+        if !self.stopped.get() {
+            return Err("the program isn't stopped".to_string());
+        }
+        let frames = self.state.frames();
+        let index = match args["frameId"].as_u64() {
+            Some(id) => (id as usize).checked_sub(1),
+            None => frames.len().checked_sub(1),
+        };
+        index
+            .and_then(|i| frames.get(i))
+            .map(|frame| frame.env.clone())
+            .ok_or_else(|| "unknown frame".to_string())
+    }
+
+    /// returns the variables reference of a new handle
+    fn handle_of(&self, handle: Handle) -> usize {
+        let mut handles = self.handles.borrow_mut();
+        handles.push(handle);
+        handles.len()
+    }
+
+    fn output(&self, category: &'static str) -> Output {
+        Output {
+            transport: self.transport.clone(),
+            category,
+        }
+    }
+
+    fn respond(&self, request: &Json, body: Result<Json, String>) -> io::Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": body.is_ok(),
+        });
+        match body {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.transport.borrow_mut().send(response)
+    }
+
+    fn event(&self, event: &str, body: Json) -> io::Result<()> {
+        self.transport
+            .borrow_mut()
+            .send(json!({"type": "event", "event": event, "body": body}))
+    }
+}
+
+impl Hooks for Adapter {
+    fn statement(
+        &self,
+        statement: &Statement,
+        env: &Rc<RefCell<Environment>>,
+    ) -> Result<(), InterpreterError> {
+        self.poll()?;
+        let entry = !self.entered.replace(true);
+        match self.state.statement(statement, env) {
+            Some(Stop::Breakpoint) => self.stop("breakpoint"),
+            Some(Stop::Step) if self.paused.replace(false) => self.stop("pause"),
+            Some(Stop::Step) if entry => self.stop("entry"),
+            Some(Stop::Step) => self.stop("step"),
+            None => Ok(()),
+        }
+    }
+
+    fn enter_call(&self, call: &Call) {
+        self.state.enter_call(call);
+    }
+
+    fn exit_call(&self, _call: &Call, _result: &Result<Value, InterpreterError>) {
+        self.state.exit_call();
+    }
+}
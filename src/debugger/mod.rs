@@ -0,0 +1,363 @@
+//! Author: Rafael Bayer (2021)
+//! The debugger module defines the `Debugger`, which stops a program as it executes
+//! and reads commands from a prompt to inspect it.
+//!
+//! The debugger is a set of interpreter `Hooks`. It stops before the first statement,
+//! at line breakpoints, and after stepping. While stopped, it can print the names bound
+//! in the current `Environment` and each of its parents, and a backtrace of the closure calls.
+//!
+//! The `dap` module serves the same debugger to editors, over the Debug Adapter Protocol.
+
+use std::{
+    cell::{Cell, Ref, RefCell},
+    collections::BTreeSet,
+    io::{self, BufRead, Write},
+    rc::Rc,
+};
+
+pub mod dap;
+
+use crate::{
+    ast::node::Statement,
+    interpreter::{
+        hooks::{Call, Hooks},
+        runtime::StdinReader,
+        value::Environment,
+        InterpreterError, Value,
+    },
+};
+
+/// Commands of the debugger prompt
+pub const HELP: &str = "\
+Commands:
+    b, break <line>      set a breakpoint at a line
+    d, delete <line>     remove the breakpoint at a line
+    c, continue          run until the next breakpoint
+    s, step              run to the next statement, stepping into calls
+    n, next              run to the next statement, stepping over calls
+    o, out               run until the current call returns
+    l, locals            print the names bound in each scope
+    p, print <name>      print the value bound to a name
+    bt, backtrace        print the closure calls leading to the current statement
+    list                 print the source around the current line
+    h, help              print this message
+    q, quit              stop the program
+An empty command repeats the last one.
+";
+
+/// Debugger, stops a program at breakpoints and reads commands from a prompt
+pub struct Debugger {
+    lines: Vec<String>,
+    state: State,
+    last_command: RefCell<String>,
+    input: RefCell<Box<dyn BufRead>>,
+    output: RefCell<Box<dyn Write>>,
+}
+
+/// Execution state of a debugged program: the calls being executed,
+/// the breakpoints, and when to stop next
+pub(crate) struct State {
+    breakpoints: RefCell<BTreeSet<usize>>,
+    mode: Cell<Mode>,
+    frames: RefCell<Vec<Frame>>,
+    /// depth, line, and start of the last statement reached,
+    /// so a breakpoint stops once each time its line is reached
+    last: Cell<Option<(usize, usize, usize)>>,
+}
+
+/// When the debugger stops next
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Mode {
+    /// at a breakpoint
+    Continue,
+    /// at the next statement
+    Step,
+    /// at the next statement in a call at most this deep
+    Next(usize),
+    /// at the next statement in a call less deep than this
+    Out(usize),
+}
+
+/// Why the program stopped
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Stop {
+    Breakpoint,
+    Step,
+}
+
+/// Closure call being executed, or the program itself
+pub(crate) struct Frame {
+    pub(crate) name: String,
+    /// line of the statement executing in this frame
+    pub(crate) line: usize,
+    pub(crate) env: Rc<RefCell<Environment>>,
+}
+
+impl State {
+    /// Returns the State of a program that hasn't started, stopping at the first statement
+    /// if `stop_on_entry`
+    pub(crate) fn new(stop_on_entry: bool) -> State {
+        State {
+            breakpoints: RefCell::new(BTreeSet::new()),
+            mode: Cell::new(if stop_on_entry { Mode::Step } else { Mode::Continue }),
+            frames: RefCell::new(Vec::new()),
+            last: Cell::new(None),
+        }
+    }
+
+    /// Records that `statement` is about to execute in `env`,
+    /// returning why the program should stop before it, if it should
+    pub(crate) fn statement(&self, statement: &Statement, env: &Rc<RefCell<Environment>>) -> Option<Stop> {
+        let line = statement.span.line;
+        let depth = {
+            let mut frames = self.frames.borrow_mut();
+            if frames.is_empty() {
+                frames.push(Frame {
+                    name: "<program>".to_string(),
+                    line,
+                    env: env.clone(),
+                });
+            }
+            let frame = frames.last_mut().expect("frames aren't empty");
+            frame.line = line;
+            frame.env = env.clone();
+            frames.len()
+        };
+
+        // later statements on the line just stopped at are skipped, but a loop reaching
+        // the line again starts before them
+        let start = statement.span.start;
+        let repeated = match self.last.replace(Some((depth, line, start))) {
+            Some((last_depth, last_line, last_start)) => {
+                last_depth == depth && last_line == line && last_start < start
+            }
+            None => false,
+        };
+        let step = match self.mode.get() {
+            Mode::Continue => false,
+            Mode::Step => true,
+            Mode::Next(max) => depth <= max,
+            Mode::Out(max) => depth < max,
+        };
+        if step {
+            Some(Stop::Step)
+        } else if !repeated && self.breakpoints.borrow().contains(&line) {
+            Some(Stop::Breakpoint)
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn enter_call(&self, call: &Call) {
+        self.frames.borrow_mut().push(Frame {
+            name: call.name.unwrap_or("<anonymous>").to_string(),
+            line: call.block.span.line,
+            env: call.env.clone(),
+        });
+    }
+
+    pub(crate) fn exit_call(&self) {
+        self.frames.borrow_mut().pop();
+    }
+
+    /// Sets when the program stops next
+    pub(crate) fn resume(&self, mode: Mode) {
+        self.mode.set(mode);
+    }
+
+    /// Returns the frames of the calls being executed, the program first
+    pub(crate) fn frames(&self) -> Ref<'_, Vec<Frame>> {
+        self.frames.borrow()
+    }
+
+    /// Returns the number of calls being executed, including the program
+    pub(crate) fn depth(&self) -> usize {
+        self.frames.borrow().len()
+    }
+
+    pub(crate) fn breakpoints(&self) -> &RefCell<BTreeSet<usize>> {
+        &self.breakpoints
+    }
+}
+
+impl Debugger {
+    /// Returns a Debugger for the program in `source`, reading commands from stdin
+    /// and writing to stdout
+    pub fn new(source: &str) -> Debugger {
+        // share stdin with the program, leaving it the input after each command
+        Self::with_io(source, StdinReader::new(), io::stdout())
+    }
+
+    /// Returns a Debugger for the program in `source`, reading commands from `input`
+    /// and writing to `output`
+    pub fn with_io<R, W>(source: &str, input: R, output: W) -> Debugger
+    where
+        R: BufRead + 'static,
+        W: Write + 'static,
+    {
+        Debugger {
+            lines: source.lines().map(str::to_string).collect(),
+            state: State::new(true),
+            last_command: RefCell::new(String::new()),
+            input: RefCell::new(Box::new(input)),
+            output: RefCell::new(Box::new(output)),
+        }
+    }
+
+    /// Reads and runs commands until one resumes the program
+    fn prompt(&self, line: usize, breakpoint: bool) -> Result<(), InterpreterError> {
+        let reason = if breakpoint { "breakpoint" } else { "stopped" };
+        self.write(&format!("{} at line {}\n", reason, line))?;
+        self.list_line(line, true)?;
+
+        loop {
+            self.write("(debug) ")?;
+            self.output.borrow_mut().flush()?;
+            let mut command = String::new();
+            if self.input.borrow_mut().read_line(&mut command)? == 0 {
+                // without more commands, the program runs to the end
+                self.write("\n")?;
+                self.state.breakpoints().borrow_mut().clear();
+                self.state.resume(Mode::Continue);
+                return Ok(());
+            }
+
+            let mut command = command.trim().to_string();
+            if command.is_empty() {
+                command = self.last_command.borrow().clone();
+            } else {
+                *self.last_command.borrow_mut() = command.clone();
+            }
+
+            let mut words = command.split_whitespace();
+            let name = words.next().unwrap_or_default();
+            let arg = words.next();
+            let depth = self.state.depth();
+            match name {
+                "" => {}
+                "c" | "continue" => return self.resume(Mode::Continue),
+                "s" | "step" => return self.resume(Mode::Step),
+                "n" | "next" => return self.resume(Mode::Next(depth)),
+                "o" | "out" => return self.resume(Mode::Out(depth)),
+                "b" | "break" => match self.line_arg(arg)? {
+                    Some(line) => {
+                        self.state.breakpoints().borrow_mut().insert(line);
+                        self.write(&format!("breakpoint set at line {}\n", line))?;
+                    }
+                    None => continue,
+                },
+                "d" | "delete" => match self.line_arg(arg)? {
+                    Some(line) if self.state.breakpoints().borrow_mut().remove(&line) => {
+                        self.write(&format!("breakpoint removed from line {}\n", line))?;
+                    }
+                    Some(line) => self.write(&format!("no breakpoint at line {}\n", line))?,
+                    None => continue,
+                },
+                "l" | "locals" => self.locals()?,
+                "p" | "print" => match arg {
+                    Some(name) => {
+                        let env = self.state.frames().last().map(|frame| frame.env.clone());
+                        let value = env.map(|env| env.borrow().get(name));
+                        match value {
+                            Some(Ok(value)) => self.write(&format!("{} = {}\n", name, value))?,
+                            Some(Err(err)) => self.write(&format!("{}\n", err))?,
+                            None => {}
+                        }
+                    }
+                    None => self.write("expected a name to print\n")?,
+                },
+                "bt" | "backtrace" => self.backtrace()?,
+                "list" => {
+                    let first = line.saturating_sub(3).max(1);
+                    for other in first..=(line + 3).min(self.lines.len()) {
+                        self.list_line(other, other == line)?;
+                    }
+                }
+                "h" | "help" => self.write(HELP)?,
+                "q" | "quit" => return Err(InterpreterError::Interrupted),
+                other => self.write(&format!("unknown command `{}`, try `help`\n", other))?,
+            }
+        }
+    }
+
+    fn resume(&self, mode: Mode) -> Result<(), InterpreterError> {
+        self.state.resume(mode);
+        Ok(())
+    }
+
+    /// parses the line argument of a command, reporting invalid lines
+    fn line_arg(&self, arg: Option<&str>) -> Result<Option<usize>, InterpreterError> {
+        match arg.and_then(|arg| arg.parse::<usize>().ok()) {
+            Some(line) if line >= 1 && line <= self.lines.len() => Ok(Some(line)),
+            _ => {
+                self.write(&format!("expected a line from 1 to {}\n", self.lines.len()))?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// prints a line of source, marking the current line
+    fn list_line(&self, line: usize, current: bool) -> Result<(), InterpreterError> {
+        let text = self.lines.get(line - 1).map_or("", String::as_str);
+        let marker = if current { ">" } else { " " };
+        self.write(&format!("{} {:>4} | {}\n", marker, line, text))
+    }
+
+    /// prints the names bound in the current environment and each of its parents
+    fn locals(&self) -> Result<(), InterpreterError> {
+        let mut env = self.state.frames().last().map(|frame| frame.env.clone());
+        let mut first = true;
+        while let Some(current) = env {
+            let scope = current.borrow();
+            let header = match (scope.parent(), first) {
+                (None, _) => "globals",
+                (Some(_), true) => "locals",
+                (Some(_), false) => "captured",
+            };
+            let variables = scope.variables();
+            if !variables.is_empty() || first {
+                self.write(&format!("{}:\n", header))?;
+            }
+            for (name, value) in variables {
+                self.write(&format!("    {} = {}\n", name, value))?;
+            }
+            first = false;
+            env = scope.parent().cloned();
+        }
+        Ok(())
+    }
+
+    /// prints the frames of the calls being executed, most recent first
+    fn backtrace(&self) -> Result<(), InterpreterError> {
+        let frames = self.state.frames();
+        for (i, frame) in frames.iter().rev().enumerate() {
+            self.write(&format!("#{} {} at line {}\n", i, frame.name, frame.line))?;
+        }
+        Ok(())
+    }
+
+    fn write(&self, output: &str) -> Result<(), InterpreterError> {
+        Ok(self.output.borrow_mut().write_all(output.as_bytes())?)
+    }
+}
+
+impl Hooks for Debugger {
+    fn statement(
+        &self,
+        statement: &Statement,
+        env: &Rc<RefCell<Environment>>,
+    ) -> Result<(), InterpreterError> {
+        match self.state.statement(statement, env) {
+            Some(stop) => self.prompt(statement.span.line, stop == Stop::Breakpoint),
+            None => Ok(()),
+        }
+    }
+
+    fn enter_call(&self, call: &Call) {
+        self.state.enter_call(call);
+    }
+
+    fn exit_call(&self, _call: &Call, _result: &Result<Value, InterpreterError>) {
+        self.state.exit_call();
+    }
+}
@@ -0,0 +1,66 @@
+//! Author: Rafael Bayer (2021)
+//! The error module defines `Error`, any error encountered
+//! reading, parsing, or running a Puffin program.
+
+use std::fmt::Display;
+
+use crate::{interpreter::InterpreterError, parser::ParseError};
+
+/// Puffin error, from any stage of running a program
+#[derive(Debug)]
+pub enum Error {
+    /// Failed to read the program
+    Io(std::io::Error),
+    /// Failed to parse the program, with every syntax error found
+    Parse(Vec<ParseError>),
+    /// Program encountered a runtime error
+    Runtime(InterpreterError),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "IO Error: {}", err),
+            Error::Parse(errors) => {
+                for (i, err) in errors.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "\n\n")?;
+                    }
+                    write!(f, "Parser Error: {}", err)?;
+                }
+                Ok(())
+            }
+            Error::Runtime(err) => write!(f, "Runtime Error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::Parse(errors) => errors
+                .first()
+                .map(|err| err as &(dyn std::error::Error + 'static)),
+            Error::Runtime(err) => Some(err),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<Vec<ParseError>> for Error {
+    fn from(errors: Vec<ParseError>) -> Self {
+        Error::Parse(errors)
+    }
+}
+
+impl From<InterpreterError> for Error {
+    fn from(err: InterpreterError) -> Self {
+        Error::Runtime(err)
+    }
+}
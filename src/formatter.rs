@@ -0,0 +1,453 @@
+//! Author: Rafael Bayer (2021)
+//! The formatter module prints the AST of a program back into source, in a consistent style.
+//!
+//! Blocks are indented by four spaces, infix operators are surrounded by spaces,
+//! and every statement is on its own line. Comments are kept, either on their own line or
+//! trailing a statement, as are single blank lines between statements.
+//! Comments inside a list of arguments, or before an `else`, end the line they are on.
+//! Structures stay on one line, unless they spanned several lines in the source.
+
+use crate::ast::node::*;
+use crate::parser::Span;
+
+const INDENT: &str = "    ";
+
+/// Formats the AST of a program as source
+pub fn format_program(program: &Program) -> String {
+    let mut formatter = Formatter {
+        comments: &program.comments,
+        next_comment: 0,
+        out: String::new(),
+        indent: 0,
+    };
+
+    let mut items = Items::new(None);
+    for statement in &program.program {
+        formatter.item(&mut items, statement.span);
+        formatter.statement(statement);
+        items.prev_line = Some(statement.span.end_line);
+    }
+    formatter.comments_before(&mut items, usize::MAX);
+
+    if !formatter.out.is_empty() {
+        formatter.out.push('\n');
+    }
+    formatter.out
+}
+
+struct Formatter<'a> {
+    comments: &'a [Comment],
+    /// index of the first comment that hasn't been written
+    next_comment: usize,
+    out: String,
+    indent: usize,
+}
+
+/// Position in a list of items on separate lines, like the statements of a block
+struct Items {
+    /// line the previous item ended on, or of the opening brace
+    prev_line: Option<usize>,
+    first: bool,
+}
+
+impl Items {
+    fn new(open_line: Option<usize>) -> Items {
+        Items {
+            prev_line: open_line,
+            first: true,
+        }
+    }
+}
+
+impl Formatter<'_> {
+    /// starts the line of an item at `span`, writing any comments before it first
+    fn item(&mut self, items: &mut Items, span: Span) {
+        self.comments_before(items, span.start);
+        self.line_break(items, span.line);
+    }
+
+    /// writes the comments that start before byte offset `pos`
+    fn comments_before(&mut self, items: &mut Items, pos: usize) {
+        while let Some(comment) = self.comments.get(self.next_comment) {
+            if comment.span.start >= pos {
+                return;
+            }
+            self.next_comment += 1;
+
+            // comments on the same line as the previous item trail it
+            if items.prev_line == Some(comment.span.line) {
+                self.out.push(' ');
+            } else {
+                self.line_break(items, comment.span.line);
+            }
+            self.out.push_str(&comment.text);
+            items.prev_line = Some(comment.span.end_line);
+        }
+    }
+
+    /// writes the comments that start before byte offset `pos` in the middle of a line,
+    /// after something that ended on `prev_line`.
+    /// Returns true if there were any, so a new line has to be started after them
+    fn comments_inline(&mut self, prev_line: usize, pos: usize) -> bool {
+        let written = self.next_comment;
+        self.comments_before(&mut Items::new(Some(prev_line)), pos);
+        self.next_comment > written
+    }
+
+    /// starts a new line at the current indentation
+    fn newline(&mut self) {
+        self.out.push('\n');
+        self.out.push_str(&INDENT.repeat(self.indent));
+    }
+
+    /// starts a new line for an item at `line`,
+    /// keeping a blank line if there was one before it in the source
+    fn line_break(&mut self, items: &mut Items, line: usize) {
+        if !self.out.is_empty() {
+            self.out.push('\n');
+            let blank = matches!(items.prev_line, Some(prev) if line > prev + 1);
+            if blank && !items.first {
+                self.out.push('\n');
+            }
+        }
+        items.first = false;
+        self.out.push_str(&INDENT.repeat(self.indent));
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match &statement.statement {
+            StatementKind::Nest(nest) => self.nest(nest),
+            kind => {
+                self.simple_statement(kind);
+                self.out.push(';');
+            }
+        }
+    }
+
+    /// writes a statement without its trailing `;`
+    fn simple_statement(&mut self, statement: &StatementKind) {
+        match statement {
+            StatementKind::Return(exp) => {
+                self.out.push_str("return ");
+                self.exp(exp);
+            }
+            StatementKind::Assign {
+                lhs,
+                rhs,
+                aug,
+                annotation,
+            } => {
+                self.assignable(lhs);
+                if let Some(ty) = annotation {
+                    self.out.push_str(&format!(": {}", ty));
+                }
+                match (aug, &rhs.exp) {
+                    // a op= b was expanded to a = a op b
+                    (Some(op), ExpKind::Infix(_, _, right)) => {
+                        self.out.push_str(&format!(" {}= ", op));
+                        self.exp(right);
+                    }
+                    _ => {
+                        self.out.push_str(" = ");
+                        self.exp(rhs);
+                    }
+                }
+            }
+            StatementKind::Exp(exp) => self.exp(exp),
+            StatementKind::Nest(nest) => self.nest(nest),
+        }
+    }
+
+    fn assignable(&mut self, assignable: &Assignable) {
+        self.out.push_str(&assignable.name);
+        for sub in &assignable.assignable {
+            match sub {
+                AssignableKind::ArrayIndex { index } => {
+                    self.out.push('[');
+                    self.exp(index);
+                    self.out.push(']');
+                }
+                AssignableKind::StructureField { field } => {
+                    self.out.push('.');
+                    self.out.push_str(field);
+                }
+            }
+        }
+    }
+
+    fn nest(&mut self, nest: &NestKind) {
+        match nest {
+            NestKind::CondNest(CondNestKind::If { cond, then }) => {
+                self.out.push_str("if (");
+                self.exp(cond);
+                self.out.push_str(") ");
+                self.block(then);
+            }
+            NestKind::CondNest(CondNestKind::IfElse {
+                cond,
+                then,
+                or_else,
+            }) => {
+                self.out.push_str("if (");
+                self.exp(cond);
+                self.out.push_str(") ");
+                self.block(then);
+                if self.comments_inline(then.span.end_line, or_else.span.start) {
+                    self.newline();
+                    self.out.push_str("else ");
+                } else {
+                    self.out.push_str(" else ");
+                }
+                self.block(or_else);
+            }
+            NestKind::LoopNest(LoopNestKind::While { cond, block }) => {
+                self.out.push_str("while ");
+                self.exp(cond);
+                self.out.push(' ');
+                self.block(block);
+            }
+            NestKind::LoopNest(LoopNestKind::ForIn { name, array, block }) => {
+                self.out.push_str(&format!("for ({} in ", name));
+                self.exp(array);
+                self.out.push_str(") ");
+                self.block(block);
+            }
+            NestKind::LoopNest(LoopNestKind::For {
+                init,
+                cond,
+                adv,
+                block,
+            }) => {
+                self.out.push_str("for (");
+                self.statement(init);
+                self.out.push(' ');
+                self.exp(cond);
+                self.out.push_str("; ");
+                self.simple_statement(&adv.statement);
+                self.out.push_str(") ");
+                self.block(block);
+            }
+        }
+    }
+
+    fn block(&mut self, block: &Block) {
+        let has_comments = self
+            .comments
+            .get(self.next_comment)
+            .is_some_and(|comment| comment.span.start < block.span.end);
+        if block.block.is_empty() && !has_comments {
+            self.out.push_str("{}");
+            return;
+        }
+
+        self.out.push('{');
+        self.indent += 1;
+        let mut items = Items::new(Some(block.span.line));
+        for statement in &block.block {
+            self.item(&mut items, statement.span);
+            self.statement(statement);
+            items.prev_line = Some(statement.span.end_line);
+        }
+        self.comments_before(&mut items, block.span.end);
+        self.indent -= 1;
+
+        self.newline();
+        self.out.push('}');
+    }
+
+    fn exp(&mut self, exp: &Exp) {
+        match &exp.exp {
+            ExpKind::Value(value) => self.value(value, exp.span),
+            ExpKind::Unary(op, operand) => {
+                self.out.push_str(&op.to_string());
+                self.operand(operand, matches!(operand.exp, ExpKind::Infix(..)));
+            }
+            ExpKind::Infix(op, left, right) => {
+                // the parser keeps parentheses as values,
+                // so these are only needed for trees that weren't parsed
+                let looser = |exp: &Exp, or_equal: bool| match &exp.exp {
+                    ExpKind::Infix(inner, _, _) => {
+                        inner.precedence() < op.precedence()
+                            || (or_equal && inner.precedence() == op.precedence())
+                    }
+                    _ => false,
+                };
+                self.operand(left, looser(left, false));
+                self.out.push_str(&format!(" {} ", op));
+                self.operand(right, looser(right, true));
+            }
+            ExpKind::Postfix(operand, op) => {
+                let wrap = matches!(operand.exp, ExpKind::Infix(..) | ExpKind::Unary(..));
+                self.operand(operand, wrap);
+                match op {
+                    PostOp::Subscript(index) => {
+                        self.out.push('[');
+                        self.exp(index);
+                        self.out.push(']');
+                    }
+                    PostOp::Call(args) => {
+                        self.out.push('(');
+                        let open_line = operand.span.end_line;
+                        self.list(args, |arg| arg.span, open_line, exp.span.end, Self::exp);
+                        self.out.push(')');
+                    }
+                    PostOp::Dot(name) => {
+                        self.out.push('.');
+                        self.out.push_str(name);
+                    }
+                }
+            }
+        }
+    }
+
+    /// writes an operand of an operator, in parentheses if `wrap`
+    fn operand(&mut self, exp: &Exp, wrap: bool) {
+        if wrap {
+            self.out.push('(');
+        }
+        self.exp(exp);
+        if wrap {
+            self.out.push(')');
+        }
+    }
+
+    fn value(&mut self, value: &ValueKind, span: Span) {
+        match value {
+            ValueKind::Paren(exp) => {
+                self.out.push('(');
+                self.exp(exp);
+                self.out.push(')');
+            }
+            ValueKind::Structure(fields) => self.structure(fields, span),
+            ValueKind::FunctionDef {
+                args,
+                arg_spans,
+                signature,
+                block,
+                lambda,
+            } => {
+                let args: Vec<(String, Span)> = args
+                    .iter()
+                    .zip(&signature.args)
+                    .map(|(arg, ty)| match ty {
+                        Some(ty) => format!("{}: {}", arg, ty),
+                        None => arg.clone(),
+                    })
+                    .zip(arg_spans.iter().copied())
+                    .collect();
+                self.out.push_str("fn(");
+                // comments up to the body, after the arguments, are kept in the arguments
+                self.list(&args, |(_, span)| *span, span.line, block.span.start, |formatter, (arg, _)| {
+                    formatter.out.push_str(arg)
+                });
+                self.out.push(')');
+                if let Some(ty) = signature.ret {
+                    self.out.push_str(&format!(" -> {}", ty));
+                }
+                match (lambda, &block.block[..]) {
+                    (
+                        true,
+                        [Statement {
+                            statement: StatementKind::Return(exp),
+                            ..
+                        }],
+                    ) => {
+                        self.out.push_str(" => ");
+                        self.exp(exp);
+                    }
+                    _ => {
+                        self.out.push(' ');
+                        self.block(block);
+                    }
+                }
+            }
+            ValueKind::Num(n) => self.out.push_str(&n.to_string()),
+            ValueKind::String(string) => self.out.push_str(&format!("\"{}\"", string)),
+            ValueKind::ArrayInit(ArrayInitKind::Sized(size)) => {
+                self.out.push('[');
+                self.exp(size);
+                self.out.push(']');
+            }
+            ValueKind::ArrayInit(ArrayInitKind::Range(from, to)) => {
+                self.out.push('[');
+                self.exp(from);
+                self.out.push(':');
+                self.exp(to);
+                self.out.push(']');
+            }
+            ValueKind::Name(name) => self.out.push_str(name),
+            ValueKind::Null => self.out.push_str("null"),
+        }
+    }
+
+    /// writes a structure, with a field per line if it spanned several lines
+    fn structure(&mut self, fields: &[Field], span: Span) {
+        // comments inside empty braces are kept in them, like comments after the last field
+        if fields.is_empty() || span.line == span.end_line {
+            self.out.push('{');
+            self.list(fields, |field| field.exp.span, span.line, span.end, Self::field);
+            self.out.push('}');
+            return;
+        }
+
+        self.out.push('{');
+        self.indent += 1;
+        let mut items = Items::new(Some(span.line));
+        for (i, field) in fields.iter().enumerate() {
+            self.item(&mut items, field.exp.span);
+            self.field(field);
+            if i + 1 < fields.len() {
+                self.out.push(',');
+            }
+            items.prev_line = Some(field.exp.span.end_line);
+        }
+        self.comments_before(&mut items, span.end);
+        self.indent -= 1;
+
+        self.newline();
+        self.out.push('}');
+    }
+
+    fn field(&mut self, field: &Field) {
+        self.out.push_str(&field.name);
+        self.out.push_str(": ");
+        self.exp(&field.exp);
+    }
+
+    /// writes items separated by commas, between brackets opened on `open_line`
+    /// and closed at byte offset `end`. `span` returns the span of an item.
+    /// Comments between the items end their line, which continues indented
+    fn list<T>(
+        &mut self,
+        items: &[T],
+        span: impl Fn(&T) -> Span,
+        open_line: usize,
+        end: usize,
+        mut write: impl FnMut(&mut Self, &T),
+    ) {
+        let mut prev_line = open_line;
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                self.out.push(',');
+            }
+            self.indent += 1;
+            let continued = self.comments_inline(prev_line, span(item).start);
+            if continued {
+                self.newline();
+            } else if i > 0 {
+                self.out.push(' ');
+            }
+            self.indent -= 1;
+            write(self, item);
+            prev_line = span(item).end_line;
+        }
+
+        self.indent += 1;
+        let closed = self.comments_inline(prev_line, end);
+        self.indent -= 1;
+        // the closing bracket goes on its own line after comments
+        if closed {
+            self.newline();
+        }
+    }
+}
@@ -0,0 +1,76 @@
+//! Author: Rafael Bayer (2021)
+//! The hooks module defines `Hooks`, callbacks a host registers on a `Runtime`
+//! to observe a program as it executes, for example to debug it.
+//! Several Hooks are registered together as a `Vec` of them, called in order.
+
+use std::{cell::RefCell, rc::Rc};
+
+use super::{value::Environment, InterpreterError, Value};
+use crate::ast::node::{Assignable, Block, Exp, Statement};
+
+/// Callbacks invoked by the interpreter as a program executes.
+/// Every callback does nothing by default.
+pub trait Hooks {
+    /// Called before `statement` executes in `env`.
+    /// Returning an error stops the program with that error.
+    fn statement(
+        &self,
+        _statement: &Statement,
+        _env: &Rc<RefCell<Environment>>,
+    ) -> Result<(), InterpreterError> {
+        Ok(())
+    }
+
+    /// Called after the condition `cond` of an `if` is evaluated,
+    /// with whether its block is taken
+    fn branch(&self, _cond: &Exp, _taken: bool) {}
+
+    /// Called after an assignment to `lhs` binds `value`
+    fn assign(&self, _lhs: &Assignable, _value: &Value) {}
+
+    /// Called when a closure call begins, after its arguments are bound
+    fn enter_call(&self, _call: &Call) {}
+
+    /// Called when a closure call ends, with its result
+    fn exit_call(&self, _call: &Call, _result: &Result<Value, InterpreterError>) {}
+}
+
+impl Hooks for Vec<Rc<dyn Hooks>> {
+    fn statement(
+        &self,
+        statement: &Statement,
+        env: &Rc<RefCell<Environment>>,
+    ) -> Result<(), InterpreterError> {
+        self.iter().try_for_each(|hooks| hooks.statement(statement, env))
+    }
+
+    fn branch(&self, cond: &Exp, taken: bool) {
+        self.iter().for_each(|hooks| hooks.branch(cond, taken));
+    }
+
+    fn assign(&self, lhs: &Assignable, value: &Value) {
+        self.iter().for_each(|hooks| hooks.assign(lhs, value));
+    }
+
+    fn enter_call(&self, call: &Call) {
+        self.iter().for_each(|hooks| hooks.enter_call(call));
+    }
+
+    fn exit_call(&self, call: &Call, result: &Result<Value, InterpreterError>) {
+        self.iter().for_each(|hooks| hooks.exit_call(call, result));
+    }
+}
+
+/// Closure call, given to the call hooks
+pub struct Call<'a> {
+    /// Name the closure was bound to, if any
+    pub name: Option<&'a str>,
+    /// Names of the arguments
+    pub args: &'a [String],
+    /// Values of the arguments
+    pub values: &'a [Value],
+    /// Body of the closure
+    pub block: &'a Block,
+    /// Environment of the call, binding the arguments
+    pub env: &'a Rc<RefCell<Environment>>,
+}
@@ -4,48 +4,135 @@
 //! to evaluate all parts of the Puffin AST.
 
 use std::{
-    cell::RefCell, collections::HashMap, convert::TryInto, f64::EPSILON, fmt::Display, rc::Rc,
-    usize,
+    cell::RefCell, collections::HashMap, convert::TryInto, fmt::Display, rc::Rc, time::Duration,
 };
 
 use crate::ast::node::*;
-use value::Environment;
+use hooks::Call;
+use runtime::{Capability, Runtime, SharedBuffer};
+use value::{heap, Environment};
 pub use value::Value;
 
-use self::value::ClosureKind;
+use self::value::{builtin::Builtin, ClosureKind};
 
+pub mod hooks;
 mod operations;
 pub mod repl;
-mod shunting_yard;
+pub mod runtime;
 pub mod value;
 
-/// Starting capacity for the expression evaluation stack.
-const EXP_STACK_START_CAPACITY: usize = 4;
-
 /// Interpreter error, essentially a Puffin Runtime error.
 #[derive(Debug, Clone)]
 pub enum InterpreterError {
     /// Usage of an unbound name
     UnboundName(String),
-    /// Unexpected number of arguments to function
-    ArgMismatch { expected: usize, got: usize },
+    /// Unexpected number of arguments to function, and the name of the function if known
+    ArgMismatch {
+        /// minimum and maximum number of arguments
+        expected: (usize, usize),
+        got: usize,
+        callee: Option<String>,
+    },
     /// Type mismatch
     UnexpectedType(String),
+    /// Value didn't have the type it was annotated with, checked when the Runtime enables type checks
+    TypeMismatch {
+        target: String,
+        expected: Type,
+        got: Type,
+    },
     /// Attempted to rebind builtin name
     BuiltinRebinding(String),
+    /// Usage of a builtin whose capability is unavailable
+    MissingCapability {
+        name: String,
+        capability: Capability,
+    },
     /// Error getting user input
     IOError(String),
     /// Array bounds error
     BoundsError { index: usize, size: usize },
     /// Range validity error
     RangeError { from: i128, to: i128 },
+    /// Error parsing or writing JSON
+    JsonError(String),
     /// User created error
     Error,
+    /// Program called `exit` with a process exit code
+    Exit(i32),
+    /// Exceeded the maximum number of steps
+    StepLimit(u64),
+    /// Exceeded the maximum call depth
+    DepthLimit(usize),
+    /// Exceeded the maximum allocated memory
+    MemoryLimit(usize),
+    /// Exceeded the maximum wall time
+    TimeLimit(Duration),
+    /// Program was stopped by its host, for example from a debugger
+    Interrupted,
+    /// Assertion builtin failed, with a description of the failure
+    AssertionFailed(String),
 }
 
 /// evaluates a program AST. Entrypoint of the interpreter
 pub fn eval(program: &Program) -> Result<Value, InterpreterError> {
-    eval_env(program, &Rc::new(RefCell::new(Environment::new())))
+    eval_with(program, Runtime::new())
+}
+
+/// evaluates a program AST under a given Runtime, for example to enforce resource limits
+pub fn eval_with(program: &Program, runtime: Runtime) -> Result<Value, InterpreterError> {
+    runtime.start();
+    eval_env(
+        program,
+        &heap::environment(Environment::with_runtime(Rc::new(runtime))),
+    )
+}
+
+/// evaluates the top-level assignments of a program AST under a given Runtime, then calls
+/// the closure bound to `name` without arguments, returning the result of the call.
+/// Other top-level statements, like calls and loops, aren't evaluated, so their side effects
+/// don't happen once per test.
+/// Each call has its own global environment, so tests of the same program can't affect each other
+pub fn eval_test(program: &Program, runtime: Runtime, name: &str) -> Result<Value, InterpreterError> {
+    runtime.start();
+    let env = heap::environment(Environment::with_runtime(Rc::new(runtime)));
+    for statement in &program.program {
+        if let StatementKind::Assign { .. } = statement.statement {
+            eval_statement(statement, &env)?;
+        }
+    }
+    let test = env.borrow().get(name)?;
+    let runtime = env.borrow().runtime().clone();
+    call(&test, vec![], &runtime)
+}
+
+/// Result and output of a program evaluated by `eval_captured`
+#[derive(Debug, Clone)]
+pub struct Captured {
+    /// Result of the program
+    pub result: Result<Value, InterpreterError>,
+    /// Everything the program wrote to its output
+    pub stdout: String,
+    /// Everything the program wrote to its error output
+    pub stderr: String,
+}
+
+/// evaluates a program AST under a given Runtime, reading input from `stdin`
+/// and capturing everything the program writes.
+pub fn eval_captured(program: &Program, runtime: Runtime, stdin: &str) -> Captured {
+    let stdout = SharedBuffer::new();
+    let stderr = SharedBuffer::new();
+    let runtime = runtime
+        .with_stdin(std::io::Cursor::new(stdin.to_string().into_bytes()))
+        .with_stdout(stdout.clone())
+        .with_stderr(stderr.clone());
+
+    let result = eval_with(program, runtime);
+    Captured {
+        result,
+        stdout: stdout.contents(),
+        stderr: stderr.contents(),
+    }
 }
 
 /// evaluates a program under a given environment
@@ -71,9 +158,14 @@ fn eval_repl_statement(
 ) -> Result<Option<Value>, InterpreterError> {
     match &statement.statement {
         StatementKind::Return(exp) => return Ok(Some(eval_exp(exp, env)?)),
-        StatementKind::Assign { lhs, rhs } => eval_assign(lhs, rhs, env),
+        StatementKind::Assign {
+            lhs,
+            rhs,
+            annotation,
+            ..
+        } => eval_assign(lhs, rhs, *annotation, env),
         // repl version also returns expression values
-        StatementKind::Exp(exp) => return Ok(Some(eval_exp(&exp, env)?)),
+        StatementKind::Exp(exp) => return Ok(Some(eval_exp(exp, env)?)),
         StatementKind::Nest(nest) => match eval_nest(nest, env)? {
             Some(return_value) => return Ok(Some(return_value)),
             None => return Ok(None),
@@ -87,10 +179,17 @@ fn eval_statement(
     statement: &Statement,
     env: &Rc<RefCell<Environment>>,
 ) -> Result<Option<Value>, InterpreterError> {
+    hook_statement(statement, env)?;
+
     match &statement.statement {
         StatementKind::Return(exp) => return Ok(Some(eval_exp(exp, env)?)),
-        StatementKind::Assign { lhs, rhs } => eval_assign(lhs, rhs, env),
-        StatementKind::Exp(exp) => eval_exp(&exp, env),
+        StatementKind::Assign {
+            lhs,
+            rhs,
+            annotation,
+            ..
+        } => eval_assign(lhs, rhs, *annotation, env),
+        StatementKind::Exp(exp) => eval_exp(exp, env),
         StatementKind::Nest(nest) => match eval_nest(nest, env)? {
             // if a nest statement has a value, it had a return statement,
             // we propagate this to the caller so they can return (or program).
@@ -140,6 +239,7 @@ fn eval_subscript(
                     size: string.len(),
                 });
             }
+            env.borrow().runtime().allocate(1)?;
             Value::from((string.as_bytes()[index] as char).to_string())
         }
         _ => return Err(unexpected_type(value)),
@@ -151,63 +251,153 @@ fn eval_call(
     exps: &[Exp],
     env: &Rc<RefCell<Environment>>,
 ) -> Result<Value, InterpreterError> {
-    Ok(match &callable {
-        // function/closure call
+    let mut actuals = Vec::with_capacity(exps.len());
+    // evaluate the actuals
+    for actual in exps {
+        actuals.push(eval_exp(actual, env)?);
+    }
+    let runtime = env.borrow().runtime().clone();
+    call(&callable, actuals, &runtime)
+}
+
+/// calls a closure or builtin with the values of its arguments,
+/// for example to call a closure passed to a builtin
+pub(crate) fn call(
+    callable: &Value,
+    actuals: Vec<Value>,
+    runtime: &Runtime,
+) -> Result<Value, InterpreterError> {
+    match callable {
+        Value::Closure { .. } => call_closure(callable, actuals),
+        Value::Builtin(f) => call_builtin(f, actuals, runtime),
+        _ => Err(unexpected_type(callable.clone())),
+    }
+}
+
+/// calls a closure, binding its arguments in a new environment
+fn call_closure(callable: &Value, actuals: Vec<Value>) -> Result<Value, InterpreterError> {
+    let (kind, args, signature, block, environment) = match callable {
         Value::Closure {
             kind,
             args,
+            signature,
             block,
             environment,
-        } => {
-            // ensure the call has the appropriate number of args for the function
-            if exps.len() != args.len() {
-                return Err(InterpreterError::ArgMismatch {
-                    expected: args.len(),
-                    got: exps.len(),
-                });
-            }
+        } => (kind, args, signature, block, environment),
+        other => return Err(unexpected_type(other.clone())),
+    };
+
+    // ensure the call has the appropriate number of args for the function
+    if actuals.len() != args.len() {
+        let callee = match kind {
+            ClosureKind::Named(name) => Some(name.clone()),
+            _ => None,
+        };
+        return Err(InterpreterError::ArgMismatch {
+            expected: (args.len(), args.len()),
+            got: actuals.len(),
+            callee,
+        });
+    }
 
-            let subenv = Rc::new(RefCell::new(Environment::new_sub(&environment)));
+    let subenv = heap::environment(Environment::new_sub(environment));
 
-            // bind the args to the actuals
-            for i in 0..args.len() {
-                let actual = eval_exp(&exps[i], env)?;
-                subenv.borrow_mut().bind(&args[i], actual)?;
-            }
+    // bind the args to the actuals
+    let runtime = subenv.borrow().runtime().clone();
+    for (i, actual) in actuals.iter().enumerate() {
+        if runtime.type_checks() {
+            check_type(actual, signature.args[i], || format!("argument `{}`", args[i]))?;
+        }
+        subenv.borrow_mut().bind(&args[i], actual.clone())?;
+    }
 
-            // if the function was named, bind its name to itself to allow recursion
-            if let ClosureKind::Named(name) = kind {
-                subenv.borrow_mut().bind(name, callable.clone())?;
-            // if the function was a receiver of a structure, bind the structure to "self"
-            } else if let ClosureKind::Receiver(structure) = kind {
-                subenv
-                    .borrow_mut()
-                    .bind("self", Value::Structure(structure.clone()))?;
-            }
+    // if the function was named, bind its name to itself to allow recursion
+    if let ClosureKind::Named(name) = kind {
+        subenv.borrow_mut().bind(name, callable.clone())?;
+    // if the function was a receiver of a structure, bind the structure to "self"
+    } else if let ClosureKind::Receiver(structure) = kind {
+        subenv
+            .borrow_mut()
+            .bind("self", Value::Structure(structure.clone()))?;
+    }
 
-            // evaluate the closures body.
-            // if the block evaluates to none, the implicit result is null
-            eval_block(&block, &subenv)?.unwrap_or(Value::Null)
-        }
-        // builtin call
-        Value::Builtin(f) => {
-            let mut actuals = Vec::with_capacity(exps.len());
-            // evaluate the actuals
-            for actual in exps {
-                actuals.push(eval_exp(&actual, env)?);
-            }
+    // evaluate the closures body.
+    // if the block evaluates to none, the implicit result is null
+    runtime.enter_call()?;
+    let call = Call {
+        name: match kind {
+            ClosureKind::Named(name) => Some(name),
+            _ => None,
+        },
+        args,
+        values: &actuals,
+        block,
+        env: &subenv,
+    };
+    let result = stacker::maybe_grow(runtime::STACK_RED_ZONE, runtime::STACK_GROWTH, || {
+        eval_body(&call, &runtime)
+    });
+    runtime.exit_call();
+    let result = result?;
+    if runtime.type_checks() {
+        check_type(&result, signature.ret, || match kind {
+            ClosureKind::Named(name) => format!("return value of `{}`", name),
+            _ => "return value".to_string(),
+        })?;
+    }
+    Ok(result)
+}
 
-            // call the builtin function body with the actuals.
-            // the function body is responsible for validating number of args
-            // for builtins, which allows dynamic number of args for certain builtins
-            (f.body)(actuals)?
-        }
-        _ => {
-            return Err(unexpected_type(callable));
-        }
+/// calls a builtin function body with the actuals.
+/// the function body is responsible for validating number of args
+/// for builtins, which allows dynamic number of args for certain builtins
+fn call_builtin(f: &Builtin, actuals: Vec<Value>, runtime: &Runtime) -> Result<Value, InterpreterError> {
+    (f.body)(actuals, runtime).map_err(|err| match err {
+        // name the builtin in its own argument errors
+        InterpreterError::ArgMismatch {
+            expected,
+            got,
+            callee: None,
+        } => InterpreterError::ArgMismatch {
+            expected,
+            got,
+            callee: Some(f.name().to_string()),
+        },
+        err => err,
     })
 }
 
+/// evaluates the body of a closure call, calling the Runtime's hooks around it.
+/// if the block evaluates to none, the implicit result is null
+fn eval_body(call: &Call, runtime: &Runtime) -> Result<Value, InterpreterError> {
+    if let Some(hooks) = runtime.hooks() {
+        hooks.enter_call(call);
+    }
+    let result = eval_block(call.block, call.env).map(|result| result.unwrap_or(Value::Null));
+    if let Some(hooks) = runtime.hooks() {
+        hooks.exit_call(call, &result);
+    }
+    result
+}
+
+/// calls the Runtime's statement hook, if any
+fn hook_statement(statement: &Statement, env: &Rc<RefCell<Environment>>) -> Result<(), InterpreterError> {
+    // hooks may inspect the environment, so it isn't borrowed while they run
+    let hooks = env.borrow().runtime().hooks().cloned();
+    match hooks {
+        Some(hooks) => hooks.statement(statement, env),
+        None => Ok(()),
+    }
+}
+
+/// calls the Runtime's branch hook, if any
+fn hook_branch(cond: &Exp, taken: bool, env: &Rc<RefCell<Environment>>) {
+    let hooks = env.borrow().runtime().hooks().cloned();
+    if let Some(hooks) = hooks {
+        hooks.branch(cond, taken);
+    }
+}
+
 fn eval_dot(dotable: Value, name: &str) -> Result<Value, InterpreterError> {
     Ok(match dotable {
         Value::Structure(map) => match map.borrow().get(name) {
@@ -219,46 +409,37 @@ fn eval_dot(dotable: Value, name: &str) -> Result<Value, InterpreterError> {
 }
 
 fn eval_exp(exp: &Exp, env: &Rc<RefCell<Environment>>) -> Result<Value, InterpreterError> {
-    // use the shunting yard algorithm to convert the expression to postfix notation
-    let mut rpn_queue = shunting_yard::as_rpn_queue(exp);
-
-    // we then evaluate the postfix expression using a stack
-    let mut stack: Vec<Value> = Vec::with_capacity(EXP_STACK_START_CAPACITY);
-
-    // evaluate rpn
-    while !rpn_queue.is_empty() {
-        let top = rpn_queue.pop_front().unwrap();
-        let result = match top {
-            // evaluate operators
-            TermKind::Operator(op, _, _) => match op {
-                // unary (prefix) operators
-                OperatorKind::Unary(unop) => {
-                    let value = stack.pop().unwrap();
-                    operations::unary(unop, value)?
-                }
-                // infix operators
-                OperatorKind::Infix(infix) => {
-                    let right = stack.pop().unwrap();
-                    let left = stack.pop().unwrap();
-                    operations::infix(infix, left, right)?
-                }
-                // postfix operators
-                OperatorKind::Postfix(postop) => {
-                    let next = stack.pop().unwrap();
-                    eval_postfix(postop, next, env)?
-                }
-            },
-            // values get evaluated and pushed onto the stack
-            TermKind::Value(v) => eval_value(v, env)?,
-        };
+    env.borrow().runtime().step()?;
+    eval_operand(exp, env)
+}
 
-        stack.push(result);
+/// evaluates the expression tree `exp`.
+/// operands are evaluated in place, so only `eval_exp` counts as a step
+fn eval_operand(exp: &Exp, env: &Rc<RefCell<Environment>>) -> Result<Value, InterpreterError> {
+    match &exp.exp {
+        ExpKind::Value(v) => eval_value(v, env),
+        // unary (prefix) operators
+        ExpKind::Unary(unop, operand) => {
+            let value = eval_operand(operand, env)?;
+            operations::unary(unop, value)
+        }
+        // infix operators
+        ExpKind::Infix(infix, left, right) => {
+            let left = eval_operand(left, env)?;
+            let right = eval_operand(right, env)?;
+            let result = operations::infix(infix, left, right)?;
+            // account for strings created by concatenation
+            if let Value::String(string) = &result {
+                env.borrow().runtime().allocate(string.len())?;
+            }
+            Ok(result)
+        }
+        // postfix operators
+        ExpKind::Postfix(operand, postop) => {
+            let value = eval_operand(operand, env)?;
+            eval_postfix(postop, value, env)
+        }
     }
-
-    // after evaluating the expression, the final value on the stack is
-    // the expressions result
-    assert_eq!(1, stack.len());
-    Ok(stack.pop().unwrap())
 }
 
 fn eval_value(
@@ -268,13 +449,15 @@ fn eval_value(
     match value {
         ValueKind::Paren(exp) => eval_exp(exp, env),
         ValueKind::Structure(fields) => {
-            let map = Rc::new(RefCell::new(HashMap::with_capacity(fields.len())));
+            env.borrow().runtime().allocate(fields.len())?;
+            let map = heap::structure(HashMap::with_capacity(fields.len()));
             for field in fields {
                 let mut field_value = eval_exp(&field.exp, env)?;
 
                 // if the fields value is a closure, check if the first argument is "self"
                 if let Value::Closure {
                     args,
+                    signature,
                     block,
                     environment,
                     ..
@@ -284,9 +467,14 @@ fn eval_value(
                     if args.first() == Some(&"self".to_string()) {
                         // take out the "self" argument, leaving the remaining args
                         let other_args: Vec<String> = args[1..].to_vec();
+                        let signature = Signature {
+                            args: signature.args[1..].to_vec(),
+                            ret: signature.ret,
+                        };
                         field_value = Value::Closure {
                             kind: ClosureKind::Receiver(map.clone()),
                             args: other_args,
+                            signature,
                             block: block.clone(),
                             environment: environment.clone(),
                         }
@@ -298,7 +486,12 @@ fn eval_value(
 
             Ok(Value::Structure(map))
         }
-        ValueKind::FunctionDef { args, block } => {
+        ValueKind::FunctionDef {
+            args,
+            signature,
+            block,
+            ..
+        } => {
             // functions evaluate to a closure that captures the local environment.
             // by default, closures are anonymous (self_name = None).
             // kind is changed later by eval_assign if we are binding this closure to a name,
@@ -306,17 +499,22 @@ fn eval_value(
             Ok(Value::Closure {
                 kind: ClosureKind::Anonymous,
                 args: args.clone(),
+                signature: signature.clone(),
                 block: block.clone(),
                 environment: env.clone(),
             })
         }
         ValueKind::Num(n) => Ok(Value::Num(*n)),
-        ValueKind::String(string) => Ok(Value::String(string.clone())),
+        ValueKind::String(string) => {
+            env.borrow().runtime().allocate(string.len())?;
+            Ok(Value::String(string.clone()))
+        }
         ValueKind::ArrayInit(init_exp) => match init_exp {
             ArrayInitKind::Sized(size_exp) => {
                 let size_float: f64 = eval_exp(size_exp, env)?.try_into()?;
                 let size = size_float as usize;
-                Ok(Value::Array(Rc::new(RefCell::new(vec![Value::Null; size]))))
+                env.borrow().runtime().allocate(size)?;
+                Ok(Value::from(vec![Value::Null; size]))
             }
             ArrayInitKind::Range(from_exp, to_exp) => {
                 let from_float: f64 = eval_exp(from_exp, env)?.try_into()?;
@@ -328,9 +526,10 @@ fn eval_value(
                 if from > to {
                     return Err(InterpreterError::RangeError { from, to });
                 }
+                env.borrow().runtime().allocate((to - from) as usize)?;
 
                 let vec: Vec<Value> = (from..to).map(|e| Value::from(e as f64)).collect();
-                Ok(Value::Array(Rc::new(RefCell::new(vec))))
+                Ok(Value::from(vec))
             }
         },
         ValueKind::Name(name) => env.borrow().get(name),
@@ -355,43 +554,61 @@ fn eval_block(
 fn eval_assign(
     lhs: &Assignable,
     rhs: &Exp,
+    annotation: Option<Type>,
     env: &Rc<RefCell<Environment>>,
 ) -> Result<Value, InterpreterError> {
     let name = lhs.name.clone();
     let subassignment = &lhs.assignable;
 
+    // the assign hook is given the value assigned, copied only if there are hooks
+    let hooks = env.borrow().runtime().hooks().cloned();
+
     // simple assignment to name (a = something),
     // no subassignment (like a[5], or a.b)
     if subassignment.is_empty() {
-        let value = eval_exp(&rhs, env)?;
+        let mut value = eval_exp(rhs, env)?;
+        if env.borrow().runtime().type_checks() {
+            check_type(&value, annotation, || format!("`{}`", name))?;
+        }
 
         // if we are binding a closure, convert to a named closure
         if let Value::Closure {
             args,
+            signature,
             block,
             environment,
             ..
         } = value
         {
-            let func_bind = Value::Closure {
+            value = Value::Closure {
                 kind: ClosureKind::Named(name.clone()),
                 args,
+                signature,
                 block,
                 environment,
             };
-            return env.borrow_mut().bind(&name, func_bind);
         }
 
-        return env.borrow_mut().bind(&name, value);
+        let assigned = hooks.as_ref().map(|_| value.clone());
+        let result = env.borrow_mut().bind(&name, value)?;
+        if let (Some(hooks), Some(assigned)) = (hooks, assigned) {
+            hooks.assign(lhs, &assigned);
+        }
+        return Ok(result);
     }
 
     // otherwise we need to recursively assign to arrays/structures
     let mut bound = env.borrow().get(&name)?;
-    let rhs = eval_exp(&rhs, env)?;
+    let rhs = eval_exp(rhs, env)?;
+    let assigned = hooks.as_ref().map(|_| rhs.clone());
 
     bound = assign_drilldown(bound, subassignment, rhs, env)?;
 
-    env.borrow_mut().bind(&name, bound)
+    let result = env.borrow_mut().bind(&name, bound)?;
+    if let (Some(hooks), Some(assigned)) = (hooks, assigned) {
+        hooks.assign(lhs, &assigned);
+    }
+    Ok(result)
 }
 
 /// recursive assignment for nested bindings.
@@ -417,7 +634,7 @@ fn assign_drilldown(
         AssignableKind::ArrayIndex { index } => {
             if let Value::Array(arr) = assign_to {
                 // compute the index to assign to
-                let index_val: f64 = eval_exp(&index, env)?.try_into()?;
+                let index_val: f64 = eval_exp(index, env)?.try_into()?;
                 let index_val = index_val as usize;
 
                 if index_val >= arr.borrow().len() {
@@ -474,7 +691,8 @@ fn eval_nest(
                 then,
                 or_else,
             } => {
-                let cond_value: f64 = eval_exp(&cond, env)?.try_into()?;
+                let cond_value: f64 = eval_exp(cond, env)?.try_into()?;
+                hook_branch(cond, cond_value as i64 != 0, env);
                 if cond_value as i64 != 0 {
                     let then_res = eval_block(then, env)?;
                     return Ok(then_res);
@@ -483,7 +701,8 @@ fn eval_nest(
                 Ok(or_else_res)
             }
             CondNestKind::If { cond, then } => {
-                let cond_value: f64 = eval_exp(&cond, env)?.try_into()?;
+                let cond_value: f64 = eval_exp(cond, env)?.try_into()?;
+                hook_branch(cond, cond_value as i64 != 0, env);
                 if cond_value as i64 != 0 {
                     let then_res = eval_block(then, env)?;
                     return Ok(then_res);
@@ -491,14 +710,16 @@ fn eval_nest(
                 Ok(None)
             }
         },
+        // every iteration is a step, even of a loop with an empty body
         NestKind::LoopNest(loopnest) => match loopnest {
             LoopNestKind::While { cond, block } => {
-                let mut while_cond: f64 = eval_exp(&cond, env)?.try_into()?;
+                let mut while_cond: f64 = eval_exp(cond, env)?.try_into()?;
                 while while_cond as i64 != 0 {
+                    env.borrow().runtime().step()?;
                     if let Some(return_result) = eval_block(block, env)? {
                         return Ok(Some(return_result));
                     }
-                    while_cond = eval_exp(&cond, env)?.try_into()?;
+                    while_cond = eval_exp(cond, env)?.try_into()?;
                 }
                 Ok(None)
             }
@@ -509,18 +730,19 @@ fn eval_nest(
                 block,
             } => {
                 eval_statement(init, env)?;
-                let mut for_cond: f64 = eval_exp(&cond, env)?.try_into()?;
+                let mut for_cond: f64 = eval_exp(cond, env)?.try_into()?;
                 while for_cond as i64 != 0 {
+                    env.borrow().runtime().step()?;
                     if let Some(return_result) = eval_block(block, env)? {
                         return Ok(Some(return_result));
                     }
                     eval_statement(adv, env)?;
-                    for_cond = eval_exp(&cond, env)?.try_into()?;
+                    for_cond = eval_exp(cond, env)?.try_into()?;
                 }
                 Ok(None)
             }
             LoopNestKind::ForIn { name, array, block } => {
-                let array = eval_exp(&array, env)?;
+                let array = eval_exp(array, env)?;
                 let vector = match array {
                     Value::Array(v) => v,
                     other => return Err(unexpected_type(other)),
@@ -528,6 +750,7 @@ fn eval_nest(
 
                 let mut index: usize = 0;
                 while index < vector.borrow().len() {
+                    env.borrow().runtime().step()?;
                     env.borrow_mut()
                         .bind(name, vector.borrow()[index].clone())?;
                     if let Some(return_result) = eval_block(block, env)? {
@@ -541,6 +764,23 @@ fn eval_nest(
     }
 }
 
+/// checks that a value has the type it was annotated with, if any.
+/// `target` describes what was annotated, for the error
+fn check_type(
+    value: &Value,
+    annotation: Option<Type>,
+    target: impl FnOnce() -> String,
+) -> Result<(), InterpreterError> {
+    match annotation {
+        Some(expected) if value.type_of() != expected => Err(InterpreterError::TypeMismatch {
+            target: target(),
+            expected,
+            got: value.type_of(),
+        }),
+        _ => Ok(()),
+    }
+}
+
 //#[track_caller]
 fn unexpected_type(value: Value) -> InterpreterError {
     //let caller = std::panic::Location::caller();
@@ -550,10 +790,71 @@ fn unexpected_type(value: Value) -> InterpreterError {
 
 impl Display for InterpreterError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self)
+        match self {
+            InterpreterError::UnboundName(name) => write!(f, "unbound name `{}`", name),
+            InterpreterError::ArgMismatch {
+                expected,
+                got,
+                callee,
+            } => {
+                let (min, max) = *expected;
+                let plural = if max == 1 { "" } else { "s" };
+                match max - min {
+                    0 => write!(f, "expected {} argument{}, got {}", max, plural, got)?,
+                    1 => write!(f, "expected {} or {} arguments, got {}", min, max, got)?,
+                    _ => write!(f, "expected {} to {} arguments, got {}", min, max, got)?,
+                }
+                match callee {
+                    Some(callee) => write!(f, " when calling `{}`", callee),
+                    None => Ok(()),
+                }
+            }
+            InterpreterError::UnexpectedType(value) => {
+                write!(f, "unexpected type of value {}", value)
+            }
+            InterpreterError::TypeMismatch {
+                target,
+                expected,
+                got,
+            } => write!(f, "expected {} to be {}, got {}", target, expected, got),
+            InterpreterError::BuiltinRebinding(name) => {
+                write!(f, "can't rebind builtin `{}`", name)
+            }
+            InterpreterError::MissingCapability { name, capability } => write!(
+                f,
+                "`{}` requires the {:?} capability, which is unavailable",
+                name, capability
+            ),
+            InterpreterError::IOError(err) => write!(f, "IO error: {}", err),
+            InterpreterError::BoundsError { index, size } => {
+                write!(f, "index {} out of bounds for length {}", index, size)
+            }
+            InterpreterError::RangeError { from, to } => {
+                write!(f, "invalid range from {} to {}", from, to)
+            }
+            InterpreterError::JsonError(err) => write!(f, "JSON error: {}", err),
+            InterpreterError::Error => write!(f, "program raised an error"),
+            InterpreterError::Exit(code) => write!(f, "program exited with code {}", code),
+            InterpreterError::StepLimit(max) => write!(f, "exceeded the limit of {} steps", max),
+            InterpreterError::DepthLimit(max) => {
+                write!(f, "exceeded the call depth limit of {}", max)
+            }
+            InterpreterError::MemoryLimit(max) => write!(
+                f,
+                "exceeded the limit of {} allocated array elements and string bytes",
+                max
+            ),
+            InterpreterError::TimeLimit(max) => {
+                write!(f, "exceeded the time limit of {:?}", max)
+            }
+            InterpreterError::Interrupted => write!(f, "program was interrupted"),
+            InterpreterError::AssertionFailed(message) => write!(f, "assertion failed: {}", message),
+        }
     }
 }
 
+impl std::error::Error for InterpreterError {}
+
 impl From<std::io::Error> for InterpreterError {
     fn from(io_err: std::io::Error) -> Self {
         InterpreterError::IOError(io_err.to_string())
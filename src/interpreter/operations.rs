@@ -74,12 +74,12 @@ pub fn infix(op: &InfixOp, lhs: Value, rhs: Value) -> Result<Value, InterpreterE
         InfixOp::And => {
             let lhs_float: f64 = lhs.try_into()?;
             let rhs_float: f64 = rhs.try_into()?;
-            Value::Num(((lhs_float.abs() > EPSILON) && (rhs_float.abs() > EPSILON)) as u32 as f64)
+            Value::Num(((lhs_float.abs() > f64::EPSILON) && (rhs_float.abs() > f64::EPSILON)) as u32 as f64)
         },
         InfixOp::Or => {
             let lhs_float: f64 = lhs.try_into()?;
             let rhs_float: f64 = rhs.try_into()?;
-            Value::Num(((lhs_float.abs() > EPSILON) || (rhs_float.abs() > EPSILON)) as u32 as f64)
+            Value::Num(((lhs_float.abs() > f64::EPSILON) || (rhs_float.abs() > f64::EPSILON)) as u32 as f64)
         },
     })
 }
@@ -2,7 +2,7 @@ use std::{cell::RefCell, rc::Rc};
 
 use crate::ast::node::Statement;
 
-use super::{InterpreterError, Value, value::Environment};
+use super::{InterpreterError, Value, value::{heap, Environment}};
 
 
 /// Repl maintains an environment for repeated evaluation of statements in the same environment
@@ -14,12 +14,23 @@ pub struct Repl {
 impl Repl {
     pub fn new() -> Repl {
         Repl {
-            environment: Rc::new(RefCell::new(Environment::new()))
+            environment: heap::environment(Environment::new())
         }
     }
 
     // evaluates a statement in the current repl environment
     pub fn repl_statement(&self, statement: &Statement) -> Result<Option<Value>, InterpreterError> {
-        super::eval_repl_statement(&statement, &self.environment)
+        super::eval_repl_statement(statement, &self.environment)
+    }
+
+    /// Returns the names bound in the repl environment starting with `prefix`, including builtins, sorted
+    pub fn complete(&self, prefix: &str) -> Vec<String> {
+        self.environment
+            .borrow()
+            .names()
+            .into_iter()
+            .filter(|name| name.starts_with(prefix))
+            .cloned()
+            .collect()
     }
 }
\ No newline at end of file
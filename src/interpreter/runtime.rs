@@ -0,0 +1,400 @@
+//! Author: Rafael Bayer (2021)
+//! The runtime module defines the `Runtime`, the host configuration and bookkeeping
+//! shared by every environment of an executing Puffin program.
+//!
+//! Hosts use the runtime to restrict the resources an untrusted program may use.
+//! Exceeding any limit stops the program with a distinct `InterpreterError`.
+//! Hosts may also choose which capabilities (groups of builtins) are available to the program,
+//! where the program's input comes from and its output goes, and seed its random numbers.
+//! Type annotations are only checked while running if the host enables it,
+//! and hosts may register `Hooks` to observe the program as it executes.
+
+use rand::{rngs::StdRng, SeedableRng};
+use std::{
+    cell::{Cell, RefCell, RefMut},
+    collections::HashSet,
+    io::{self, BufRead, Read, Write},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use super::{hooks::Hooks, InterpreterError};
+
+/// Default maximum depth of nested closure calls
+pub const DEFAULT_MAX_DEPTH: usize = 1000;
+
+/// Stack in bytes a call needs left to run its body on the current stack.
+/// With less, the body runs on a new stack of `STACK_GROWTH` bytes, so programs can recurse
+/// up to their depth limit on any thread, even when built without optimizations
+pub(crate) const STACK_RED_ZONE: usize = 1024 * 1024;
+
+/// Size in bytes of each stack added for deep calls, see `STACK_RED_ZONE`
+pub(crate) const STACK_GROWTH: usize = 16 * 1024 * 1024;
+
+/// Resource limits for a Puffin program, `None` is unlimited.
+/// By default only the depth of calls is limited, to `DEFAULT_MAX_DEPTH`,
+/// so deep recursion is an error rather than a stack overflow.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Limits {
+    /// Maximum number of steps (evaluated expressions and loop iterations)
+    pub max_steps: Option<u64>,
+    /// Maximum depth of nested closure calls
+    pub max_depth: Option<usize>,
+    /// Maximum total number of array elements, structure fields, and string bytes allocated.
+    /// This is a budget for the whole run, freeing values doesn't give any of it back
+    pub max_memory: Option<usize>,
+    /// Maximum wall time
+    pub max_time: Option<Duration>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_steps: None,
+            max_depth: Some(DEFAULT_MAX_DEPTH),
+            max_memory: None,
+            max_time: None,
+        }
+    }
+}
+
+/// Capability, a group of builtins with access to the world outside of the program
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Capability {
+    /// Console input and output
+    Console,
+    /// Random number generation
+    Random,
+    /// Filesystem access
+    Filesystem,
+    /// Current time
+    Time,
+    /// Process environment
+    Environment,
+}
+
+impl Capability {
+    /// Every capability
+    pub const ALL: [Capability; 5] = [
+        Capability::Console,
+        Capability::Random,
+        Capability::Filesystem,
+        Capability::Time,
+        Capability::Environment,
+    ];
+}
+
+/// Runtime state shared by all environments of a program
+pub struct Runtime {
+    limits: Limits,
+    capabilities: HashSet<Capability>,
+    stdin: RefCell<Box<dyn BufRead>>,
+    stdout: RefCell<Box<dyn Write>>,
+    stderr: RefCell<Box<dyn Write>>,
+    rng: RefCell<StdRng>,
+    args: Vec<String>,
+    type_checks: bool,
+    hooks: Option<Rc<dyn Hooks>>,
+    steps: Cell<u64>,
+    depth: Cell<usize>,
+    memory: Cell<usize>,
+    started: Cell<Instant>,
+}
+
+impl Default for Runtime {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Runtime {
+    /// Returns a new Runtime with the default limits, and with every capability
+    pub fn new() -> Runtime {
+        Runtime {
+            limits: Limits::default(),
+            capabilities: Capability::ALL.iter().copied().collect(),
+            stdin: RefCell::new(Box::new(StdinReader::new())),
+            stdout: RefCell::new(Box::new(io::stdout())),
+            stderr: RefCell::new(Box::new(io::stderr())),
+            rng: RefCell::new(StdRng::from_entropy()),
+            args: Vec::new(),
+            type_checks: false,
+            hooks: None,
+            steps: Cell::new(0),
+            depth: Cell::new(0),
+            memory: Cell::new(0),
+            started: Cell::new(Instant::now()),
+        }
+    }
+
+    /// Sets the resource limits of this Runtime
+    pub fn with_limits(mut self, limits: Limits) -> Runtime {
+        self.limits = limits;
+        self
+    }
+
+    /// Sets the capabilities of this Runtime, any capability not given is unavailable
+    pub fn with_capabilities<I>(mut self, capabilities: I) -> Runtime
+    where
+        I: IntoIterator<Item = Capability>,
+    {
+        self.capabilities = capabilities.into_iter().collect();
+        self
+    }
+
+    /// Sets the input read by builtins such as `input_str`
+    pub fn with_stdin<R: BufRead + 'static>(mut self, stdin: R) -> Runtime {
+        self.stdin = RefCell::new(Box::new(stdin));
+        self
+    }
+
+    /// Sets the output written to by builtins such as `print`
+    pub fn with_stdout<W: Write + 'static>(mut self, stdout: W) -> Runtime {
+        self.stdout = RefCell::new(Box::new(stdout));
+        self
+    }
+
+    /// Sets the error output written to by builtins such as `error`
+    pub fn with_stderr<W: Write + 'static>(mut self, stderr: W) -> Runtime {
+        self.stderr = RefCell::new(Box::new(stderr));
+        self
+    }
+
+    /// Seeds the random number generator, so random builtins produce the same
+    /// sequence of values on every run
+    pub fn with_seed(self, seed: u64) -> Runtime {
+        self.seed(seed);
+        self
+    }
+
+    /// Sets the command line arguments passed to the program as `ARGS`
+    pub fn with_args<I>(mut self, args: I) -> Runtime
+    where
+        I: IntoIterator<Item = String>,
+    {
+        self.args = args.into_iter().collect();
+        self
+    }
+
+    /// Enables checking that values have the types they are annotated with,
+    /// when passed to or returned from a function, or assigned to a name
+    pub fn with_type_checks(mut self, enabled: bool) -> Runtime {
+        self.type_checks = enabled;
+        self
+    }
+
+    /// Sets the hooks called as the program executes
+    pub fn with_hooks(mut self, hooks: Rc<dyn Hooks>) -> Runtime {
+        self.hooks = Some(hooks);
+        self
+    }
+
+    /// Returns the hooks of this Runtime, if any
+    pub fn hooks(&self) -> Option<&Rc<dyn Hooks>> {
+        self.hooks.as_ref()
+    }
+
+    /// Returns true if this Runtime checks type annotations
+    pub fn type_checks(&self) -> bool {
+        self.type_checks
+    }
+
+    /// Returns the resource limits of this Runtime
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// Returns true if this Runtime has the given capability
+    pub fn has_capability(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+
+    /// Returns the command line arguments passed to the program
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    /// Returns the number of steps taken so far
+    pub fn steps(&self) -> u64 {
+        self.steps.get()
+    }
+
+    /// Returns the total number of array elements, structure fields, and string bytes
+    /// allocated so far, including those already freed
+    pub fn memory(&self) -> usize {
+        self.memory.get()
+    }
+
+    /// Reads a line of input into `buf`, returning the number of bytes read
+    pub(crate) fn read_line(&self, buf: &mut String) -> io::Result<usize> {
+        self.stdin.borrow_mut().read_line(buf)
+    }
+
+    /// Writes `output` to the output
+    pub(crate) fn write_stdout(&self, output: &str) -> io::Result<()> {
+        self.stdout.borrow_mut().write_all(output.as_bytes())
+    }
+
+    /// Writes `output` to the error output
+    pub(crate) fn write_stderr(&self, output: &str) -> io::Result<()> {
+        self.stderr.borrow_mut().write_all(output.as_bytes())
+    }
+
+    /// Flushes the output, for example before waiting on input after a prompt
+    pub(crate) fn flush_stdout(&self) -> io::Result<()> {
+        self.stdout.borrow_mut().flush()
+    }
+
+    /// Reseeds the random number generator
+    pub(crate) fn seed(&self, seed: u64) {
+        *self.rng.borrow_mut() = StdRng::seed_from_u64(seed);
+    }
+
+    /// Returns the random number generator
+    pub(crate) fn rng(&self) -> RefMut<'_, StdRng> {
+        self.rng.borrow_mut()
+    }
+
+    /// Restarts the wall clock, called when a program begins executing
+    pub(crate) fn start(&self) {
+        self.started.set(Instant::now());
+    }
+
+    /// Counts a single step, checking the step and time limits
+    pub(crate) fn step(&self) -> Result<(), InterpreterError> {
+        let steps = self.steps.get() + 1;
+        self.steps.set(steps);
+
+        if let Some(max_steps) = self.limits.max_steps {
+            if steps > max_steps {
+                return Err(InterpreterError::StepLimit(max_steps));
+            }
+        }
+
+        if let Some(max_time) = self.limits.max_time {
+            if self.started.get().elapsed() > max_time {
+                return Err(InterpreterError::TimeLimit(max_time));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Enters a closure call, checking the depth limit.
+    /// Every successful call to `enter_call` must be followed by `exit_call`.
+    pub(crate) fn enter_call(&self) -> Result<(), InterpreterError> {
+        let depth = self.depth.get() + 1;
+
+        if let Some(max_depth) = self.limits.max_depth {
+            if depth > max_depth {
+                return Err(InterpreterError::DepthLimit(max_depth));
+            }
+        }
+
+        self.depth.set(depth);
+        Ok(())
+    }
+
+    /// Exits a closure call
+    pub(crate) fn exit_call(&self) {
+        self.depth.set(self.depth.get() - 1);
+    }
+
+    /// Accounts for `size` array elements, structure fields, or string bytes about to be allocated,
+    /// checking the memory limit
+    pub(crate) fn allocate(&self, size: usize) -> Result<(), InterpreterError> {
+        let memory = self.memory.get().saturating_add(size);
+
+        if let Some(max_memory) = self.limits.max_memory {
+            if memory > max_memory {
+                return Err(InterpreterError::MemoryLimit(max_memory));
+            }
+        }
+
+        self.memory.set(memory);
+        Ok(())
+    }
+}
+
+/// In-memory output shared between a host and a Runtime.
+/// Clones write to the same buffer, so a host can keep one to read what a program wrote.
+#[derive(Debug, Clone, Default)]
+pub struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl SharedBuffer {
+    /// Returns a new, empty SharedBuffer
+    pub fn new() -> SharedBuffer {
+        Self::default()
+    }
+
+    /// Returns everything written to the buffer so far
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Reader of the process stdin, through the buffer std keeps for it.
+/// Every StdinReader shares that buffer and only takes one line from it at a time,
+/// so a reader never holds input past the current line that another reader
+/// (like the debugger or the REPL) is expecting.
+#[derive(Debug, Default)]
+pub struct StdinReader {
+    line: Vec<u8>,
+    pos: usize,
+}
+
+impl StdinReader {
+    /// Returns a new StdinReader
+    pub fn new() -> StdinReader {
+        Self::default()
+    }
+}
+
+impl Read for StdinReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl BufRead for StdinReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos == self.line.len() {
+            self.line.clear();
+            self.pos = 0;
+            io::stdin().lock().read_until(b'\n', &mut self.line)?;
+        }
+        Ok(&self.line[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.line.len());
+    }
+}
+
+impl std::fmt::Debug for Runtime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<Runtime: {:?} {:?}>", self.limits, self.capabilities)
+    }
+}
+
+impl PartialEq for Runtime {
+    fn eq(&self, other: &Self) -> bool {
+        // runtimes hold execution state, so they are only equal to themselves
+        std::ptr::eq(self, other)
+    }
+}
@@ -1,21 +1,69 @@
 //! Author: Rafael Bayer (2021)
 //! The builtin module defines builtin functions and values in Puffin
 
-use rand;
+use rand::{seq::SliceRandom, Rng};
 use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
 use std::convert::TryInto;
-use std::io;
 use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
 use std::{cell::RefCell, vec};
 
-use super::{ClosureKind, Environment, Value};
-use crate::ast::node::Block;
-use crate::interpreter::{unexpected_type, InterpreterError};
+use super::{heap, json, ClosureKind, Environment, Value};
+use crate::ast::node::{Block, Signature};
+use crate::parser::Span;
+use crate::interpreter::{
+    runtime::{Capability, Runtime},
+    unexpected_type, InterpreterError,
+};
 
-/// Builtin wraps a name and a builtin function body
+/// Name of the array of command line arguments passed to the program
+pub const ARGS: &str = "ARGS";
+
+/// Builtin wraps a name and a builtin function body,
+/// with the names of its parameters, its documentation, and the capability it requires
 pub struct Builtin {
     name: &'static str,
-    pub body: fn(Vec<Value>) -> Result<Value, InterpreterError>,
+    pub body: fn(Vec<Value>, &Runtime) -> Result<Value, InterpreterError>,
+    /// optional parameters end with `?`, and `...` is any number of arguments
+    params: &'static [&'static str],
+    doc: &'static str,
+    capability: Option<Capability>,
+}
+
+impl Builtin {
+    /// Returns the name of this Builtin
+    pub fn name(&self) -> &str {
+        self.name
+    }
+
+    /// Returns the names of the parameters of this Builtin, such as `["a", "indent?"]`.
+    /// Optional parameters end with `?`, and `...` stands for any number of arguments
+    pub fn params(&self) -> &[&str] {
+        self.params
+    }
+
+    /// Returns the documentation of this Builtin
+    pub fn doc(&self) -> &str {
+        self.doc
+    }
+
+    /// Returns the minimum and maximum number of arguments this Builtin accepts,
+    /// or None if it takes any number of arguments
+    pub fn arity(&self) -> Option<(usize, usize)> {
+        if self.params.contains(&"...") {
+            return None;
+        }
+        let required = self.params.iter().filter(|param| !param.ends_with('?')).count();
+        Some((required, self.params.len()))
+    }
+
+    /// Returns the capability required to use this Builtin,
+    /// or None if it is always available
+    pub fn capability(&self) -> Option<Capability> {
+        self.capability
+    }
 }
 
 impl std::fmt::Debug for Builtin {
@@ -29,6 +77,9 @@ impl Clone for Builtin {
         Builtin {
             name: self.name,
             body: self.body,
+            params: self.params,
+            doc: self.doc,
+            capability: self.capability,
         }
     }
 }
@@ -48,12 +99,15 @@ pub fn get_builtins() -> HashMap<String, Value> {
         ("PI", Value::from(std::f64::consts::PI)),
         ("true", Value::from(1f64)),
         ("false", Value::from(0f64)),
-        ("EPSILON", Value::from(std::f64::EPSILON)),
+        ("EPSILON", Value::from(f64::EPSILON)),
         (
             "len",
             Value::from(Builtin {
                 name: "len",
                 body: builtin_len,
+                params: &["a"],
+                doc: "Returns the length of the array, string, or structure `a`",
+                capability: None,
             }),
         ),
         (
@@ -61,6 +115,9 @@ pub fn get_builtins() -> HashMap<String, Value> {
             Value::from(Builtin {
                 name: "str",
                 body: builtin_str,
+                params: &["a"],
+                doc: "Returns the string representation of `a`",
+                capability: None,
             }),
         ),
         (
@@ -68,6 +125,9 @@ pub fn get_builtins() -> HashMap<String, Value> {
             Value::from(Builtin {
                 name: "print",
                 body: builtin_print,
+                params: &["..."],
+                doc: "Prints the arguments, separated by spaces",
+                capability: Some(Capability::Console),
             }),
         ),
         (
@@ -75,6 +135,9 @@ pub fn get_builtins() -> HashMap<String, Value> {
             Value::from(Builtin {
                 name: "println",
                 body: builtin_println,
+                params: &["..."],
+                doc: "Prints the arguments, separated by spaces, followed by a newline",
+                capability: Some(Capability::Console),
             }),
         ),
         (
@@ -82,48 +145,69 @@ pub fn get_builtins() -> HashMap<String, Value> {
             Value::from(Builtin {
                 name: "error",
                 body: builtin_error,
+                params: &["..."],
+                doc: "Prints the arguments to stderr, and fails with an error",
+                capability: Some(Capability::Console),
             }),
         ),
         (
             "sin",
             Value::Builtin(Builtin {
                 name: "sin",
-                body: |v| builtin_floatops(v, f64::sin),
+                body: |v, _| builtin_floatops(v, f64::sin),
+                params: &["a"],
+                doc: "Returns the sine of `a`",
+                capability: None,
             }),
         ),
         (
             "cos",
             Value::Builtin(Builtin {
                 name: "cos",
-                body: |v| builtin_floatops(v, f64::cos),
+                body: |v, _| builtin_floatops(v, f64::cos),
+                params: &["a"],
+                doc: "Returns the cosine of `a`",
+                capability: None,
             }),
         ),
         (
             "tan",
             Value::Builtin(Builtin {
                 name: "tan",
-                body: |v| builtin_floatops(v, f64::tan),
+                body: |v, _| builtin_floatops(v, f64::tan),
+                params: &["a"],
+                doc: "Returns the tangent of `a`",
+                capability: None,
             }),
         ),
         (
             "sqrt",
             Value::Builtin(Builtin {
                 name: "sqrt",
-                body: |v| builtin_floatops(v, f64::sqrt),
+                body: |v, _| builtin_floatops(v, f64::sqrt),
+                params: &["a"],
+                doc: "Returns the square root of `a`",
+                capability: None,
             }),
         ),
         (
             "abs",
             Value::Builtin(Builtin {
                 name: "abs",
-                body: |v| builtin_floatops(v, f64::abs),
+                body: |v, _| builtin_floatops(v, f64::abs),
+                params: &["a"],
+                doc: "Returns the absolute value of `a`",
+                capability: None,
             }),
         ),
         (
             "round",
             Value::Builtin(Builtin {
                 name: "round",
-                body: |v| builtin_floatops(v, f64::round),
+                body: |v, _| builtin_floatops(v, f64::round),
+                params: &["a"],
+                doc: "Returns `a` rounded to the nearest integer",
+                capability: None,
             }),
         ),
         (
@@ -131,20 +215,29 @@ pub fn get_builtins() -> HashMap<String, Value> {
             Value::Builtin(Builtin {
                 name: "pow",
                 body: builtin_pow,
+                params: &["a", "b"],
+                doc: "Returns `a` to the power of `b`",
+                capability: None,
             }),
         ),
         (
             "input_str",
             Value::Builtin(Builtin {
                 name: "input_str",
-                body: |v| builtin_input(v, InputType::String),
+                body: |v, rt| builtin_input(v, rt, InputType::String),
+                params: &["..."],
+                doc: "Prints the arguments as a prompt, and returns the next line of input",
+                capability: Some(Capability::Console),
             }),
         ),
         (
             "input_num",
             Value::Builtin(Builtin {
                 name: "input_num",
-                body: |v| builtin_input(v, InputType::Num),
+                body: |v, rt| builtin_input(v, rt, InputType::Num),
+                params: &["..."],
+                doc: "Prints the arguments as a prompt, and returns the next line of input as a number",
+                capability: Some(Capability::Console),
             }),
         ),
         (
@@ -152,6 +245,9 @@ pub fn get_builtins() -> HashMap<String, Value> {
             Value::Builtin(Builtin {
                 name: "push",
                 body: builtin_push,
+                params: &["a", "b"],
+                doc: "Pushes `b` onto the end of the array `a`",
+                capability: None,
             }),
         ),
         (
@@ -159,6 +255,9 @@ pub fn get_builtins() -> HashMap<String, Value> {
             Value::Builtin(Builtin {
                 name: "pop",
                 body: builtin_pop,
+                params: &["a"],
+                doc: "Removes and returns the last element of the array `a`",
+                capability: None,
             }),
         ),
         (
@@ -166,6 +265,9 @@ pub fn get_builtins() -> HashMap<String, Value> {
             Value::Builtin(Builtin {
                 name: "remove",
                 body: builtin_remove,
+                params: &["a", "i"],
+                doc: "Removes and returns the element at index `i` of the array `a`",
+                capability: None,
             }),
         ),
         (
@@ -173,6 +275,9 @@ pub fn get_builtins() -> HashMap<String, Value> {
             Value::Builtin(Builtin {
                 name: "insert",
                 body: builtin_insert,
+                params: &["a", "i", "v"],
+                doc: "Inserts `v` into the array `a` at index `i`",
+                capability: None,
             }),
         ),
         (
@@ -180,49 +285,261 @@ pub fn get_builtins() -> HashMap<String, Value> {
             Value::Builtin(Builtin {
                 name: "rand",
                 body: builtin_rand,
+                params: &[],
+                doc: "Returns a uniformly distributed random number between 0 and 1",
+                capability: Some(Capability::Random),
             }),
         ),
         (
-            "typeof",
+            "seed",
             Value::Builtin(Builtin {
-                name: "typeof",
-                body: builtin_typeof,
+                name: "seed",
+                body: builtin_seed,
+                params: &["n"],
+                doc: "Seeds the random number generator, so the random builtins return the same values on every run",
+                capability: Some(Capability::Random),
+            }),
+        ),
+        (
+            "rand_int",
+            Value::Builtin(Builtin {
+                name: "rand_int",
+                body: builtin_rand_int,
+                params: &["lo", "hi"],
+                doc: "Returns a uniformly distributed random integer between `lo` and `hi`, inclusive",
+                capability: Some(Capability::Random),
+            }),
+        ),
+        (
+            "choice",
+            Value::Builtin(Builtin {
+                name: "choice",
+                body: builtin_choice,
+                params: &["a"],
+                doc: "Returns a random element of the array `a`",
+                capability: Some(Capability::Random),
             }),
         ),
-        // type consts
-        ("NULL", builtin_typeof(vec![Value::Null]).unwrap()),
-        ("NUM", builtin_typeof(vec![Value::Num(0f64)]).unwrap()),
         (
-            "STRING",
-            builtin_typeof(vec![Value::String(String::new())]).unwrap(),
+            "shuffle",
+            Value::Builtin(Builtin {
+                name: "shuffle",
+                body: builtin_shuffle,
+                params: &["a"],
+                doc: "Shuffles the array `a` in place, returning `a`",
+                capability: Some(Capability::Random),
+            }),
         ),
-        ("ARRAY", builtin_typeof(vec![Value::from(vec![])]).unwrap()),
         (
-            "STRUCT",
-            builtin_typeof(vec![Value::from(HashMap::new())]).unwrap(),
+            "time",
+            Value::Builtin(Builtin {
+                name: "time",
+                body: builtin_time,
+                params: &[],
+                doc: "Returns the number of seconds since the unix epoch",
+                capability: Some(Capability::Time),
+            }),
+        ),
+        (
+            "read_file",
+            Value::Builtin(Builtin {
+                name: "read_file",
+                body: builtin_read_file,
+                params: &["path"],
+                doc: "Returns the contents of the file at `path`",
+                capability: Some(Capability::Filesystem),
+            }),
+        ),
+        (
+            "write_file",
+            Value::Builtin(Builtin {
+                name: "write_file",
+                body: builtin_write_file,
+                params: &["path", "s"],
+                doc: "Writes `s` to the file at `path`",
+                capability: Some(Capability::Filesystem),
+            }),
+        ),
+        (
+            "append_file",
+            Value::Builtin(Builtin {
+                name: "append_file",
+                body: builtin_append_file,
+                params: &["path", "s"],
+                doc: "Appends `s` to the file at `path`",
+                capability: Some(Capability::Filesystem),
+            }),
+        ),
+        (
+            "read_lines",
+            Value::Builtin(Builtin {
+                name: "read_lines",
+                body: builtin_read_lines,
+                params: &["path"],
+                doc: "Returns the lines of the file at `path`",
+                capability: Some(Capability::Filesystem),
+            }),
+        ),
+        (
+            "exists",
+            Value::Builtin(Builtin {
+                name: "exists",
+                body: builtin_exists,
+                params: &["path"],
+                doc: "Returns true if a file or directory exists at `path`",
+                capability: Some(Capability::Filesystem),
+            }),
+        ),
+        (
+            "list_dir",
+            Value::Builtin(Builtin {
+                name: "list_dir",
+                body: builtin_list_dir,
+                params: &["path"],
+                doc: "Returns the sorted names of the entries of the directory at `path`",
+                capability: Some(Capability::Filesystem),
+            }),
+        ),
+        (
+            "remove_file",
+            Value::Builtin(Builtin {
+                name: "remove_file",
+                body: builtin_remove_file,
+                params: &["path"],
+                doc: "Removes the file at `path`",
+                capability: Some(Capability::Filesystem),
+            }),
+        ),
+        (
+            "mkdir",
+            Value::Builtin(Builtin {
+                name: "mkdir",
+                body: builtin_mkdir,
+                params: &["path"],
+                doc: "Creates a directory at `path`, along with any missing parents",
+                capability: Some(Capability::Filesystem),
+            }),
+        ),
+        (
+            "json_parse",
+            Value::Builtin(Builtin {
+                name: "json_parse",
+                body: builtin_json_parse,
+                params: &["s"],
+                doc: "Parses the JSON string `s`",
+                capability: None,
+            }),
+        ),
+        (
+            "json_stringify",
+            Value::Builtin(Builtin {
+                name: "json_stringify",
+                body: builtin_json_stringify,
+                params: &["a", "indent?"],
+                doc: "Returns `a` as a JSON string, pretty printed if `indent` is given",
+                capability: None,
+            }),
+        ),
+        (
+            "env",
+            Value::Builtin(Builtin {
+                name: "env",
+                body: builtin_env,
+                params: &["name"],
+                doc: "Returns the value of the environment variable `name`, or null if it isn't set",
+                capability: Some(Capability::Environment),
+            }),
+        ),
+        (
+            "exit",
+            Value::Builtin(Builtin {
+                name: "exit",
+                body: builtin_exit,
+                params: &["code"],
+                doc: "Ends the program with the exit code `code`",
+                capability: None,
+            }),
+        ),
+        (
+            "assert",
+            Value::Builtin(Builtin {
+                name: "assert",
+                body: builtin_assert,
+                params: &["a", "message?"],
+                doc: "Fails with an assertion error unless `a` is true",
+                capability: None,
+            }),
+        ),
+        (
+            "assert_eq",
+            Value::Builtin(Builtin {
+                name: "assert_eq",
+                body: builtin_assert_eq,
+                params: &["a", "b", "message?"],
+                doc: "Fails with an assertion error unless `a == b`",
+                capability: None,
+            }),
+        ),
+        (
+            "assert_throws",
+            Value::Builtin(Builtin {
+                name: "assert_throws",
+                body: builtin_assert_throws,
+                params: &["f", "message?"],
+                doc: "Calls `f`, failing with an assertion error unless it raises an error. Returns the message of the error",
+                capability: None,
+            }),
+        ),
+        (
+            "gc",
+            Value::Builtin(Builtin {
+                name: "gc",
+                body: builtin_gc,
+                params: &[],
+                doc: "Collects unreachable reference cycles, returning the number of values freed",
+                capability: None,
+            }),
+        ),
+        (
+            "typeof",
+            Value::Builtin(Builtin {
+                name: "typeof",
+                body: builtin_typeof,
+                params: &["a"],
+                doc: "Returns the type of `a`",
+                capability: None,
+            }),
         ),
+        // type consts
+        ("NULL", type_of(&Value::Null)),
+        ("NUM", type_of(&Value::Num(0f64))),
+        ("STRING", type_of(&Value::String(String::new()))),
+        ("ARRAY", type_of(&Value::from(vec![]))),
+        ("STRUCT", type_of(&Value::from(HashMap::new()))),
         (
             "CLOSURE",
-            builtin_typeof(vec![Value::Closure {
+            type_of(&Value::Closure {
                 kind: ClosureKind::Anonymous,
                 args: vec![],
-                block: Block { block: vec![] },
-                environment: Rc::new(RefCell::new(Environment::empty())),
-            }])
-            .unwrap(),
+                signature: Signature::default(),
+                block: Block {
+                    block: vec![],
+                    span: Span::default(),
+                },
+                environment: heap::environment(Environment::empty()),
+            }),
         ),
         (
             "BUILTIN",
-            builtin_typeof(vec![Value::Builtin(Builtin {
+            type_of(&Value::Builtin(Builtin {
                 name: "builtin",
-                body: |_| Ok(Value::Null),
-            })])
-            .unwrap(),
-        ),
-        (
-            "TYPE",
-            builtin_typeof(vec![Value::Type(String::new())]).unwrap(),
+                body: |_, _| Ok(Value::Null),
+                params: &[],
+                doc: "",
+                capability: None,
+            })),
         ),
+        ("TYPE", type_of(&Value::Type(String::new()))),
     ];
     builtins
         .into_iter()
@@ -231,13 +548,14 @@ pub fn get_builtins() -> HashMap<String, Value> {
 }
 
 /// converts `a` into a string
-fn builtin_str(v: Vec<Value>) -> Result<Value, InterpreterError> {
-    let arg = get_one(v)?;
-    Ok(Value::String(arg.to_string()))
+fn builtin_str(v: Vec<Value>, runtime: &Runtime) -> Result<Value, InterpreterError> {
+    let string = get_one(v)?.to_string();
+    runtime.allocate(string.len())?;
+    Ok(Value::String(string))
 }
 
 /// Returns the length of a string, array, or structure
-fn builtin_len(v: Vec<Value>) -> Result<Value, InterpreterError> {
+fn builtin_len(v: Vec<Value>, _: &Runtime) -> Result<Value, InterpreterError> {
     let arg = get_one(v)?;
     match arg {
         Value::String(s) => Ok(Value::from(s.len() as f64)),
@@ -248,36 +566,36 @@ fn builtin_len(v: Vec<Value>) -> Result<Value, InterpreterError> {
 }
 
 /// prints args
-fn builtin_print(v: Vec<Value>) -> Result<Value, InterpreterError> {
-    output(v, |e| print!("{} ", e));
+fn builtin_print(v: Vec<Value>, runtime: &Runtime) -> Result<Value, InterpreterError> {
+    runtime.write_stdout(&format!("{} ", output(v)))?;
     Ok(Value::Null)
 }
 
-/// v:
-fn builtin_println(v: Vec<Value>) -> Result<Value, InterpreterError> {
-    output(v, |e| println!("{}", e));
+/// prints args, followed by a newline
+fn builtin_println(v: Vec<Value>, runtime: &Runtime) -> Result<Value, InterpreterError> {
+    runtime.write_stdout(&format!("{}\n", output(v)))?;
     Ok(Value::Null)
 }
 
 /// printlns args to stderr, and returns an InterpreterError
-fn builtin_error(v: Vec<Value>) -> Result<Value, InterpreterError> {
-    output(v, |e| eprintln!("ERR: {} ", e));
+fn builtin_error(v: Vec<Value>, runtime: &Runtime) -> Result<Value, InterpreterError> {
+    runtime.write_stderr(&format!("ERR: {} \n", output(v)))?;
     Err(InterpreterError::Error)
 }
 
-fn output<F>(v: Vec<Value>, f: F)
-where
-    F: Fn(String),
-{
-    f(v.iter()
-        .map(|e| match e {
-            // special case for printing strings, don't include quotes.
-            // quotes are only included when string is part of another structure
-            Value::String(inner) => inner.clone(),
-            other => other.to_string(),
-        })
-        .collect::<Vec<String>>()
-        .join(" "));
+/// joins args with spaces for output
+fn output(v: Vec<Value>) -> String {
+    v.iter().map(text).collect::<Vec<String>>().join(" ")
+}
+
+/// converts a value to text for output
+fn text(value: &Value) -> String {
+    match value {
+        // special case for printing strings, don't include quotes.
+        // quotes are only included when string is part of another structure
+        Value::String(inner) => inner.clone(),
+        other => other.to_string(),
+    }
 }
 
 /// Used to create single argument math builtins:
@@ -291,7 +609,7 @@ where
     Ok(Value::from(f(float)))
 }
 
-fn builtin_pow(mut v: Vec<Value>) -> Result<Value, InterpreterError> {
+fn builtin_pow(mut v: Vec<Value>, _: &Runtime) -> Result<Value, InterpreterError> {
     expect_args(2, &v)?;
     let exp: f64 = v.pop().unwrap().try_into()?;
     let base: f64 = v.pop().unwrap().try_into()?;
@@ -305,18 +623,21 @@ enum InputType {
 }
 
 /// Used to create builtins
-fn builtin_input(v: Vec<Value>, input_type: InputType) -> Result<Value, InterpreterError> {
+fn builtin_input(v: Vec<Value>, runtime: &Runtime, input_type: InputType) -> Result<Value, InterpreterError> {
     // print any args as a prompt
-    builtin_print(v)?;
+    builtin_print(v, runtime)?;
     // flush stdout so prompt appears first
-    io::Write::flush(&mut io::stdout())?;
+    runtime.flush_stdout()?;
 
     let mut buf = String::new();
-    io::stdin().read_line(&mut buf)?;
+    runtime.read_line(&mut buf)?;
     buf = buf.trim_end().to_string();
 
     Ok(match input_type {
-        InputType::String => Value::String(buf),
+        InputType::String => {
+            runtime.allocate(buf.len())?;
+            Value::String(buf)
+        }
         InputType::Num => {
             let parsed: f64 = if let Ok(n) = buf.parse() {
                 n
@@ -332,8 +653,9 @@ fn builtin_input(v: Vec<Value>, input_type: InputType) -> Result<Value, Interpre
 }
 
 /// Push `b` onto array `a`
-fn builtin_push(mut v: Vec<Value>) -> Result<Value, InterpreterError> {
+fn builtin_push(mut v: Vec<Value>, runtime: &Runtime) -> Result<Value, InterpreterError> {
     expect_args(2, &v)?;
+    runtime.allocate(1)?;
 
     let value = v.pop().unwrap();
     let array: Rc<RefCell<Vec<Value>>> = v.pop().unwrap().try_into()?;
@@ -342,9 +664,9 @@ fn builtin_push(mut v: Vec<Value>) -> Result<Value, InterpreterError> {
 }
 
 /// Pop from array `a`
-fn builtin_pop(v: Vec<Value>) -> Result<Value, InterpreterError> {
+fn builtin_pop(v: Vec<Value>, _: &Runtime) -> Result<Value, InterpreterError> {
     let array: Rc<RefCell<Vec<Value>>> = get_one(v)?.try_into()?;
-    if array.borrow().len() == 0 {
+    if array.borrow().is_empty() {
         return Err(InterpreterError::BoundsError { index: 0, size: 0 });
     }
     let removed = array.borrow_mut().pop().unwrap();
@@ -352,7 +674,7 @@ fn builtin_pop(v: Vec<Value>) -> Result<Value, InterpreterError> {
 }
 
 /// Remove from array `a` at index `i`
-fn builtin_remove(mut v: Vec<Value>) -> Result<Value, InterpreterError> {
+fn builtin_remove(mut v: Vec<Value>, _: &Runtime) -> Result<Value, InterpreterError> {
     expect_args(2, &v)?;
 
     let index_float: f64 = v.pop().unwrap().try_into()?;
@@ -370,8 +692,9 @@ fn builtin_remove(mut v: Vec<Value>) -> Result<Value, InterpreterError> {
 }
 
 /// inserts element `v` at index `i` in array `a`
-fn builtin_insert(mut v: Vec<Value>) -> Result<Value, InterpreterError> {
+fn builtin_insert(mut v: Vec<Value>, runtime: &Runtime) -> Result<Value, InterpreterError> {
     expect_args(3, &v)?;
+    runtime.allocate(1)?;
 
     let value = v.pop().unwrap();
 
@@ -393,32 +716,285 @@ fn builtin_insert(mut v: Vec<Value>) -> Result<Value, InterpreterError> {
 }
 
 /// Return a random number in [0, 1)
-fn builtin_rand(v: Vec<Value>) -> Result<Value, InterpreterError> {
+fn builtin_rand(v: Vec<Value>, runtime: &Runtime) -> Result<Value, InterpreterError> {
+    expect_args(0, &v)?;
+    Ok(Value::Num(runtime.rng().gen()))
+}
+
+/// Seeds the random number generator with `n`,
+/// all random builtins produce the same sequence of values after the same seed
+fn builtin_seed(v: Vec<Value>, runtime: &Runtime) -> Result<Value, InterpreterError> {
+    let seed: f64 = get_one(v)?.try_into()?;
+    runtime.seed(seed as i64 as u64);
+    Ok(Value::Null)
+}
+
+/// Returns a uniformly distributed random integer in [lo, hi]
+fn builtin_rand_int(mut v: Vec<Value>, runtime: &Runtime) -> Result<Value, InterpreterError> {
+    expect_args(2, &v)?;
+
+    let hi: f64 = v.pop().unwrap().try_into()?;
+    let lo: f64 = v.pop().unwrap().try_into()?;
+    let (lo, hi) = (lo as i64, hi as i64);
+    if lo > hi {
+        return Err(InterpreterError::RangeError {
+            from: lo as i128,
+            to: hi as i128,
+        });
+    }
+
+    Ok(Value::from(runtime.rng().gen_range(lo..=hi) as f64))
+}
+
+/// Returns a random element of array `a`
+fn builtin_choice(v: Vec<Value>, runtime: &Runtime) -> Result<Value, InterpreterError> {
+    let array: Rc<RefCell<Vec<Value>>> = get_one(v)?.try_into()?;
+    let array = array.borrow();
+    match array.choose(&mut *runtime.rng()) {
+        Some(element) => Ok(element.clone()),
+        None => Err(InterpreterError::BoundsError { index: 0, size: 0 }),
+    }
+}
+
+/// Shuffles array `a` in place, returning `a`
+fn builtin_shuffle(v: Vec<Value>, runtime: &Runtime) -> Result<Value, InterpreterError> {
+    let array: Rc<RefCell<Vec<Value>>> = get_one(v)?.try_into()?;
+    array.borrow_mut().shuffle(&mut *runtime.rng());
+    Ok(Value::Array(array))
+}
+
+/// Returns the number of seconds since the unix epoch
+fn builtin_time(v: Vec<Value>, _: &Runtime) -> Result<Value, InterpreterError> {
     expect_args(0, &v)?;
-    Ok(Value::Num(rand::random()))
-}
-
-fn builtin_typeof(v: Vec<Value>) -> Result<Value, InterpreterError> {
-    Ok(Value::Type(
-        (match get_one(v)? {
-            Value::Null => "null",
-            Value::Num(_) => "num",
-            Value::String(_) => "string",
-            Value::Array(_) => "array",
-            Value::Structure(_) => "struct",
-            Value::Closure {
-                kind: _,
-                args: _,
-                block: _,
-                environment: _,
-            } => "closure",
-            Value::Builtin(_) => "builtin",
-            Value::Type(_) => "type",
-        })
-        .into(),
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    Ok(Value::from(now.as_secs_f64()))
+}
+
+/// Returns the contents of the file at `path` as a string
+fn builtin_read_file(v: Vec<Value>, runtime: &Runtime) -> Result<Value, InterpreterError> {
+    let path: String = get_one(v)?.try_into()?;
+    let contents = fs::read_to_string(path)?;
+    runtime.allocate(contents.len())?;
+    Ok(Value::String(contents))
+}
+
+/// Writes `contents` to the file at `path`, replacing the file if it exists
+fn builtin_write_file(mut v: Vec<Value>, _: &Runtime) -> Result<Value, InterpreterError> {
+    expect_args(2, &v)?;
+    let contents = text(&v.pop().unwrap());
+    let path: String = v.pop().unwrap().try_into()?;
+    fs::write(path, contents)?;
+    Ok(Value::Null)
+}
+
+/// Appends `contents` to the file at `path`, creating the file if it doesn't exist
+fn builtin_append_file(mut v: Vec<Value>, _: &Runtime) -> Result<Value, InterpreterError> {
+    expect_args(2, &v)?;
+    let contents = text(&v.pop().unwrap());
+    let path: String = v.pop().unwrap().try_into()?;
+    fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(contents.as_bytes())?;
+    Ok(Value::Null)
+}
+
+/// Returns the lines of the file at `path` as an array of strings
+fn builtin_read_lines(v: Vec<Value>, runtime: &Runtime) -> Result<Value, InterpreterError> {
+    let path: String = get_one(v)?.try_into()?;
+    let contents = fs::read_to_string(path)?;
+    let lines: Vec<Value> = contents
+        .lines()
+        .map(|line| Value::String(line.to_string()))
+        .collect();
+    runtime.allocate(contents.len() + lines.len())?;
+    Ok(Value::from(lines))
+}
+
+/// Returns true if a file or directory exists at `path`
+fn builtin_exists(v: Vec<Value>, _: &Runtime) -> Result<Value, InterpreterError> {
+    let path: String = get_one(v)?.try_into()?;
+    Ok(Value::from(std::path::Path::new(&path).exists()))
+}
+
+/// Returns the sorted names of the entries of the directory at `path`
+fn builtin_list_dir(v: Vec<Value>, runtime: &Runtime) -> Result<Value, InterpreterError> {
+    let path: String = get_one(v)?.try_into()?;
+    let mut names = Vec::new();
+    for entry in fs::read_dir(path)? {
+        names.push(entry?.file_name().to_string_lossy().into_owned());
+    }
+    names.sort();
+
+    runtime.allocate(names.len())?;
+    Ok(Value::from(
+        names.into_iter().map(Value::String).collect::<Vec<Value>>(),
     ))
 }
 
+/// Removes the file at `path`
+fn builtin_remove_file(v: Vec<Value>, _: &Runtime) -> Result<Value, InterpreterError> {
+    let path: String = get_one(v)?.try_into()?;
+    fs::remove_file(path)?;
+    Ok(Value::Null)
+}
+
+/// Creates a directory at `path`, along with any missing parent directories
+fn builtin_mkdir(v: Vec<Value>, _: &Runtime) -> Result<Value, InterpreterError> {
+    let path: String = get_one(v)?.try_into()?;
+    fs::create_dir_all(path)?;
+    Ok(Value::Null)
+}
+
+/// Parses JSON string `s` into a value
+fn builtin_json_parse(v: Vec<Value>, runtime: &Runtime) -> Result<Value, InterpreterError> {
+    let text: String = get_one(v)?.try_into()?;
+    runtime.allocate(text.len())?;
+    json::parse(&text)
+}
+
+/// Returns value `a` as a JSON string, pretty printed if an indent is given
+fn builtin_json_stringify(mut v: Vec<Value>, runtime: &Runtime) -> Result<Value, InterpreterError> {
+    let indent = match v.len() {
+        1 => 0,
+        2 => {
+            let indent: f64 = v.pop().unwrap().try_into()?;
+            indent as usize
+        }
+        got => {
+            return Err(InterpreterError::ArgMismatch {
+                expected: (1, 2),
+                got,
+                callee: None,
+            })
+        }
+    };
+
+    let text = json::stringify(&v.pop().unwrap(), indent)?;
+    runtime.allocate(text.len())?;
+    Ok(Value::String(text))
+}
+
+/// Returns the value of the environment variable `name`, or null if it isn't set
+fn builtin_env(v: Vec<Value>, runtime: &Runtime) -> Result<Value, InterpreterError> {
+    let name: String = get_one(v)?.try_into()?;
+    Ok(match std::env::var(name) {
+        Ok(value) => {
+            runtime.allocate(value.len())?;
+            Value::String(value)
+        }
+        Err(_) => Value::Null,
+    })
+}
+
+/// Ends the program with exit code `code`
+fn builtin_exit(v: Vec<Value>, _: &Runtime) -> Result<Value, InterpreterError> {
+    let code: f64 = get_one(v)?.try_into()?;
+    Err(InterpreterError::Exit(code as i32))
+}
+
+/// Fails unless `cond` is true, with an optional message
+fn builtin_assert(mut v: Vec<Value>, _: &Runtime) -> Result<Value, InterpreterError> {
+    let message = assertion_message(&mut v, 1)?;
+    let cond: f64 = v.pop().unwrap().try_into()?;
+    if cond as i64 != 0 {
+        return Ok(Value::Null);
+    }
+    Err(InterpreterError::AssertionFailed(
+        message.unwrap_or_else(|| "condition was false".to_string()),
+    ))
+}
+
+/// Fails unless `a` equals `b`, with an optional message
+fn builtin_assert_eq(mut v: Vec<Value>, _: &Runtime) -> Result<Value, InterpreterError> {
+    let message = assertion_message(&mut v, 2)?;
+    let b = v.pop().unwrap();
+    let a = v.pop().unwrap();
+    if a == b {
+        return Ok(Value::Null);
+    }
+    let detail = format!("{} != {}", a, b);
+    Err(InterpreterError::AssertionFailed(match message {
+        Some(message) => format!("{} ({})", message, detail),
+        None => detail,
+    }))
+}
+
+/// Calls `f` without arguments, failing unless it raises an error, with an optional message.
+/// Returns the message of the error
+fn builtin_assert_throws(mut v: Vec<Value>, runtime: &Runtime) -> Result<Value, InterpreterError> {
+    let message = assertion_message(&mut v, 1)?;
+    let f = v.pop().unwrap();
+    // `f` must be callable without arguments, or calling it would always fail
+    match &f {
+        Value::Closure { kind, args, .. } if !args.is_empty() => {
+            let callee = match kind {
+                ClosureKind::Named(name) => name.clone(),
+                _ => "<anonymous>".to_string(),
+            };
+            return Err(InterpreterError::ArgMismatch {
+                expected: (args.len(), args.len()),
+                got: 0,
+                callee: Some(callee),
+            });
+        }
+        Value::Closure { .. } | Value::Builtin(_) => {}
+        other => return Err(unexpected_type(other.clone())),
+    }
+    match crate::interpreter::call(&f, vec![], runtime) {
+        Ok(_) => {
+            let detail = "expected an error".to_string();
+            Err(InterpreterError::AssertionFailed(match message {
+                Some(message) => format!("{} ({})", message, detail),
+                None => detail,
+            }))
+        }
+        // exits, limits, and interruptions end the program, they aren't errors of `f`
+        Err(
+            err @ InterpreterError::Exit(_)
+            | err @ InterpreterError::StepLimit(_)
+            | err @ InterpreterError::DepthLimit(_)
+            | err @ InterpreterError::MemoryLimit(_)
+            | err @ InterpreterError::TimeLimit(_)
+            | err @ InterpreterError::Interrupted,
+        ) => Err(err),
+        Err(err) => Ok(Value::String(err.to_string())),
+    }
+}
+
+/// Takes the optional message after the `n` arguments of an assertion
+fn assertion_message(v: &mut Vec<Value>, n: usize) -> Result<Option<String>, InterpreterError> {
+    if v.len() == n + 1 {
+        return Ok(Some(text(&v.pop().unwrap())));
+    }
+    if v.len() != n {
+        return Err(InterpreterError::ArgMismatch {
+            expected: (n, n + 1),
+            got: v.len(),
+            callee: None,
+        });
+    }
+    Ok(None)
+}
+
+/// Collects unreachable reference cycles, returning the number of containers freed
+fn builtin_gc(v: Vec<Value>, _: &Runtime) -> Result<Value, InterpreterError> {
+    expect_args(0, &v)?;
+    Ok(Value::from(heap::collect() as f64))
+}
+
+fn builtin_typeof(v: Vec<Value>, _: &Runtime) -> Result<Value, InterpreterError> {
+    Ok(type_of(&get_one(v)?))
+}
+
+/// Returns the type of a value
+fn type_of(value: &Value) -> Value {
+    Value::Type(value.type_of().name().into())
+}
+
 /// Gets exactly 1 argument from v
 #[inline]
 fn get_one(mut v: Vec<Value>) -> Result<Value, InterpreterError> {
@@ -432,8 +1008,9 @@ fn get_one(mut v: Vec<Value>) -> Result<Value, InterpreterError> {
 fn expect_args<T>(n: usize, v: &[T]) -> Result<(), InterpreterError> {
     if v.len() != n {
         return Err(InterpreterError::ArgMismatch {
-            expected: n,
+            expected: (n, n),
             got: v.len(),
+            callee: None,
         });
     }
 
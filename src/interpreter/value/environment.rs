@@ -9,6 +9,7 @@ use std::{
 };
 
 use super::{builtin, InterpreterError, Value};
+use crate::interpreter::runtime::{Capability, Runtime};
 
 /// Environment maps between names and values
 #[derive(Debug, Clone, PartialEq)]
@@ -19,6 +20,10 @@ pub struct Environment {
     bindings: HashMap<String, Value>,
     // builtin names, can't be rebound
     builtins: HashSet<String>,
+    // builtins left out because their capability is unavailable
+    disabled: HashMap<String, Capability>,
+    // runtime shared by all environments of a program
+    runtime: Rc<Runtime>,
 }
 
 impl Default for Environment {
@@ -34,18 +39,44 @@ impl Environment {
             parent: None,
             bindings: HashMap::new(),
             builtins: HashSet::new(),
+            disabled: HashMap::new(),
+            runtime: Rc::new(Runtime::new()),
         }
     }
 
     /// Returns a new Environment, filling it with Builtin values
     pub fn new() -> Environment {
+        Self::with_runtime(Rc::new(Runtime::new()))
+    }
+
+    /// Returns a new Environment for a given Runtime, filling it with the Builtin values
+    /// allowed by the Runtime's capabilities, and the Runtime's `ARGS`
+    pub fn with_runtime(runtime: Rc<Runtime>) -> Environment {
         // get_builtins and the builtins hashset should probably both be static/lazy & cached
-        let bindings = builtin::get_builtins();
+        let mut bindings = builtin::get_builtins();
+        let args: Vec<Value> = runtime.args().iter().cloned().map(Value::String).collect();
+        bindings.insert(builtin::ARGS.to_string(), Value::from(args));
+        // disabled builtins remain reserved names
         let builtins = bindings.keys().cloned().collect();
+
+        let mut disabled = HashMap::new();
+        bindings.retain(|name, value| match value {
+            Value::Builtin(builtin) => match builtin.capability() {
+                Some(capability) if !runtime.has_capability(capability) => {
+                    disabled.insert(name.clone(), capability);
+                    false
+                }
+                _ => true,
+            },
+            _ => true,
+        });
+
         Environment {
             parent: None,
             bindings,
             builtins,
+            disabled,
+            runtime,
         }
     }
 
@@ -55,9 +86,16 @@ impl Environment {
             parent: Some(parent.clone()),
             bindings: HashMap::new(),
             builtins: HashSet::new(),
+            disabled: HashMap::new(),
+            runtime: parent.borrow().runtime.clone(),
         }
     }
 
+    /// Returns the Runtime of this Environment
+    pub fn runtime(&self) -> &Rc<Runtime> {
+        &self.runtime
+    }
+
     /// Binds a name to a value.
     /// Returns InterpreterError::BuiltinRebinding if name is used by a Builtin.
     pub fn bind(&mut self, name: &str, value: Value) -> Result<Value, InterpreterError> {
@@ -75,15 +113,58 @@ impl Environment {
         Ok(Value::Null)
     }
 
+    /// Returns the parent of this Environment, if any
+    pub fn parent(&self) -> Option<&Rc<RefCell<Environment>>> {
+        self.parent.as_ref()
+    }
+
+    /// Returns an iterator over the values bound locally in this Environment
+    pub fn values(&self) -> impl Iterator<Item = &Value> {
+        self.bindings.values()
+    }
+
+    /// Returns the names and values bound locally in this Environment by the program,
+    /// leaving out Builtins, sorted by name
+    pub fn variables(&self) -> Vec<(&String, &Value)> {
+        let mut variables: Vec<(&String, &Value)> = self
+            .bindings
+            .iter()
+            .filter(|(name, _)| !self.builtins.contains(*name))
+            .collect();
+        variables.sort_by(|a, b| a.0.cmp(b.0));
+        variables
+    }
+
+    /// Returns the names bound locally in this Environment, including builtins, sorted by name
+    pub fn names(&self) -> Vec<&String> {
+        let mut names: Vec<&String> = self.bindings.keys().collect();
+        names.sort();
+        names
+    }
+
+    /// Removes the parent and all local bindings from this Environment, returning them.
+    /// Used by the heap to break reference cycles through environments.
+    pub(super) fn clear(&mut self) -> (Option<Rc<RefCell<Environment>>>, Vec<Value>) {
+        let values = self.bindings.drain().map(|kv| kv.1).collect();
+        (self.parent.take(), values)
+    }
+
     /// Returns the value for a name in this Environment, or the
     /// nearest parent to define it.
-    /// Returns an InterpreterError::UnboundName if name is unbound.
+    /// Returns an InterpreterError::UnboundName if name is unbound, or
+    /// InterpreterError::MissingCapability if name is a disabled Builtin.
     pub fn get(&self, name: &str) -> Result<Value, InterpreterError> {
         match self.bindings.get(name) {
             Some(value) => Ok(value.clone()),
             None => match &self.parent {
                 Some(parent) => parent.borrow().get(name),
-                None => Err(InterpreterError::UnboundName(name.to_string())),
+                None => match self.disabled.get(name) {
+                    Some(capability) => Err(InterpreterError::MissingCapability {
+                        name: name.to_string(),
+                        capability: *capability,
+                    }),
+                    None => Err(InterpreterError::UnboundName(name.to_string())),
+                },
             },
         }
     }
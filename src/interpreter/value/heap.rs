@@ -0,0 +1,325 @@
+//! Author: Rafael Bayer (2021)
+//! The heap module tracks every reference counted container in Puffin
+//! (arrays, structures, and environments), and collects reference cycles between them.
+//!
+//! `Rc` frees a container as soon as its last reference is dropped, but containers that
+//! reference each other (for example, a structure holding a receiver closure that points back
+//! at the structure) keep each other alive forever. The collector finds these using trial deletion:
+//! a container with more strong references than the other tracked containers account for
+//! is referenced from outside the heap (the interpreter's stack, or a host), and is a root.
+//! Any container that can't be reached from a root is only alive because of a cycle, and is cleared.
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    rc::{Rc, Weak},
+};
+
+use super::{ClosureKind, Environment, Value};
+
+/// Number of tracked containers before the first automatic collection
+const INITIAL_THRESHOLD: usize = 10_000;
+
+/// Memory statistics for the Puffin heap of the current thread
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct HeapStats {
+    /// Live arrays
+    pub arrays: usize,
+    /// Live structures
+    pub structures: usize,
+    /// Live environments
+    pub environments: usize,
+    /// Number of collections run so far
+    pub collections: usize,
+    /// Total number of containers freed by collections
+    pub freed: usize,
+}
+
+/// Weak reference to a tracked container
+enum Tracked {
+    Array(Weak<RefCell<Vec<Value>>>),
+    Structure(Weak<RefCell<HashMap<String, Value>>>),
+    Environment(Weak<RefCell<Environment>>),
+}
+
+/// Strong reference to a tracked container, held only for the duration of a collection
+enum Object {
+    Array(Rc<RefCell<Vec<Value>>>),
+    Structure(Rc<RefCell<HashMap<String, Value>>>),
+    Environment(Rc<RefCell<Environment>>),
+}
+
+/// Contents removed from garbage containers.
+/// These are dropped only once the collection has released every borrow.
+#[derive(Default)]
+struct Trash {
+    values: Vec<Value>,
+    parents: Vec<Rc<RefCell<Environment>>>,
+}
+
+struct Heap {
+    objects: Vec<Tracked>,
+    threshold: usize,
+    collections: usize,
+    freed: usize,
+}
+
+thread_local! {
+    static HEAP: RefCell<Heap> = const {
+        RefCell::new(Heap {
+            objects: Vec::new(),
+            threshold: INITIAL_THRESHOLD,
+            collections: 0,
+            freed: 0,
+        })
+    };
+}
+
+/// Allocates a new tracked array
+pub fn array(array: Vec<Value>) -> Rc<RefCell<Vec<Value>>> {
+    let rc = Rc::new(RefCell::new(array));
+    track(Tracked::Array(Rc::downgrade(&rc)));
+    rc
+}
+
+/// Allocates a new tracked structure
+pub fn structure(structure: HashMap<String, Value>) -> Rc<RefCell<HashMap<String, Value>>> {
+    let rc = Rc::new(RefCell::new(structure));
+    track(Tracked::Structure(Rc::downgrade(&rc)));
+    rc
+}
+
+/// Allocates a new tracked environment
+pub fn environment(environment: Environment) -> Rc<RefCell<Environment>> {
+    let rc = Rc::new(RefCell::new(environment));
+    track(Tracked::Environment(Rc::downgrade(&rc)));
+    rc
+}
+
+/// Returns memory statistics for the heap of the current thread
+pub fn stats() -> HeapStats {
+    HEAP.with(|heap| {
+        let heap = heap.borrow();
+        let mut stats = HeapStats {
+            collections: heap.collections,
+            freed: heap.freed,
+            ..HeapStats::default()
+        };
+
+        for tracked in &heap.objects {
+            match tracked {
+                Tracked::Array(w) if w.strong_count() > 0 => stats.arrays += 1,
+                Tracked::Structure(w) if w.strong_count() > 0 => stats.structures += 1,
+                Tracked::Environment(w) if w.strong_count() > 0 => stats.environments += 1,
+                _ => {}
+            }
+        }
+
+        stats
+    })
+}
+
+/// Collects all unreachable reference cycles on the heap of the current thread.
+/// Returns the number of containers freed.
+pub fn collect() -> usize {
+    // take a strong snapshot of every live container, forgetting the dead ones
+    let objects: Vec<Object> = HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        let objects: Vec<Object> = heap.objects.iter().filter_map(Tracked::upgrade).collect();
+        heap.objects = objects.iter().map(Object::downgrade).collect();
+        objects
+    });
+
+    let index: HashMap<usize, usize> = objects
+        .iter()
+        .enumerate()
+        .map(|(i, object)| (object.address(), i))
+        .collect();
+
+    // edges between tracked containers, and the number of references
+    // to each container held by other tracked containers
+    let mut edges: Vec<Vec<usize>> = Vec::with_capacity(objects.len());
+    let mut internal = vec![0; objects.len()];
+    let mut traced = vec![true; objects.len()];
+    for (i, object) in objects.iter().enumerate() {
+        let mut children = Vec::new();
+        traced[i] = object.trace(&mut |address| {
+            if let Some(&child) = index.get(&address) {
+                internal[child] += 1;
+                children.push(child);
+            }
+        });
+        edges.push(children);
+    }
+
+    // roots are referenced from outside the heap. The snapshot itself holds one reference.
+    // containers we couldn't trace (currently borrowed) are conservatively treated as roots.
+    let mut reachable = vec![false; objects.len()];
+    let mut stack: Vec<usize> = (0..objects.len())
+        .filter(|&i| !traced[i] || strong_count(&objects[i]) - 1 > internal[i])
+        .collect();
+
+    while let Some(i) = stack.pop() {
+        if reachable[i] {
+            continue;
+        }
+        reachable[i] = true;
+        stack.extend(edges[i].iter().copied().filter(|&child| !reachable[child]));
+    }
+
+    // everything else is only alive because of a cycle, clear it to break the cycle
+    let mut trash = Trash::default();
+    let mut freed = 0;
+    for (i, object) in objects.iter().enumerate() {
+        if !reachable[i] && object.clear(&mut trash) {
+            freed += 1;
+        }
+    }
+
+    HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        heap.collections += 1;
+        heap.freed += freed;
+    });
+
+    // release the snapshot before the trash, so dropping the trash frees the containers
+    drop(objects);
+    drop(trash);
+    freed
+}
+
+/// Registers a new container, running a collection if the heap has grown past its threshold
+fn track(tracked: Tracked) {
+    let should_collect = HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        heap.objects.push(tracked);
+        heap.objects.len() >= heap.threshold
+    });
+
+    if should_collect {
+        collect();
+        // collect again once the surviving heap has doubled in size
+        HEAP.with(|heap| {
+            let mut heap = heap.borrow_mut();
+            heap.objects.retain(Tracked::is_alive);
+            heap.threshold = INITIAL_THRESHOLD.max(heap.objects.len() * 2);
+        });
+    }
+}
+
+fn strong_count(object: &Object) -> usize {
+    match object {
+        Object::Array(rc) => Rc::strong_count(rc),
+        Object::Structure(rc) => Rc::strong_count(rc),
+        Object::Environment(rc) => Rc::strong_count(rc),
+    }
+}
+
+/// Calls `f` with the address of every container directly referenced by `value`
+fn trace_value(value: &Value, f: &mut impl FnMut(usize)) {
+    match value {
+        Value::Array(array) => f(address(array)),
+        Value::Structure(structure) => f(address(structure)),
+        Value::Closure {
+            kind, environment, ..
+        } => {
+            f(address(environment));
+            if let ClosureKind::Receiver(structure) = kind {
+                f(address(structure));
+            }
+        }
+        _ => {}
+    }
+}
+
+#[inline]
+fn address<T>(rc: &Rc<T>) -> usize {
+    Rc::as_ptr(rc) as *const () as usize
+}
+
+impl Tracked {
+    fn is_alive(&self) -> bool {
+        match self {
+            Tracked::Array(w) => w.strong_count() > 0,
+            Tracked::Structure(w) => w.strong_count() > 0,
+            Tracked::Environment(w) => w.strong_count() > 0,
+        }
+    }
+
+    fn upgrade(&self) -> Option<Object> {
+        Some(match self {
+            Tracked::Array(w) => Object::Array(w.upgrade()?),
+            Tracked::Structure(w) => Object::Structure(w.upgrade()?),
+            Tracked::Environment(w) => Object::Environment(w.upgrade()?),
+        })
+    }
+}
+
+impl Object {
+    fn downgrade(&self) -> Tracked {
+        match self {
+            Object::Array(rc) => Tracked::Array(Rc::downgrade(rc)),
+            Object::Structure(rc) => Tracked::Structure(Rc::downgrade(rc)),
+            Object::Environment(rc) => Tracked::Environment(Rc::downgrade(rc)),
+        }
+    }
+
+    fn address(&self) -> usize {
+        match self {
+            Object::Array(rc) => address(rc),
+            Object::Structure(rc) => address(rc),
+            Object::Environment(rc) => address(rc),
+        }
+    }
+
+    /// Calls `f` with the address of every container referenced by this one.
+    /// Returns false if the container is mutably borrowed and could not be traced.
+    fn trace(&self, f: &mut impl FnMut(usize)) -> bool {
+        match self {
+            Object::Array(rc) => match rc.try_borrow() {
+                Ok(array) => array.iter().for_each(|value| trace_value(value, f)),
+                Err(_) => return false,
+            },
+            Object::Structure(rc) => match rc.try_borrow() {
+                Ok(structure) => structure.values().for_each(|value| trace_value(value, f)),
+                Err(_) => return false,
+            },
+            Object::Environment(rc) => match rc.try_borrow() {
+                Ok(environment) => {
+                    if let Some(parent) = environment.parent() {
+                        f(address(parent));
+                    }
+                    environment.values().for_each(|value| trace_value(value, f));
+                }
+                Err(_) => return false,
+            },
+        }
+
+        true
+    }
+
+    /// Moves the contents of this container into `trash`.
+    /// Returns false if the container is borrowed and could not be cleared.
+    fn clear(&self, trash: &mut Trash) -> bool {
+        match self {
+            Object::Array(rc) => match rc.try_borrow_mut() {
+                Ok(mut array) => trash.values.append(&mut array),
+                Err(_) => return false,
+            },
+            Object::Structure(rc) => match rc.try_borrow_mut() {
+                Ok(mut structure) => trash.values.extend(structure.drain().map(|kv| kv.1)),
+                Err(_) => return false,
+            },
+            Object::Environment(rc) => match rc.try_borrow_mut() {
+                Ok(mut environment) => {
+                    let (parent, values) = environment.clear();
+                    trash.parents.extend(parent);
+                    trash.values.extend(values);
+                }
+                Err(_) => return false,
+            },
+        }
+
+        true
+    }
+}
@@ -0,0 +1,116 @@
+//! Author: Rafael Bayer (2021)
+//! The json module converts between Puffin values and JSON text.
+//!
+//! Puffin has no boolean type, so JSON `true` and `false` parse as `1` and `0`.
+//! Structures are written with their fields sorted by name, and closures, builtins, types,
+//! non-finite numbers, and circular references can't be written at all.
+
+use std::{cell::RefCell, collections::HashMap, collections::HashSet, rc::Rc};
+
+use serde::Serialize;
+use serde_json::{ser::PrettyFormatter, Map, Number, Serializer};
+
+use super::Value;
+use crate::interpreter::InterpreterError;
+
+/// Parses JSON text into a Puffin value
+pub fn parse(text: &str) -> Result<Value, InterpreterError> {
+    let json: serde_json::Value = serde_json::from_str(text).map_err(json_error)?;
+    Ok(from_json(json))
+}
+
+/// Writes a Puffin value as JSON text.
+/// If `indent` is non-zero, the output is pretty printed with `indent` spaces per level.
+pub fn stringify(value: &Value, indent: usize) -> Result<String, InterpreterError> {
+    let json = to_json(value, &mut HashSet::new())?;
+    if indent == 0 {
+        return serde_json::to_string(&json).map_err(json_error);
+    }
+
+    let indent = vec![b' '; indent];
+    let mut out = Vec::new();
+    let mut serializer = Serializer::with_formatter(&mut out, PrettyFormatter::with_indent(&indent));
+    json.serialize(&mut serializer).map_err(json_error)?;
+    Ok(String::from_utf8(out).expect("serde_json writes utf-8"))
+}
+
+fn from_json(json: serde_json::Value) -> Value {
+    match json {
+        serde_json::Value::Null => Value::Null,
+        serde_json::Value::Bool(b) => Value::from(b),
+        serde_json::Value::Number(n) => Value::Num(n.as_f64().unwrap_or(f64::NAN)),
+        serde_json::Value::String(s) => Value::String(s),
+        serde_json::Value::Array(array) => {
+            Value::from(array.into_iter().map(from_json).collect::<Vec<Value>>())
+        }
+        serde_json::Value::Object(object) => Value::from(
+            object
+                .into_iter()
+                .map(|(name, value)| (name, from_json(value)))
+                .collect::<HashMap<String, Value>>(),
+        ),
+    }
+}
+
+/// Converts a Puffin value to JSON.
+/// `seen` holds the containers currently being converted, a container seen twice
+/// on the way down is a circular reference. Shared (but acyclic) containers are fine.
+fn to_json(value: &Value, seen: &mut HashSet<usize>) -> Result<serde_json::Value, InterpreterError> {
+    Ok(match value {
+        Value::Null => serde_json::Value::Null,
+        Value::Num(n) => serde_json::Value::Number(number(*n)?),
+        Value::String(s) => serde_json::Value::String(s.clone()),
+        Value::Array(array) => {
+            enter(array, seen)?;
+            let elements = array
+                .borrow()
+                .iter()
+                .map(|element| to_json(element, seen))
+                .collect::<Result<Vec<_>, _>>()?;
+            seen.remove(&address(array));
+            serde_json::Value::Array(elements)
+        }
+        Value::Structure(structure) => {
+            enter(structure, seen)?;
+            let mut fields = Map::new();
+            for (name, field) in structure.borrow().iter() {
+                fields.insert(name.clone(), to_json(field, seen)?);
+            }
+            seen.remove(&address(structure));
+            serde_json::Value::Object(fields)
+        }
+        other => {
+            return Err(InterpreterError::JsonError(format!(
+                "can't convert {} to JSON",
+                other
+            )))
+        }
+    })
+}
+
+/// Converts a Puffin number to a JSON number, writing integral numbers without a fraction
+fn number(n: f64) -> Result<Number, InterpreterError> {
+    if n.fract() == 0.0 && n.abs() < i64::MAX as f64 {
+        return Ok(Number::from(n as i64));
+    }
+
+    Number::from_f64(n)
+        .ok_or_else(|| InterpreterError::JsonError(format!("can't convert {} to JSON", n)))
+}
+
+fn enter<T>(container: &Rc<RefCell<T>>, seen: &mut HashSet<usize>) -> Result<(), InterpreterError> {
+    if !seen.insert(address(container)) {
+        return Err(InterpreterError::JsonError(
+            "can't convert circular reference to JSON".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn address<T>(container: &Rc<RefCell<T>>) -> usize {
+    container.as_ptr() as usize
+}
+
+fn json_error(err: serde_json::Error) -> InterpreterError {
+    InterpreterError::JsonError(err.to_string())
+}
@@ -13,8 +13,10 @@ use super::InterpreterError;
 use crate::ast::node::*;
 use crate::interpreter::unexpected_type;
 
-mod builtin;
+pub(crate) mod builtin;
 pub mod environment;
+pub mod heap;
+pub mod json;
 pub use environment::Environment;
 
 use builtin::Builtin;
@@ -36,6 +38,7 @@ pub enum Value {
     Closure {
         kind: ClosureKind,
         args: Vec<String>,
+        signature: Signature,
         block: Block,
         environment: Rc<RefCell<Environment>>,
     },
@@ -55,6 +58,22 @@ pub enum ClosureKind {
     Named(String),
 }
 
+impl Value {
+    /// Returns the type of this value
+    pub fn type_of(&self) -> Type {
+        match self {
+            Value::Null => Type::Null,
+            Value::Num(_) => Type::Num,
+            Value::String(_) => Type::String,
+            Value::Array(_) => Type::Array,
+            Value::Structure(_) => Type::Struct,
+            Value::Closure { .. } => Type::Closure,
+            Value::Builtin(_) => Type::Builtin,
+            Value::Type(_) => Type::Type,
+        }
+    }
+}
+
 /// Circular refrence display
 const CIRCULAR_REF: &str = "...";
 
@@ -209,6 +228,13 @@ impl From<f64> for Value {
     }
 }
 
+impl From<bool> for Value {
+    /// produces a Num `Value` from a bool, 1 if true and 0 if false
+    fn from(v: bool) -> Self {
+        Value::Num(v as i32 as f64)
+    }
+}
+
 impl From<String> for Value {
     /// produces a String `Value` from a String
     fn from(v: String) -> Self {
@@ -219,14 +245,14 @@ impl From<String> for Value {
 impl From<Vec<Value>> for Value {
     /// produces a Array `Value` from a Vec<Value>
     fn from(v: Vec<Value>) -> Self {
-        Value::Array(Rc::new(RefCell::new(v)))
+        Value::Array(heap::array(v))
     }
 }
 
 impl From<HashMap<String, Value>> for Value {
     /// produces a Structure `Value` from a HashMap<String, Value>
     fn from(v: HashMap<String, Value>) -> Self {
-        Value::Structure(Rc::new(RefCell::new(v)))
+        Value::Structure(heap::structure(v))
     }
 }
 
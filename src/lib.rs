@@ -22,44 +22,85 @@ Welcome to the Puffin Language Interpreter!
 -----------------------------------------------------------
 */
 
-#[macro_use]
-extern crate pest_derive;
-
-extern crate cached;
-
+pub mod checker;
+pub mod cli;
+pub mod coverage;
+pub mod debugger;
+mod error;
+pub mod formatter;
 pub mod parser;
+pub mod profiler;
 pub mod ast;
 pub mod interpreter;
+pub mod lsp;
 pub mod repl;
-use std::{fs, process};
+pub mod testing;
+pub mod tracer;
+use std::{fs, path::Path, rc::Rc, str::FromStr, time::Duration};
+
+use ast::node::Program;
+use checker::Severity;
+use coverage::Coverage;
+use debugger::Debugger;
+pub use error::Error;
+pub use formatter::format_program;
+use interpreter::{
+    hooks::Hooks,
+    runtime::{Limits, Runtime},
+    value::Value,
+};
+pub use parser::PuffinParser;
+use profiler::Profiler;
+use tracer::Tracer;
+use parser::lexer::{self, Token, TokenKind};
 
-use interpreter::value::Value;
-pub use parser::{Rule, PuffinParser};
-pub use pest::Parser;
+use cli::Source;
 
 /// Puffin Run Config.
-/// Includes the filename of the program, as well as any optional flags
+/// Includes the source of the program, any optional flags,
+/// and the arguments passed to the program
+#[derive(Debug, Clone, PartialEq)]
 pub struct Config {
-    pub filename: String,
+    pub source: Source,
     pub show_parse: bool,
     pub show_ast: bool,
+    pub seed: Option<u64>,
+    pub check_types: bool,
+    /// resource limits of the program
+    pub limits: Limits,
+    /// path to write the folded stacks of a profile to, if the program is profiled
+    pub profile: Option<String>,
+    /// path to write the LCOV coverage report to, if coverage is recorded
+    pub coverage: Option<String>,
+    /// whether to print the statements, calls, and assignments of the program as it runs
+    pub trace: bool,
+    /// names of the closures whose calls are traced, or every closure if empty
+    pub trace_functions: Vec<String>,
+    pub args: Vec<String>,
 }
 
 impl Config {
     /// Create a Config `from std::env::args()`.
     /// Note: expects that first argument is the puffin interpreter executable.
-    /// Example args: `["./puffin", "program.puf", "-ast", "-parse"]`
+    /// Flags come directly after the source, the first other argument (or everything after `--`)
+    /// is passed to the program.
+    /// Example args: `["./puffin", "program.puf", "-ast", "--seed", "42", "--", "-input.txt"]`
     pub fn new(args: &[String]) -> Result<Config, String> {
-        if args.len() < 2 {
-            return Err("Required Arguments: filename".to_string());
-        }
-
-        let filename = args[1].clone();
+        let (source, used) = Source::parse(&args[1.min(args.len())..])?;
         let mut show_parse = false;
         let mut show_ast = false;
+        let mut seed = None;
+        let mut check_types = false;
+        let mut limits = Limits::default();
+        let mut profile = None;
+        let mut coverage = None;
+        let mut trace = false;
+        let mut trace_functions = Vec::new();
+        let mut program_args = Vec::new();
         
         // parse optional flags
-        for option in args.iter().skip(2) {
+        let mut options = args.iter().skip(1 + used);
+        while let Some(option) = options.next() {
             match option.to_lowercase().as_str() {
                 "-parse" => {
                     show_parse = true;
@@ -67,43 +108,356 @@ impl Config {
                 "-ast" => {
                     show_ast = true;
                 },
-                _ => return Err(format!("Unknown option: {}", option))
+                "--seed" => {
+                    let value = options.next().ok_or("Missing value for --seed")?;
+                    let value: i64 = value
+                        .parse()
+                        .map_err(|_| format!("Invalid seed: {}", value))?;
+                    // same conversion as the `seed` builtin
+                    seed = Some(value as u64);
+                },
+                "--check-types" => {
+                    check_types = true;
+                },
+                "--max-steps" => {
+                    limits.max_steps = Some(flag_value(&mut options, "--max-steps")?);
+                },
+                "--max-depth" => {
+                    limits.max_depth = Some(flag_value(&mut options, "--max-depth")?);
+                },
+                "--max-memory" => {
+                    limits.max_memory = Some(flag_value(&mut options, "--max-memory")?);
+                },
+                "--timeout" => {
+                    let seconds: f64 = flag_value(&mut options, "--timeout")?;
+                    let timeout = Duration::try_from_secs_f64(seconds)
+                        .map_err(|_| format!("Invalid value for --timeout: {}", seconds))?;
+                    limits.max_time = Some(timeout);
+                },
+                "--profile" => {
+                    let path = options.next().ok_or("Missing path for --profile")?;
+                    profile = Some(path.clone());
+                },
+                "--coverage" => {
+                    let path = options.next().ok_or("Missing path for --coverage")?;
+                    coverage = Some(path.clone());
+                },
+                "--trace" => {
+                    trace = true;
+                },
+                "--trace-fn" => {
+                    let name = options.next().ok_or("Missing name for --trace-fn")?;
+                    trace = true;
+                    trace_functions.push(name.clone());
+                },
+                "--" => {
+                    program_args.extend(options.cloned());
+                    break;
+                },
+                flag if flag.starts_with('-') => return Err(format!("Unknown option: {}", option)),
+                _ => {
+                    program_args.push(option.clone());
+                    program_args.extend(options.cloned());
+                    break;
+                },
             }
         }
 
         Ok(Config {
-            filename,
+            source,
             show_parse,
             show_ast,
+            seed,
+            check_types,
+            limits,
+            profile,
+            coverage,
+            trace,
+            trace_functions,
+            args: program_args,
         })
     }
 }
 
+/// Parses the value of a flag from the next argument
+fn flag_value<'a, T, I>(options: &mut I, flag: &str) -> Result<T, String>
+where
+    T: FromStr,
+    I: Iterator<Item = &'a String>,
+{
+    let value = options.next().ok_or_else(|| format!("Missing value for {}", flag))?;
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value for {}: {}", flag, value))
+}
+
+/// Parses a puffin program into its AST, without running it
+pub fn parse(source: &str) -> Result<Program, Error> {
+    Ok(PuffinParser::parse_program(source)?)
+}
+
+/// Runs a puffin program in a given str, returning the program final output
+pub fn run_source(source: &str) -> Result<Value, Error> {
+    run_source_with(source, Runtime::new())
+}
+
+/// Runs a puffin program in a given str under a given Runtime,
+/// returning the program final output
+pub fn run_source_with(source: &str, runtime: Runtime) -> Result<Value, Error> {
+    let program = parse(source)?;
+    Ok(interpreter::eval_with(&program, runtime)?)
+}
+
+/// Runs the puffin program in the file at `path`, returning the program final output
+pub fn run_file<P: AsRef<Path>>(path: P) -> Result<Value, Error> {
+    run_source(&fs::read_to_string(path)?)
+}
+
 /// Runs a puffin program given a Config.
 /// Returns the program final output if successful.
-/// Prints errors to stderr and exits with non-zero exit code if errors are encountered.
-pub fn run(config: Config) -> Value {
-    let contents = fs::read_to_string(config.filename.clone()).unwrap_or_else(|err| {
-        eprintln!("Failed to read file: {:#?}", err);
-        process::exit(1);
-    });
-    
-    let parsed = PuffinParser::parse_program(&contents).unwrap_or_else(|err| {
-        eprintln!("Parser Error: {}", parser::line_col(err));
-        process::exit(1);
-    });
+pub fn run(config: Config) -> Result<Value, Error> {
+    let contents = config.source.read()?;
     if config.show_parse {
-        println!("{} parse:\n{:#?}", config.filename, &parsed);
+        println!("{} tokens:\n{:#?}", config.source.name(), tokenize(&contents)?);
     }
-    let program = ast::build_program(parsed.into_iter().next().unwrap()).unwrap_or_else(|err| {
-        eprintln!("AST Error: {:#?}", err);
-        process::exit(1);
-    });
+    let program = parse(&contents)?;
     if config.show_ast {
-        println!("{} ast:\n{:#?}", config.filename, &program);
+        println!("{} ast:\n{:#?}", config.source.name(), &program);
     }
-    interpreter::eval(&program).unwrap_or_else(|err| {
-        eprintln!("Runtime Error: {:#?}", err);
-        process::exit(1);
-    })
-}
\ No newline at end of file
+
+    let reports = Reports::new(&config, &program, &contents);
+    let mut runtime = runtime(config);
+    if !reports.hooks.is_empty() {
+        runtime = runtime.with_hooks(Rc::new(reports.hooks.clone()));
+    }
+    let result = interpreter::eval_with(&program, runtime);
+    // the profile and coverage are written even if the program failed
+    reports.write()?;
+    Ok(result?)
+}
+
+/// Runs a puffin program given a Config in the debugger,
+/// which stops before the first statement and reads commands from stdin.
+/// Returns the program final output if successful.
+pub fn debug(config: Config) -> Result<Value, Error> {
+    let contents = config.source.read()?;
+    let program = parse(&contents)?;
+    let reports = Reports::new(&config, &program, &contents);
+    let mut hooks = reports.hooks.clone();
+    hooks.push(Rc::new(Debugger::new(&contents)));
+    let result = interpreter::eval_with(&program, runtime(config).with_hooks(Rc::new(hooks)));
+    reports.write()?;
+    Ok(result?)
+}
+
+/// Hooks a program runs under for the tracing, profile, and coverage flags of its Config,
+/// along with where the profile and coverage are written
+struct Reports {
+    source: String,
+    hooks: Vec<Rc<dyn Hooks>>,
+    profiler: Option<(String, Rc<Profiler>)>,
+    coverage: Option<(String, Rc<Coverage>)>,
+}
+
+impl Reports {
+    fn new(config: &Config, program: &Program, contents: &str) -> Reports {
+        let source = config.source.name().to_string();
+        let mut hooks: Vec<Rc<dyn Hooks>> = Vec::new();
+        if config.trace {
+            let tracer = Tracer::new(&source, contents).with_functions(config.trace_functions.clone());
+            hooks.push(Rc::new(tracer));
+        }
+        let profiler = config.profile.clone().map(|path| (path, Rc::new(Profiler::new())));
+        if let Some((_, profiler)) = &profiler {
+            hooks.push(profiler.clone());
+        }
+        let coverage = config.coverage.clone().map(|path| (path, Rc::new(Coverage::new(program))));
+        if let Some((_, coverage)) = &coverage {
+            hooks.push(coverage.clone());
+        }
+        Reports {
+            source,
+            hooks,
+            profiler,
+            coverage,
+        }
+    }
+
+    /// prints the profile, and writes the profile and coverage files
+    fn write(self) -> Result<(), Error> {
+        if let Some((path, profiler)) = self.profiler {
+            profiler.finish();
+            eprint!("{}", profiler.table());
+            fs::write(path, profiler.folded())?;
+        }
+        if let Some((path, coverage)) = self.coverage {
+            fs::write(path, coverage.lcov(&self.source))?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the Runtime to run a program under, given its Config
+fn runtime(config: Config) -> Runtime {
+    let mut runtime = Runtime::new()
+        .with_args(config.args)
+        .with_type_checks(config.check_types)
+        .with_limits(config.limits);
+    if let Some(seed) = config.seed {
+        runtime = runtime.with_seed(seed);
+    }
+    runtime
+}
+
+/// Parses and checks a puffin program without running it,
+/// printing any diagnostics to stderr.
+/// Returns false if the checker found errors.
+pub fn check(source: &Source) -> Result<bool, Error> {
+    let contents = source.read()?;
+    let diagnostics = checker::check_program(&parse(&contents)?);
+    for (i, diagnostic) in diagnostics.iter().enumerate() {
+        if i > 0 {
+            eprintln!();
+        }
+        eprintln!("{}", diagnostic.render(&contents));
+    }
+    Ok(!diagnostics
+        .iter()
+        .any(|diagnostic| diagnostic.severity == Severity::Error))
+}
+
+/// Runs the tests of the puffin programs in `paths` (see `testing`), whose names contain `filter`,
+/// printing the result of each test and a summary.
+/// With `coverage`, an LCOV report of the lines and branches the tests executed is written to it.
+/// Returns false if a test failed, or a program couldn't be parsed.
+pub fn test(paths: &[String], filter: Option<&str>, coverage: Option<&str>) -> Result<bool, Error> {
+    let mut passed = 0;
+    let mut failures = Vec::new();
+    let mut filtered = 0;
+    let mut lcov = String::new();
+    let mut parsed = true;
+
+    for path in testing::sources(paths)? {
+        let name = path.display().to_string();
+        let program = match parse(&fs::read_to_string(&path)?) {
+            Ok(program) => program,
+            Err(err) => {
+                eprintln!("{}: {}", name, err);
+                parsed = false;
+                continue;
+            }
+        };
+
+        let recorder = coverage.map(|_| Rc::new(Coverage::new(&program)));
+        let hooks = recorder.clone().map(|recorder| recorder as Rc<dyn Hooks>);
+        let (tests, skipped): (Vec<String>, Vec<String>) = testing::discover(&program)
+            .into_iter()
+            .partition(|test| testing::selected(test, filter));
+        filtered += skipped.len();
+        if !tests.is_empty() {
+            println!("{}", name);
+        }
+        for test in tests {
+            let captured = testing::run(&program, &test, hooks.clone());
+            match &captured.result {
+                Ok(_) => {
+                    println!("    {} ... ok", test);
+                    passed += 1;
+                }
+                Err(_) => {
+                    println!("    {} ... FAILED", test);
+                    failures.push((format!("{}: {}", name, test), captured));
+                }
+            }
+        }
+        if let Some(recorder) = recorder {
+            lcov.push_str(&recorder.lcov(&name));
+        }
+    }
+
+    if !failures.is_empty() {
+        println!("\nfailures:");
+        for (test, captured) in &failures {
+            if let Err(err) = &captured.result {
+                println!("    {}\n        {}", test, err);
+            }
+            // output is only shown for the tests that failed
+            for (stream, output) in [("stdout", &captured.stdout), ("stderr", &captured.stderr)] {
+                if !output.is_empty() {
+                    println!("        ---- {} ----", stream);
+                    for line in output.lines() {
+                        println!("        {}", line);
+                    }
+                }
+            }
+        }
+    }
+    let result = if failures.is_empty() && parsed { "ok" } else { "FAILED" };
+    println!(
+        "\ntest result: {}. {} passed; {} failed; {} filtered out",
+        result,
+        passed,
+        failures.len(),
+        filtered
+    );
+    if let Some(path) = coverage {
+        fs::write(path, lcov)?;
+    }
+    Ok(failures.is_empty() && parsed)
+}
+
+/// Formats the source of a puffin program
+pub fn format_source(source: &str) -> Result<String, Error> {
+    Ok(format_program(&parse(source)?))
+}
+
+/// Formats puffin programs, rewriting files in place and printing other sources to stdout.
+/// With `check`, nothing is written, instead unformatted sources are reported.
+/// Returns false if `check` found a source that isn't formatted.
+pub fn fmt(sources: &[Source], check: bool) -> Result<bool, Error> {
+    let mut formatted = true;
+    for source in sources {
+        let contents = source.read()?;
+        let output = format_source(&contents)?;
+        if check {
+            if output != contents {
+                eprintln!("{} is not formatted", source.name());
+                formatted = false;
+            }
+        } else if let Source::File(path) = source {
+            if output != contents {
+                fs::write(path, output)?;
+            }
+        } else {
+            print!("{}", output);
+        }
+    }
+    Ok(formatted)
+}
+
+/// Prints the AST of a puffin program
+pub fn print_ast(source: &Source) -> Result<(), Error> {
+    let program = parse(&source.read()?)?;
+    println!("{:#?}", program);
+    Ok(())
+}
+
+/// Prints the tokens of a puffin program, one per line
+pub fn print_tokens(source: &Source) -> Result<(), Error> {
+    let contents = source.read()?;
+    for token in tokenize(&contents)? {
+        let span = token.span;
+        println!("{}:{}\t{:?}\t{}", span.line, span.col, token.kind, &contents[span.start..span.end]);
+    }
+    Ok(())
+}
+
+/// Splits a puffin program into tokens, without the final `Eof` token
+fn tokenize(source: &str) -> Result<Vec<Token>, Error> {
+    let mut lexed = lexer::tokenize(source);
+    if !lexed.errors.is_empty() {
+        return Err(lexed.errors.into());
+    }
+    lexed.tokens.retain(|token| token.kind != TokenKind::Eof);
+    Ok(lexed.tokens)
+}
@@ -0,0 +1,435 @@
+//! Author: Rafael Bayer (2021)
+//! The analysis module indexes the names of a program for the language server.
+//!
+//! Names are resolved like the checker resolves them, see `ast::visit`: each function has its own
+//! scope, and a name refers to where it is first bound in the innermost scope that binds it.
+//! Every binding and use of a name is recorded as a reference to its symbol.
+//! Structure fields are tracked for names bound to structure literals,
+//! to functions returning them, and for `self` in receivers.
+
+use std::collections::HashMap;
+
+use crate::ast::{
+    node::*,
+    visit::{self, Binding, Function, Visitor},
+};
+use crate::interpreter::value::{builtin, Value};
+use crate::parser::{
+    lexer::{self, Token, TokenKind},
+    Span,
+};
+
+/// Kind of thing a name is bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Builtin,
+    Function,
+    Variable,
+    Argument,
+    /// name bound by a `for (name in array)` loop
+    Loop,
+}
+
+/// Name bound in a scope of the program
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// span of the name where it is first bound, None for builtins
+    pub span: Option<Span>,
+    /// span of the statement first binding the name, None for builtins
+    pub statement: Option<Span>,
+    /// description of the symbol, such as the signature of a function
+    pub detail: String,
+    /// documentation of the symbol, for builtins
+    pub doc: Option<String>,
+    /// fields of the structure bound to the name, if known
+    pub fields: Vec<String>,
+    /// fields of the structure returned by a function
+    returns: Vec<String>,
+}
+
+/// Scope of a function, or the program
+#[derive(Debug)]
+struct Scope {
+    span: Span,
+    parent: Option<usize>,
+    bindings: HashMap<String, usize>,
+}
+
+/// Index of the names of a program
+#[derive(Debug)]
+pub struct Analysis {
+    symbols: Vec<Symbol>,
+    /// span of each binding and use of a name, and the symbol it refers to
+    references: Vec<(Span, usize)>,
+    scopes: Vec<Scope>,
+}
+
+impl Analysis {
+    /// Indexes a program parsed from `source`
+    pub fn new(source: &str, program: &Program) -> Analysis {
+        let global = Scope {
+            span: Span::new(source, 0, source.len()),
+            parent: None,
+            bindings: HashMap::new(),
+        };
+        let mut indexer = Indexer {
+            source,
+            tokens: lexer::tokenize(source).tokens,
+            analysis: Analysis {
+                symbols: Vec::new(),
+                references: Vec::new(),
+                scopes: vec![global],
+            },
+            stack: vec![0],
+            self_fields: Vec::new(),
+        };
+
+        let mut builtins: Vec<(String, Value)> = builtin::get_builtins().into_iter().collect();
+        builtins.push((builtin::ARGS.to_string(), Value::from(Vec::new())));
+        builtins.sort_by(|a, b| a.0.cmp(&b.0));
+        for (name, value) in builtins {
+            let detail = builtin_detail(&name, &value);
+            let doc = match &value {
+                Value::Builtin(builtin) => Some(builtin.doc().to_string()),
+                _ => None,
+            };
+            indexer.bind_symbol(Symbol {
+                name,
+                kind: SymbolKind::Builtin,
+                span: None,
+                statement: None,
+                detail,
+                doc,
+                fields: Vec::new(),
+                returns: Vec::new(),
+            });
+        }
+
+        visit::walk_program(&mut indexer, program);
+        indexer.analysis
+    }
+
+    /// Returns the symbol of the name at byte offset `pos`, if any
+    pub fn symbol_at(&self, pos: usize) -> Option<&Symbol> {
+        self.references
+            .iter()
+            .find(|(span, _)| span.start <= pos && pos <= span.end)
+            .map(|(_, symbol)| &self.symbols[*symbol])
+    }
+
+    /// Returns the symbol a name refers to at byte offset `pos`
+    pub fn resolve(&self, name: &str, pos: usize) -> Option<&Symbol> {
+        let mut scope = Some(self.scope_at(pos));
+        while let Some(index) = scope {
+            if let Some(symbol) = self.scopes[index].bindings.get(name) {
+                return Some(&self.symbols[*symbol]);
+            }
+            scope = self.scopes[index].parent;
+        }
+        None
+    }
+
+    /// Returns every symbol visible at byte offset `pos`, innermost scopes first
+    pub fn visible(&self, pos: usize) -> Vec<&Symbol> {
+        let mut visible: Vec<&Symbol> = Vec::new();
+        let mut scope = Some(self.scope_at(pos));
+        while let Some(index) = scope {
+            let mut symbols: Vec<&Symbol> = self.scopes[index]
+                .bindings
+                .values()
+                .map(|symbol| &self.symbols[*symbol])
+                .filter(|symbol| visible.iter().all(|seen| seen.name != symbol.name))
+                .collect();
+            symbols.sort_by(|a, b| a.name.cmp(&b.name));
+            visible.extend(symbols);
+            scope = self.scopes[index].parent;
+        }
+        visible
+    }
+
+    /// Returns the functions bound at the top level of the program, in source order
+    pub fn functions(&self) -> Vec<&Symbol> {
+        let mut functions: Vec<&Symbol> = self.scopes[0]
+            .bindings
+            .values()
+            .map(|symbol| &self.symbols[*symbol])
+            .filter(|symbol| symbol.kind == SymbolKind::Function)
+            .collect();
+        functions.sort_by_key(|symbol| symbol.span.map(|span| span.start));
+        functions
+    }
+
+    /// returns the innermost scope containing byte offset `pos`
+    fn scope_at(&self, pos: usize) -> usize {
+        let mut inner = 0;
+        for (i, scope) in self.scopes.iter().enumerate() {
+            // scopes are created outermost first
+            if scope.span.start <= pos && pos <= scope.span.end {
+                inner = i;
+            }
+        }
+        inner
+    }
+}
+
+/// Walks a program, recording its symbols and references
+struct Indexer<'a> {
+    source: &'a str,
+    tokens: Vec<Token>,
+    analysis: Analysis,
+    /// scopes being indexed, innermost last
+    stack: Vec<usize>,
+    /// fields of the structure of the receiver being entered
+    self_fields: Vec<String>,
+}
+
+impl<'a> Visitor<'a> for Indexer<'_> {
+    fn statement(&mut self, statement: &'a Statement) {
+        match &statement.statement {
+            StatementKind::Assign { lhs, rhs, .. } => {
+                let span = self.name_span(statement.span.start, &lhs.name);
+                let symbol = self.reference(&lhs.name, span);
+                match (symbol, lhs.assignable.first(), &rhs.exp) {
+                    // assigning a field adds it to the structure
+                    (Some(symbol), Some(AssignableKind::StructureField { field }), _) => {
+                        self.add_fields(symbol, std::slice::from_ref(field));
+                    }
+                    // a call to a function returning a structure binds its fields
+                    (Some(symbol), None, ExpKind::Postfix(callee, PostOp::Call(_))) => {
+                        if let ExpKind::Value(ValueKind::Name(name)) = &callee.exp {
+                            if let Some(function) = self.resolve(name) {
+                                let fields = self.analysis.symbols[function].returns.clone();
+                                self.add_fields(symbol, &fields);
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            StatementKind::Nest(NestKind::LoopNest(LoopNestKind::ForIn { name, .. })) => {
+                let span = self.names_after(statement.span.start).next();
+                if let Some(span) = span {
+                    self.reference(name, span);
+                }
+            }
+            _ => {}
+        }
+        visit::walk_statement(self, statement);
+    }
+
+    fn exp(&mut self, exp: &'a Exp) {
+        match &exp.exp {
+            ExpKind::Value(ValueKind::Name(name)) => {
+                self.reference(name, exp.span);
+            }
+            _ => visit::walk_exp(self, exp),
+        }
+    }
+
+    /// indexes a function definition in a new scope
+    fn function(&mut self, function: Function<'a>) {
+        let scope = self.analysis.scopes.len();
+        self.analysis.scopes.push(Scope {
+            span: function.block.span,
+            parent: self.stack.last().copied(),
+            bindings: HashMap::new(),
+        });
+        self.stack.push(scope);
+
+        // receivers bind `self` to the structure
+        self.self_fields = match function.structure {
+            Some(fields) if function.is_receiver() => {
+                fields.iter().map(|field| field.name.clone()).collect()
+            }
+            _ => Vec::new(),
+        };
+        visit::walk_function(self, function);
+        self.stack.pop();
+    }
+
+    fn bind(&mut self, binding: Binding<'a>) {
+        match binding {
+            Binding::Argument { name, span, annotation } => {
+                let detail = match annotation {
+                    Some(ty) => format!("(argument) {}: {}", name, ty),
+                    None => format!("(argument) {}", name),
+                };
+                let fields = if name == "self" { self.self_fields.clone() } else { Vec::new() };
+                let symbol = self.bind_symbol(Symbol {
+                    name: name.to_string(),
+                    kind: SymbolKind::Argument,
+                    span: Some(span),
+                    statement: Some(span),
+                    detail,
+                    doc: None,
+                    fields,
+                    returns: Vec::new(),
+                });
+                self.analysis.references.push((span, symbol));
+            }
+            Binding::Assign {
+                name,
+                statement,
+                rhs,
+                annotation,
+            } => {
+                let (kind, detail, returns) = match Function::new(rhs) {
+                    Some(function) => (
+                        SymbolKind::Function,
+                        function_detail(name, function.args, function.signature),
+                        returned_fields(function.block),
+                    ),
+                    None => {
+                        let detail = match annotation {
+                            Some(ty) => format!("{}: {}", name, ty),
+                            None => name.to_string(),
+                        };
+                        (SymbolKind::Variable, detail, Vec::new())
+                    }
+                };
+                let fields = match &rhs.exp {
+                    ExpKind::Value(ValueKind::Structure(fields)) => {
+                        fields.iter().map(|field| field.name.clone()).collect()
+                    }
+                    _ => Vec::new(),
+                };
+                self.bind_symbol(Symbol {
+                    name: name.to_string(),
+                    kind,
+                    span: Some(self.name_span(statement.span.start, name)),
+                    statement: Some(statement.span),
+                    detail,
+                    doc: None,
+                    fields,
+                    returns,
+                });
+            }
+            Binding::Loop { name, statement } => {
+                let span = self.names_after(statement.span.start).next();
+                self.bind_symbol(Symbol {
+                    name: name.to_string(),
+                    kind: SymbolKind::Loop,
+                    span,
+                    statement: Some(statement.span),
+                    detail: format!("(loop) {}", name),
+                    doc: None,
+                    fields: Vec::new(),
+                    returns: Vec::new(),
+                });
+            }
+        }
+    }
+}
+
+impl Indexer<'_> {
+    /// binds a symbol in the current scope, unless the scope already binds its name
+    fn bind_symbol(&mut self, symbol: Symbol) -> usize {
+        let scope = *self.stack.last().unwrap();
+        if let Some(index) = self.analysis.scopes[scope].bindings.get(&symbol.name) {
+            return *index;
+        }
+
+        let index = self.analysis.symbols.len();
+        self.analysis.scopes[scope].bindings.insert(symbol.name.clone(), index);
+        self.analysis.symbols.push(symbol);
+        index
+    }
+
+    /// records a reference to a name, returning its symbol if it is bound
+    fn reference(&mut self, name: &str, span: Span) -> Option<usize> {
+        let symbol = self.resolve(name)?;
+        self.analysis.references.push((span, symbol));
+        Some(symbol)
+    }
+
+    /// returns the symbol of a name in the innermost scope binding it
+    fn resolve(&self, name: &str) -> Option<usize> {
+        self.stack
+            .iter()
+            .rev()
+            .find_map(|scope| self.analysis.scopes[*scope].bindings.get(name).copied())
+    }
+
+    fn add_fields(&mut self, symbol: usize, fields: &[String]) {
+        let known = &mut self.analysis.symbols[symbol].fields;
+        for field in fields {
+            if !known.contains(field) {
+                known.push(field.clone());
+            }
+        }
+    }
+
+    /// returns the span of a name starting at byte offset `start`
+    fn name_span(&self, start: usize, name: &str) -> Span {
+        Span::new(self.source, start, start + name.len())
+    }
+
+    /// returns the spans of the names in the source from byte offset `start`,
+    /// skipping names used as type annotations
+    fn names_after(&self, start: usize) -> impl Iterator<Item = Span> + '_ {
+        let first = self.tokens.partition_point(|token| token.span.start < start);
+        self.tokens[first..]
+            .iter()
+            .enumerate()
+            .filter(move |(i, token)| {
+                token.kind == TokenKind::Name
+                    && (*i == 0 || self.tokens[first + i - 1].kind != TokenKind::Colon)
+            })
+            .map(move |(_, token)| Span::new(self.source, token.span.start, token.span.end))
+    }
+}
+
+/// returns the fields of the structures a function returns directly
+fn returned_fields(block: &Block) -> Vec<String> {
+    let mut fields = Vec::new();
+    for statement in &block.block {
+        if let StatementKind::Return(Exp {
+            exp: ExpKind::Value(ValueKind::Structure(returned)),
+            ..
+        }) = &statement.statement
+        {
+            for field in returned {
+                if !fields.contains(&field.name) {
+                    fields.push(field.name.clone());
+                }
+            }
+        }
+    }
+    fields
+}
+
+/// returns the signature of a function, like `fn add(a: num, b) -> num`
+fn function_detail(name: &str, args: &[String], signature: &Signature) -> String {
+    let args: Vec<String> = args
+        .iter()
+        .zip(&signature.args)
+        .map(|(arg, ty)| match ty {
+            Some(ty) => format!("{}: {}", arg, ty),
+            None => arg.clone(),
+        })
+        .collect();
+    match signature.ret {
+        Some(ret) => format!("fn {}({}) -> {}", name, args.join(", "), ret),
+        None => format!("fn {}({})", name, args.join(", ")),
+    }
+}
+
+/// returns the signature of a builtin function, or the type and value of a builtin constant
+fn builtin_detail(name: &str, value: &Value) -> String {
+    let mut detail = match value {
+        Value::Builtin(builtin) => format!("fn {}({})", name, builtin.params().join(", ")),
+        Value::Num(n) => format!("{}: num = {}", name, n),
+        other => format!("{}: {}", name, other.type_of()),
+    };
+
+    let capability = match value {
+        Value::Builtin(builtin) => builtin.capability(),
+        _ => None,
+    };
+    if let Some(capability) = capability {
+        detail += &format!("\nrequires the {:?} capability", capability);
+    }
+    detail
+}
@@ -0,0 +1,376 @@
+//! Author: Rafael Bayer (2021)
+//! The lsp module implements a language server for Puffin, speaking the
+//! Language Server Protocol (JSON-RPC messages framed by `Content-Length` headers).
+//!
+//! Documents are kept in full, and analyzed again on every change.
+//! Diagnostics come from the parser and the checker. Hover, go-to-definition,
+//! completion, and document symbols use the `analysis` of the last version
+//! of a document that parsed, so they keep working while the document is being edited.
+
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+};
+
+use serde_json::{json, Value};
+
+use crate::{
+    checker,
+    parser::{PuffinParser, Span},
+};
+pub mod analysis;
+use analysis::{Analysis, SymbolKind};
+
+/// Serves the language server protocol, reading messages from `input` and writing to `output`,
+/// until the client sends `exit` or closes the input
+pub fn serve<R: BufRead, W: Write>(mut input: R, output: W) -> io::Result<()> {
+    let mut server = Server {
+        output,
+        documents: HashMap::new(),
+    };
+    loop {
+        let message = match read_message(&mut input) {
+            Ok(Some(message)) => message,
+            Ok(None) => break,
+            // a message that isn't JSON is answered with an error, and the server keeps reading
+            Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+                let error = json!({"code": -32700, "message": format!("parse error: {}", err)});
+                server.send(json!({"jsonrpc": "2.0", "id": Value::Null, "error": error}))?;
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
+        if message["method"] == "exit" {
+            break;
+        }
+        server.handle(message)?;
+    }
+    Ok(())
+}
+
+/// Reads a message framed by a `Content-Length` header, returning None at the end of the input.
+/// The Debug Adapter Protocol frames its messages the same way
+pub(crate) fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let length = length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length"))?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Open document, and the analysis of its last version that parsed
+struct Document {
+    text: String,
+    analysis: Option<(String, Analysis)>,
+}
+
+struct Server<W: Write> {
+    output: W,
+    documents: HashMap<String, Document>,
+}
+
+impl<W: Write> Server<W> {
+    fn handle(&mut self, message: Value) -> io::Result<()> {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+
+        // notifications, no response
+        let id = match message.get("id") {
+            Some(id) => id.clone(),
+            None => {
+                match method {
+                    "textDocument/didOpen" => {
+                        let document = &params["textDocument"];
+                        self.update(uri(document), text(&document["text"]))?;
+                    }
+                    "textDocument/didChange" => {
+                        // documents are synced in full, the last change is the whole document
+                        if let Some(change) = params["contentChanges"].as_array().and_then(|changes| changes.last()) {
+                            self.update(uri(&params["textDocument"]), text(&change["text"]))?;
+                        }
+                    }
+                    "textDocument/didClose" => {
+                        let uri = uri(&params["textDocument"]);
+                        self.documents.remove(&uri);
+                        self.notify("textDocument/publishDiagnostics", json!({"uri": uri, "diagnostics": []}))?;
+                    }
+                    _ => {}
+                }
+                return Ok(());
+            }
+        };
+
+        let result = match method {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "completionProvider": {"triggerCharacters": ["."]},
+                    "documentSymbolProvider": true,
+                },
+                "serverInfo": {"name": "puffin-lsp", "version": crate::cli::VERSION},
+            }),
+            "shutdown" => Value::Null,
+            "textDocument/hover" => self.hover(params),
+            "textDocument/definition" => self.definition(params),
+            "textDocument/completion" => self.completion(params),
+            "textDocument/documentSymbol" => self.document_symbols(params),
+            _ => {
+                let error = json!({"code": -32601, "message": format!("unknown method {}", method)});
+                return self.send(json!({"jsonrpc": "2.0", "id": id, "error": error}));
+            }
+        };
+        self.send(json!({"jsonrpc": "2.0", "id": id, "result": result}))
+    }
+
+    /// replaces the text of a document, publishing its diagnostics
+    fn update(&mut self, uri: String, text: String) -> io::Result<()> {
+        let document = self.documents.entry(uri.clone()).or_insert(Document {
+            text: String::new(),
+            analysis: None,
+        });
+
+        let diagnostics: Vec<Value> = match PuffinParser::parse_program(&text) {
+            Ok(program) => {
+                let analysis = Analysis::new(&text, &program);
+                let diagnostics = checker::check_program(&program)
+                    .into_iter()
+                    .map(|diagnostic| {
+                        let severity = match diagnostic.severity {
+                            checker::Severity::Error => 1,
+                            checker::Severity::Warning => 2,
+                        };
+                        diagnostic_json(&text, diagnostic.span, severity, diagnostic.message)
+                    })
+                    .collect();
+                document.analysis = Some((text.clone(), analysis));
+                diagnostics
+            }
+            Err(errors) => errors
+                .into_iter()
+                .map(|err| {
+                    let message = match err.hint {
+                        Some(hint) => format!("{}\nhint: {}", err.message, hint),
+                        None => err.message,
+                    };
+                    diagnostic_json(&text, err.span, 1, message)
+                })
+                .collect(),
+        };
+        document.text = text;
+
+        self.notify(
+            "textDocument/publishDiagnostics",
+            json!({"uri": uri, "diagnostics": diagnostics}),
+        )
+    }
+
+    /// returns the analysis of the document in `params`, and the byte offset of the position
+    /// in `params` within the analyzed text
+    fn locate(&self, params: &Value) -> Option<(&str, &Analysis, usize)> {
+        let document = self.documents.get(&uri(&params["textDocument"]))?;
+        let (analyzed, analysis) = document.analysis.as_ref()?;
+        let position = &params["position"];
+        let pos = offset(
+            analyzed,
+            position["line"].as_u64()? as usize,
+            position["character"].as_u64()? as usize,
+        );
+        Some((analyzed, analysis, pos))
+    }
+
+    fn hover(&self, params: &Value) -> Value {
+        match self.locate(params).and_then(|(_, analysis, pos)| analysis.symbol_at(pos)) {
+            Some(symbol) => {
+                let mut value = format!("```puffin\n{}\n```", symbol.detail);
+                if let Some(doc) = &symbol.doc {
+                    value.push_str("\n\n");
+                    value.push_str(doc);
+                }
+                json!({"contents": {"kind": "markdown", "value": value}})
+            }
+            None => Value::Null,
+        }
+    }
+
+    fn definition(&self, params: &Value) -> Value {
+        let (text, analysis, pos) = match self.locate(params) {
+            Some(located) => located,
+            None => return Value::Null,
+        };
+        match analysis.symbol_at(pos).and_then(|symbol| symbol.span) {
+            Some(span) => json!({
+                "uri": uri(&params["textDocument"]),
+                "range": range(text, span),
+            }),
+            None => Value::Null,
+        }
+    }
+
+    fn completion(&self, params: &Value) -> Value {
+        let (analysis, pos) = match self.locate(params) {
+            Some((_, analysis, pos)) => (analysis, pos),
+            None => return json!([]),
+        };
+        let document = &self.documents[&uri(&params["textDocument"])];
+        let position = &params["position"];
+        let cursor = offset(
+            &document.text,
+            position["line"].as_u64().unwrap_or_default() as usize,
+            position["character"].as_u64().unwrap_or_default() as usize,
+        );
+
+        // after `name.`, complete the fields of the structure bound to name
+        let before = &document.text[..cursor];
+        let word = before.trim_end_matches(|c: char| c.is_ascii_alphanumeric() || c == '_');
+        if let Some(receiver) = word.strip_suffix('.') {
+            let name_start = receiver
+                .char_indices()
+                .rev()
+                .find(|(_, c)| !(c.is_ascii_alphanumeric() || *c == '_'))
+                .map_or(0, |(i, c)| i + c.len_utf8());
+            let fields = analysis
+                .resolve(&receiver[name_start..], pos)
+                .map(|symbol| symbol.fields.clone())
+                .unwrap_or_default();
+            let items: Vec<Value> = fields
+                .iter()
+                .map(|field| json!({"label": field, "kind": 5}))
+                .collect();
+            return json!(items);
+        }
+
+        let mut items: Vec<Value> = analysis
+            .visible(pos)
+            .into_iter()
+            .map(|symbol| {
+                let kind = match symbol.kind {
+                    SymbolKind::Function => 3,
+                    SymbolKind::Builtin if symbol.detail.starts_with("fn ") => 3,
+                    SymbolKind::Builtin => 21,
+                    SymbolKind::Variable | SymbolKind::Argument | SymbolKind::Loop => 6,
+                };
+                let mut item = json!({"label": symbol.name, "kind": kind, "detail": symbol.detail});
+                if let Some(doc) = &symbol.doc {
+                    item["documentation"] = json!(doc);
+                }
+                item
+            })
+            .collect();
+        for keyword in &["fn", "if", "else", "while", "for", "in", "return", "null"] {
+            items.push(json!({"label": keyword, "kind": 14}));
+        }
+        json!(items)
+    }
+
+    fn document_symbols(&self, params: &Value) -> Value {
+        let document = match self.documents.get(&uri(&params["textDocument"])) {
+            Some(document) => document,
+            None => return json!([]),
+        };
+        let (text, analysis) = match &document.analysis {
+            Some((text, analysis)) => (text, analysis),
+            None => return json!([]),
+        };
+
+        let symbols: Vec<Value> = analysis
+            .functions()
+            .into_iter()
+            .filter_map(|symbol| {
+                Some(json!({
+                    "name": symbol.name,
+                    "detail": symbol.detail,
+                    "kind": 12,
+                    "range": range(text, symbol.statement?),
+                    "selectionRange": range(text, symbol.span?),
+                }))
+            })
+            .collect();
+        json!(symbols)
+    }
+
+    fn notify(&mut self, method: &str, params: Value) -> io::Result<()> {
+        self.send(json!({"jsonrpc": "2.0", "method": method, "params": params}))
+    }
+
+    fn send(&mut self, message: Value) -> io::Result<()> {
+        write_message(&mut self.output, &message)
+    }
+}
+
+/// Writes a message framed by a `Content-Length` header
+pub(crate) fn write_message<W: Write>(output: &mut W, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+fn uri(document: &Value) -> String {
+    text(&document["uri"])
+}
+
+fn text(value: &Value) -> String {
+    value.as_str().unwrap_or_default().to_string()
+}
+
+fn diagnostic_json(text: &str, span: Span, severity: u8, message: String) -> Value {
+    json!({
+        "range": range(text, span),
+        "severity": severity,
+        "source": "puffin",
+        "message": message,
+    })
+}
+
+/// returns the LSP range of a span of `text`
+fn range(text: &str, span: Span) -> Value {
+    json!({"start": position(text, span.start), "end": position(text, span.end)})
+}
+
+/// returns the LSP position of byte offset `pos` in `text`,
+/// a line and a character counted in UTF-16 code units, both starting at 0
+fn position(text: &str, pos: usize) -> Value {
+    let pos = pos.min(text.len());
+    let line_start = text[..pos].rfind('\n').map_or(0, |i| i + 1);
+    json!({
+        "line": text[..pos].matches('\n').count(),
+        "character": text[line_start..pos].encode_utf16().count(),
+    })
+}
+
+/// returns the byte offset in `text` of an LSP position, clamped to the end of its line
+fn offset(text: &str, line: usize, character: usize) -> usize {
+    let line_start = match line {
+        0 => 0,
+        _ => match text.match_indices('\n').nth(line - 1) {
+            Some((i, _)) => i + 1,
+            None => return text.len(),
+        },
+    };
+
+    let mut units = 0;
+    for (i, c) in text[line_start..].char_indices() {
+        if units >= character || c == '\n' {
+            return line_start + i;
+        }
+        units += c.len_utf16();
+    }
+    text.len()
+}
@@ -1,32 +1,76 @@
 //! Author: Rafael Bayer (2021)
 //! Main Entrypoint of the Puffin Interpreter Binary
 
-use puffin::{interpreter::value::Value, repl};
+use puffin::{
+    cli::{self, Command},
+    interpreter::{value::Value, InterpreterError},
+    repl, Error,
+};
 use std::{env, process};
 
 /// Main with no arguments begins a Puffin REPL session.
-/// If a filepath is passed, the interpreter will instead execute the contained program,
-/// outputting a non-null top-level return to stdout. The interpreter program will terminate
-/// when the program does.
+/// Otherwise, the arguments are parsed into a `Command` (see `puffin --help`).
+/// Running a program outputs a non-null top-level return to stdout. The interpreter
+/// program will terminate when the program does, with a non-zero exit code on errors.
 fn main() {
     let args: Vec<String> = env::args().collect();
-    
-    // if no additional arguments are passed, star the Puffin REPL
-    if args.len() == 1 {
-        repl::start_repl();
-    }
 
-    // otherwise, we parse the args into config, and run
-    let config = puffin::Config::new(&args).unwrap_or_else(|err| {
+    let command = Command::new(&args).unwrap_or_else(|err| {
         eprintln!("Argument Parsing Error: {}", err);
+        eprintln!("Try `puffin --help` for usage");
         process::exit(1);
     });
 
-    let value = puffin::run(config);
-    // if program value is null, we don't print it
-    if let Value::Null = value {
-        return;
+    let result = match command {
+        Command::Repl => repl::start_repl(),
+        Command::Check(source) => puffin::check(&source).map(|passed| {
+            if !passed {
+                process::exit(1);
+            }
+        }),
+        Command::Test {
+            paths,
+            filter,
+            coverage,
+        } => puffin::test(&paths, filter.as_deref(), coverage.as_deref()).map(|passed| {
+            if !passed {
+                process::exit(1);
+            }
+        }),
+        Command::Ast(source) => puffin::print_ast(&source),
+        Command::Tokens(source) => puffin::print_tokens(&source),
+        Command::Fmt { sources, check } => puffin::fmt(&sources, check).map(|formatted| {
+            if !formatted {
+                process::exit(1);
+            }
+        }),
+        Command::Help => {
+            print!("{}", cli::HELP);
+            Ok(())
+        }
+        Command::Version => {
+            println!("puffin {}", cli::VERSION);
+            Ok(())
+        }
+        Command::Run(config) => puffin::run(config).map(print_value),
+        Command::Debug(config) => puffin::debug(config).map(print_value),
+    };
+
+    // errors are printed to stderr with a non-zero exit code,
+    // unless the program chose its own exit code
+    match result {
+        Ok(()) => {}
+        Err(Error::Runtime(InterpreterError::Exit(code))) => process::exit(code),
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
     }
-    println!("{}", value)
+}
 
+/// prints the final output of a program, unless it is null
+fn print_value(value: Value) {
+    if !matches!(value, Value::Null) {
+        println!("{}", value);
+    }
 }
@@ -0,0 +1,84 @@
+//! Author: Rafael Bayer (2021)
+//! The error module defines `ParseError`, a readable parse error.
+//!
+//! `ParseError` renders its message alongside the offending source line,
+//! with a caret pointing at the start of the error, and an optional hint
+//! for fixing common mistakes, such as a missing `;` after a function.
+
+use std::fmt::Display;
+
+use super::Span;
+
+/// Readable parse error, pointing at a span of the source
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    /// What went wrong
+    pub message: String,
+    /// Location of the error in the source
+    pub span: Span,
+    /// Source line containing the start of the error
+    pub source_line: String,
+    /// Suggestion for fixing a common mistake
+    pub hint: Option<String>,
+}
+
+impl ParseError {
+    /// Creates a ParseError with a given message covering `span` of `source`
+    pub fn new(message: String, span: Span, source: &str) -> ParseError {
+        let line_start = source[..span.start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[span.start..]
+            .find('\n')
+            .map_or(source.len(), |i| span.start + i);
+
+        ParseError {
+            message,
+            span,
+            source_line: source[line_start..line_end].trim_end_matches('\r').to_string(),
+            hint: None,
+        }
+    }
+
+    /// Creates a ParseError with a given message at byte offset `pos` in `source`,
+    /// spanning the character at `pos`, if any
+    pub fn at(message: String, pos: usize, source: &str) -> ParseError {
+        let pos = pos.min(source.len());
+        let end = match source[pos..].chars().next() {
+            Some(c) if c != '\n' => pos + c.len_utf8(),
+            _ => pos,
+        };
+
+        Self::new(message, Span::new(source, pos, end), source)
+    }
+
+    /// Adds a hint to this error
+    pub fn with_hint(mut self, hint: &str) -> ParseError {
+        self.hint = Some(hint.to_string());
+        self
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let line = self.span.line.to_string();
+        let pad = " ".repeat(line.len());
+        // keep tabs, so the caret lines up with the source line
+        let caret: String = self
+            .source_line
+            .chars()
+            .take(self.span.col - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+
+        writeln!(f, "{}", self.message)?;
+        writeln!(f, "{}--> {}:{}", pad, self.span.line, self.span.col)?;
+        writeln!(f, "{} |", pad)?;
+        writeln!(f, "{} | {}", line, self.source_line)?;
+        write!(f, "{} | {}^", pad, caret)?;
+        if let Some(hint) = &self.hint {
+            write!(f, "\n{} = hint: {}", pad, hint)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ParseError {}
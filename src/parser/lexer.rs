@@ -0,0 +1,346 @@
+//! Author: Rafael Bayer (2021)
+//! The lexer module splits the source of a program into tokens.
+//!
+//! Whitespace is skipped, and comments (anything following `//` until the end of the line)
+//! are kept apart from the tokens.
+//! Tokens only record their kind and span, their text is sliced from the source when needed.
+
+use super::{ParseError, Span};
+use crate::ast::node::Comment;
+
+/// Kind of a token
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    /// Number literal, like `5` or `0.5`
+    Num,
+    /// String literal, like `"hello"`
+    String,
+    /// Name that isn't a keyword
+    Name,
+
+    // keywords
+    Fn,
+    If,
+    Else,
+    While,
+    For,
+    In,
+    Return,
+    Null,
+
+    // delimiters
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    LBrace,
+    RBrace,
+    Comma,
+    Colon,
+    Semicolon,
+    Dot,
+
+    // operators
+    Assign,
+    Arrow,
+    ThinArrow,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    Bang,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+
+    /// End of the source
+    Eof,
+}
+
+/// Token of a program, the kind of token and where it is in the source
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+impl TokenKind {
+    /// Describes the kind of token for error messages, for example "`(`" or "a name"
+    pub fn describe(&self) -> &'static str {
+        match self {
+            TokenKind::Num => "a number",
+            TokenKind::String => "a string",
+            TokenKind::Name => "a name",
+            TokenKind::Fn => "`fn`",
+            TokenKind::If => "`if`",
+            TokenKind::Else => "`else`",
+            TokenKind::While => "`while`",
+            TokenKind::For => "`for`",
+            TokenKind::In => "`in`",
+            TokenKind::Return => "`return`",
+            TokenKind::Null => "`null`",
+            TokenKind::LParen => "`(`",
+            TokenKind::RParen => "`)`",
+            TokenKind::LBracket => "`[`",
+            TokenKind::RBracket => "`]`",
+            TokenKind::LBrace => "`{`",
+            TokenKind::RBrace => "`}`",
+            TokenKind::Comma => "`,`",
+            TokenKind::Colon => "`:`",
+            TokenKind::Semicolon => "`;`",
+            TokenKind::Dot => "`.`",
+            TokenKind::Assign => "`=`",
+            TokenKind::Arrow => "`=>`",
+            TokenKind::ThinArrow => "`->`",
+            TokenKind::Plus => "`+`",
+            TokenKind::Minus => "`-`",
+            TokenKind::Star => "`*`",
+            TokenKind::Slash => "`/`",
+            TokenKind::Percent => "`%`",
+            TokenKind::Bang => "`!`",
+            TokenKind::Eq => "`==`",
+            TokenKind::Ne => "`!=`",
+            TokenKind::Lt => "`<`",
+            TokenKind::Le => "`<=`",
+            TokenKind::Gt => "`>`",
+            TokenKind::Ge => "`>=`",
+            TokenKind::And => "`&&`",
+            TokenKind::Or => "`||`",
+            TokenKind::Eof => "end of input",
+        }
+    }
+
+    /// Returns true if this kind of token is a keyword
+    pub fn is_keyword(&self) -> bool {
+        matches!(
+            self,
+            TokenKind::Fn
+                | TokenKind::If
+                | TokenKind::Else
+                | TokenKind::While
+                | TokenKind::For
+                | TokenKind::In
+                | TokenKind::Return
+                | TokenKind::Null
+        )
+    }
+}
+
+/// Returns the keyword spelled by `name`, if any
+pub fn keyword(name: &str) -> Option<TokenKind> {
+    Some(match name {
+        "fn" => TokenKind::Fn,
+        "if" => TokenKind::If,
+        "else" => TokenKind::Else,
+        "while" => TokenKind::While,
+        "for" => TokenKind::For,
+        "in" => TokenKind::In,
+        "return" => TokenKind::Return,
+        "null" => TokenKind::Null,
+        _ => return None,
+    })
+}
+
+/// Tokens and comments of a program, along with any errors splitting it
+#[derive(Debug, Clone)]
+pub struct Lexed {
+    /// Tokens, ending with an `Eof` token
+    pub tokens: Vec<Token>,
+    pub comments: Vec<Comment>,
+    pub errors: Vec<ParseError>,
+}
+
+/// Splits `source` into tokens and comments.
+/// Characters that don't start a token are reported as errors and skipped.
+pub fn tokenize(source: &str) -> Lexed {
+    let mut lexer = Lexer {
+        source,
+        pos: 0,
+        line: 1,
+        col: 1,
+        comments: Vec::new(),
+        errors: Vec::new(),
+    };
+    let mut tokens = Vec::new();
+
+    loop {
+        lexer.skip_trivia();
+        let (start, line, col) = (lexer.pos, lexer.line, lexer.col);
+        let kind = match lexer.token() {
+            Some(kind) => kind,
+            None => continue,
+        };
+
+        tokens.push(Token {
+            kind,
+            span: Span {
+                start,
+                end: lexer.pos,
+                line,
+                col,
+                end_line: lexer.line,
+            },
+        });
+        if kind == TokenKind::Eof {
+            return Lexed {
+                tokens,
+                comments: lexer.comments,
+                errors: lexer.errors,
+            };
+        }
+    }
+}
+
+/// Position in the source being split into tokens
+struct Lexer<'a> {
+    source: &'a str,
+    pos: usize,
+    line: usize,
+    col: usize,
+    comments: Vec<Comment>,
+    errors: Vec<ParseError>,
+}
+
+impl Lexer<'_> {
+    fn peek(&self) -> Option<char> {
+        self.source[self.pos..].chars().next()
+    }
+
+    fn peek_second(&self) -> Option<char> {
+        self.source[self.pos..].chars().nth(1)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.col = 1;
+        } else {
+            self.col += 1;
+        }
+        Some(c)
+    }
+
+    /// consumes characters while `f` holds
+    fn bump_while(&mut self, f: impl Fn(char) -> bool) {
+        while self.peek().is_some_and(&f) {
+            self.bump();
+        }
+    }
+
+    /// consumes `next` if it is the next character
+    fn bump_if(&mut self, next: char) -> bool {
+        if self.peek() == Some(next) {
+            self.bump();
+            return true;
+        }
+        false
+    }
+
+    fn skip_trivia(&mut self) {
+        loop {
+            self.bump_while(char::is_whitespace);
+            if !self.source[self.pos..].starts_with("//") {
+                return;
+            }
+
+            let (start, line, col) = (self.pos, self.line, self.col);
+            self.bump_while(|c| c != '\n');
+            self.comments.push(Comment {
+                text: self.source[start..self.pos].trim_end().to_string(),
+                span: Span {
+                    start,
+                    end: self.pos,
+                    line,
+                    col,
+                    end_line: line,
+                },
+            });
+        }
+    }
+
+    /// consumes the next token, returning its kind.
+    /// Errors are recorded, returning the token that was likely intended, if any
+    fn token(&mut self) -> Option<TokenKind> {
+        let start = self.pos;
+        let c = match self.bump() {
+            Some(c) => c,
+            None => return Some(TokenKind::Eof),
+        };
+
+        Some(match c {
+            '0'..='9' => {
+                self.bump_while(|c| c.is_ascii_digit());
+                // a fraction needs digits after the point, otherwise the point is a `.`
+                if self.peek() == Some('.') && self.peek_second().is_some_and(|c| c.is_ascii_digit()) {
+                    self.bump();
+                    self.bump_while(|c| c.is_ascii_digit());
+                }
+                TokenKind::Num
+            }
+            'a'..='z' | 'A'..='Z' => {
+                self.bump_while(|c| c.is_ascii_alphanumeric() || c == '_');
+                keyword(&self.source[start..self.pos]).unwrap_or(TokenKind::Name)
+            }
+            '"' => {
+                self.bump_while(|c| c != '"');
+                if !self.bump_if('"') {
+                    self.error("unterminated string", start);
+                }
+                TokenKind::String
+            }
+            '(' => TokenKind::LParen,
+            ')' => TokenKind::RParen,
+            '[' => TokenKind::LBracket,
+            ']' => TokenKind::RBracket,
+            '{' => TokenKind::LBrace,
+            '}' => TokenKind::RBrace,
+            ',' => TokenKind::Comma,
+            ':' => TokenKind::Colon,
+            ';' => TokenKind::Semicolon,
+            '.' => TokenKind::Dot,
+            '+' => TokenKind::Plus,
+            '-' if self.bump_if('>') => TokenKind::ThinArrow,
+            '-' => TokenKind::Minus,
+            '*' => TokenKind::Star,
+            '/' => TokenKind::Slash,
+            '%' => TokenKind::Percent,
+            '=' if self.bump_if('=') => TokenKind::Eq,
+            '=' if self.bump_if('>') => TokenKind::Arrow,
+            '=' => TokenKind::Assign,
+            '!' if self.bump_if('=') => TokenKind::Ne,
+            '!' => TokenKind::Bang,
+            '<' if self.bump_if('=') => TokenKind::Le,
+            '<' => TokenKind::Lt,
+            '>' if self.bump_if('=') => TokenKind::Ge,
+            '>' => TokenKind::Gt,
+            '&' => {
+                if !self.bump_if('&') {
+                    self.error("expected `&&`", start);
+                }
+                TokenKind::And
+            }
+            '|' => {
+                if !self.bump_if('|') {
+                    self.error("expected `||`", start);
+                }
+                TokenKind::Or
+            }
+            other => {
+                self.error(&format!("unexpected character `{}`", other), start);
+                return None;
+            }
+        })
+    }
+
+    fn error(&mut self, message: &str, pos: usize) {
+        self.errors.push(ParseError::at(message.to_string(), pos, self.source));
+    }
+}
@@ -1,47 +1,737 @@
 //! Author: Rafael Bayer (2021)
 //! This module includes the definition of the PuffinParser.
-//! 
+//!
 //! The PuffinParser is responsible for parsing raw string input of
-//! a program into the programs parse tree
+//! a program into the programs AST. The source is first split into tokens by the `lexer`,
+//! then statements are parsed by recursive descent, and expressions by precedence climbing.
+//!
+//! The parser also expands certain syntactic sugars into their full representation within the AST,
+//! such as augmented assignments and lambdas.
 
-use pest::{Parser, error::Error, iterators::Pairs};
+use crate::ast::node::*;
+use lexer::{Token, TokenKind};
 
-extern crate pest;
+mod error;
+pub mod lexer;
+mod recovery;
+pub use error::ParseError;
 
-/// `PuffinParser`: PEG based recursive descent parser, automatically generated by Pest.
-/// Grammar file: `puffin.pest`
-#[derive(Parser)]
-#[grammar = "puffin.pest"]
-pub struct PuffinParser;
+/// Location of a part of the source, as byte offsets along with
+/// the line and column of the start, and the line of the end, all starting at 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub col: usize,
+    pub end_line: usize,
+}
+
+impl Span {
+    /// Creates the Span of bytes `start..end` of `source`
+    pub fn new(source: &str, start: usize, end: usize) -> Span {
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line = source[..start].matches('\n').count() + 1;
+        Span {
+            start,
+            end,
+            line,
+            col: source[line_start..start].chars().count() + 1,
+            end_line: line + source[start..end].matches('\n').count(),
+        }
+    }
+
+    /// Returns the Span from the start of this span to the end of `other`
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            end: other.end,
+            end_line: other.end_line,
+            ..self
+        }
+    }
+}
+
+/// `PuffinParser`: hand-written recursive descent parser, producing the AST of a program.
+/// If the program has errors, parsing recovers at statement boundaries to report every error.
+pub struct PuffinParser<'a> {
+    source: &'a str,
+    tokens: Vec<Token>,
+    comments: Vec<Comment>,
+    /// index of the next token
+    pos: usize,
+    errors: Vec<ParseError>,
+}
+
+impl<'a> PuffinParser<'a> {
+    /// Creates a parser for `source`
+    fn new(source: &'a str) -> PuffinParser<'a> {
+        let lexed = lexer::tokenize(source);
+        PuffinParser {
+            source,
+            tokens: lexed.tokens,
+            comments: lexed.comments,
+            pos: 0,
+            errors: lexed.errors,
+        }
+    }
+
+    /// Parses a program into its AST.
+    /// If the program has errors, returns every error found, in order.
+    pub fn parse_program(source: &str) -> Result<Program, Vec<ParseError>> {
+        let mut parser = PuffinParser::new(source);
+        let program = parser.statements(false);
+
+        if parser.errors.is_empty() {
+            return Ok(Program {
+                program,
+                comments: parser.comments,
+            });
+        }
+        parser.errors.sort_by_key(|err| err.span.start);
+        Err(parser.errors)
+    }
+
+    /****************** Statements ******************/
+
+    /// parses statements until the end of the block (or program),
+    /// recording errors and recovering from them
+    fn statements(&mut self, in_block: bool) -> Vec<Statement> {
+        let mut statements = Vec::new();
+        loop {
+            match self.peek().kind {
+                TokenKind::Eof => return statements,
+                TokenKind::RBrace if in_block => return statements,
+                _ => {}
+            }
+
+            let start = self.pos;
+            match self.statement() {
+                Ok(statement) => statements.push(statement),
+                Err(err) => {
+                    self.errors.push(err);
+                    self.synchronize(start, in_block);
+                }
+            }
+        }
+    }
+
+    fn statement(&mut self) -> Result<Statement, ParseError> {
+        let start = self.peek();
+        let statement = match start.kind {
+            TokenKind::Return => {
+                self.advance();
+                let exp = self.exp()?;
+                self.end_statement(None)?;
+                StatementKind::Return(exp)
+            }
+            TokenKind::If | TokenKind::While | TokenKind::For => StatementKind::Nest(self.nest()?),
+            kind if starts_exp(kind) => {
+                let statement = self.simple_statement()?;
+                self.end_statement(Some(&statement))?;
+                statement
+            }
+            _ => return Err(self.expected("a statement")),
+        };
+
+        Ok(Statement {
+            statement,
+            span: start.span.to(self.prev_span()),
+        })
+    }
+
+    /// parses an assignment or expression statement, without the trailing `;`
+    fn simple_statement(&mut self) -> Result<StatementKind, ParseError> {
+        let exp = self.exp()?;
+
+        // a name can be annotated with a type, only when assigned
+        let mut annotation = None;
+        if matches!(exp.exp, ExpKind::Value(ValueKind::Name(_))) && self.eat(TokenKind::Colon).is_some() {
+            annotation = Some(self.ty()?);
+            if self.peek().kind != TokenKind::Assign {
+                return Err(self.expected("`=` after type annotation"));
+            }
+        }
+
+        // assignment, can be augmented by certain operators
+        let aug = match self.peek().kind {
+            TokenKind::Assign => None,
+            kind if aug_op(kind).is_some() && self.nth(1).kind == TokenKind::Assign => {
+                self.advance();
+                aug_op(kind)
+            }
+            _ => return Ok(StatementKind::Exp(exp)),
+        };
+        self.advance();
+
+        let lhs = self.assignable(&exp)?;
+        let mut rhs = self.exp()?;
+        if let Some(op) = &aug {
+            // final statement expands
+            // from: a op= b;
+            // to:   a = a op b;
+            let span = exp.span.to(rhs.span);
+            rhs = Exp {
+                exp: ExpKind::Infix(op.clone(), Box::new(exp), Box::new(rhs)),
+                span,
+            };
+        }
+
+        Ok(StatementKind::Assign {
+            lhs,
+            rhs,
+            aug,
+            annotation,
+        })
+    }
+
+    /// Consumes the `;` ending a statement.
+    /// A missing `;` is recorded, but the statement is complete, so parsing continues after it.
+    fn end_statement(&mut self, statement: Option<&StatementKind>) -> Result<(), ParseError> {
+        if self.eat(TokenKind::Semicolon).is_some() {
+            return Ok(());
+        }
+
+        let next = self.peek();
+        if let TokenKind::RParen | TokenKind::RBracket | TokenKind::Comma | TokenKind::Colon = next.kind {
+            return Err(self.error(format!("unexpected {}", next.kind.describe()), next.span));
+        }
+
+        let mut err = self.expected_after("`;` after expression");
+        if let Some(StatementKind::Assign { rhs, .. }) = statement {
+            if matches!(rhs.exp, ExpKind::Value(ValueKind::FunctionDef { lambda: false, .. })) {
+                err = err.with_hint("assigning a function is a statement, add `;` after its closing `}`");
+            }
+        }
+        self.errors.push(err);
+        Ok(())
+    }
+
+    /// converts the left hand side of an assignment to an Assignable,
+    /// a name followed by any number of subscripts or structure fields.
+    /// For example, `a[5].b` => `a`, `[[5], .b]`
+    fn assignable(&self, exp: &Exp) -> Result<Assignable, ParseError> {
+        match &exp.exp {
+            ExpKind::Value(ValueKind::Name(name)) => Ok(Assignable {
+                name: name.clone(),
+                assignable: Vec::new(),
+            }),
+            ExpKind::Postfix(inner, op @ (PostOp::Subscript(_) | PostOp::Dot(_))) => {
+                let mut assignable = self.assignable(inner)?;
+                assignable.assignable.push(match op {
+                    PostOp::Subscript(index) => AssignableKind::ArrayIndex {
+                        index: (**index).clone(),
+                    },
+                    PostOp::Dot(field) => AssignableKind::StructureField {
+                        field: field.clone(),
+                    },
+                    PostOp::Call(_) => unreachable!(),
+                });
+                Ok(assignable)
+            }
+            _ => Err(self.error("can't assign to this expression".to_string(), exp.span)),
+        }
+    }
+
+    /// parses conditionals and loops
+    fn nest(&mut self) -> Result<NestKind, ParseError> {
+        Ok(match self.advance().kind {
+            TokenKind::If => {
+                self.expect(TokenKind::LParen)?;
+                let cond = self.exp()?;
+                self.expect_after(TokenKind::RParen)?;
+                let then = self.block()?;
+
+                NestKind::CondNest(if self.eat(TokenKind::Else).is_some() {
+                    CondNestKind::IfElse {
+                        cond,
+                        then,
+                        or_else: self.block()?,
+                    }
+                } else {
+                    CondNestKind::If { cond, then }
+                })
+            }
+            TokenKind::While => {
+                let cond = self.exp()?;
+                let block = self.block()?;
+                NestKind::LoopNest(LoopNestKind::While { cond, block })
+            }
+            TokenKind::For => {
+                self.expect(TokenKind::LParen)?;
+                NestKind::LoopNest(if self.nth(1).kind == TokenKind::In {
+                    // for-in/foreach
+                    let name = self.name()?;
+                    self.advance();
+                    let array = self.exp()?;
+                    self.expect_after(TokenKind::RParen)?;
+                    LoopNestKind::ForIn {
+                        name,
+                        array,
+                        block: self.block()?,
+                    }
+                } else {
+                    // traditional C-style for-loop
+                    let init = self.statement()?;
+                    let cond = self.exp()?;
+                    self.expect_after(TokenKind::Semicolon)?;
+
+                    // "adv" (usually the i++ part) of the loop can be either an expression, or an assignment.
+                    // either way, we wrap it in a statement
+                    let start = self.peek().span;
+                    let adv = Statement {
+                        statement: self.simple_statement()?,
+                        span: start.to(self.prev_span()),
+                    };
+                    self.expect_after(TokenKind::RParen)?;
+
+                    LoopNestKind::For {
+                        init: Box::new(init),
+                        cond,
+                        adv: Box::new(adv),
+                        block: self.block()?,
+                    }
+                })
+            }
+            _ => unreachable!("nests start with `if`, `while` or `for`"),
+        })
+    }
+
+    fn block(&mut self) -> Result<Block, ParseError> {
+        let start = self.expect(TokenKind::LBrace)?.span;
+        let block = self.statements(true);
+        // statements only stop before a `}`, or at the end of input
+        if self.eat(TokenKind::RBrace).is_none() {
+            return Err(self.expected("a statement"));
+        }
+
+        Ok(Block {
+            block,
+            span: start.to(self.prev_span()),
+        })
+    }
+
+    /****************** Expressions ******************/
+
+    fn exp(&mut self) -> Result<Exp, ParseError> {
+        self.infix(0)
+    }
+
+    /// parses an expression of infix operators with at least `min_prec` precedence
+    fn infix(&mut self, min_prec: usize) -> Result<Exp, ParseError> {
+        let mut lhs = self.unary()?;
+
+        while let Some(op) = infix_op(self.peek().kind) {
+            // `a + = b` is an augmented assignment, not an addition
+            let aug = aug_op(self.peek().kind).is_some() && self.nth(1).kind == TokenKind::Assign;
+            let prec = op.precedence();
+            if prec < min_prec || aug {
+                break;
+            }
+            self.advance();
+
+            let rhs = self.infix(prec + 1)?;
+            let span = lhs.span.to(rhs.span);
+            lhs = Exp {
+                exp: ExpKind::Infix(op, Box::new(lhs), Box::new(rhs)),
+                span,
+            };
+        }
+
+        Ok(lhs)
+    }
+
+    /// parses a unary (prefix) operator, which binds tighter than infix operators.
+    /// Operators don't stack, `--x` is written `-(-x)`
+    fn unary(&mut self) -> Result<Exp, ParseError> {
+        let op = match self.peek().kind {
+            TokenKind::Bang => Unop::Not,
+            TokenKind::Minus => Unop::Neg,
+            _ => return self.postfix(),
+        };
+
+        let start = self.advance().span;
+        if matches!(self.peek().kind, TokenKind::Bang | TokenKind::Minus) {
+            return Err(self
+                .expected("an expression")
+                .with_hint("prefix operators can't be repeated, add parentheses such as `-(-x)`"));
+        }
+        let operand = self.postfix()?;
+        Ok(Exp {
+            span: start.to(operand.span),
+            exp: ExpKind::Unary(op, Box::new(operand)),
+        })
+    }
+
+    /// parses postfix operators, which have the highest precedence
+    fn postfix(&mut self) -> Result<Exp, ParseError> {
+        let mut exp = self.value()?;
+
+        loop {
+            let op = match self.peek().kind {
+                // array subscripting
+                TokenKind::LBracket => {
+                    self.advance();
+                    let index = self.exp()?;
+                    self.expect_after(TokenKind::RBracket)?;
+                    PostOp::Subscript(Box::new(index))
+                }
+                // function call
+                TokenKind::LParen => {
+                    self.advance();
+                    PostOp::Call(self.list(TokenKind::RParen, Self::exp)?)
+                }
+                // structure access
+                TokenKind::Dot => {
+                    self.advance();
+                    PostOp::Dot(self.name()?)
+                }
+                _ => return Ok(exp),
+            };
+
+            let span = exp.span.to(self.prev_span());
+            exp = Exp {
+                exp: ExpKind::Postfix(Box::new(exp), op),
+                span,
+            };
+        }
+    }
+
+    fn value(&mut self) -> Result<Exp, ParseError> {
+        let token = self.peek();
+        let value = match token.kind {
+            TokenKind::Num => {
+                self.advance();
+                match self.text(token).parse() {
+                    Ok(n) => ValueKind::Num(n),
+                    Err(_) => return Err(self.error("invalid number".to_string(), token.span)),
+                }
+            }
+            TokenKind::String => {
+                self.advance();
+                // trim the quote literals, an unterminated string has no closing quote
+                let text = &self.text(token)[1..];
+                ValueKind::String(text.strip_suffix('"').unwrap_or(text).to_string())
+            }
+            TokenKind::Name => ValueKind::Name(self.name()?),
+            TokenKind::Null => {
+                self.advance();
+                ValueKind::Null
+            }
+            TokenKind::LParen => {
+                self.advance();
+                let exp = self.exp()?;
+                self.expect_after(TokenKind::RParen)?;
+                ValueKind::Paren(Box::new(exp))
+            }
+            TokenKind::LBrace => self.structure()?,
+            TokenKind::LBracket => self.array_init()?,
+            TokenKind::Fn => self.function()?,
+            _ => return Err(self.expected("an expression")),
+        };
+
+        Ok(Exp {
+            exp: ExpKind::Value(value),
+            span: token.span.to(self.prev_span()),
+        })
+    }
+
+    /// structures are composed of fields, optionally separated by commas
+    fn structure(&mut self) -> Result<ValueKind, ParseError> {
+        self.advance();
+        let mut fields = Vec::new();
+
+        while self.eat(TokenKind::RBrace).is_none() {
+            // each field is a name, followed by its value
+            let start = self.peek();
+            // keywords can't be field names, which `name` reports
+            if start.kind.is_keyword() {
+                self.name()?;
+            }
+            if start.kind != TokenKind::Name || self.nth(1).kind != TokenKind::Colon {
+                return Err(self.error("expected a structure field".to_string(), start.span));
+            }
+            let name = self.name()?;
+            self.advance();
+            fields.push(Field {
+                name,
+                exp: self.exp()?,
+            });
+
+            // a comma must be followed by another field
+            if self.eat(TokenKind::Comma).is_some() && self.peek().kind == TokenKind::RBrace {
+                return Err(self.expected("a structure field"));
+            }
+        }
+
+        Ok(ValueKind::Structure(fields))
+    }
+
+    /// array initialization, either sized (`[n]`) or a range (`[a:b]`)
+    fn array_init(&mut self) -> Result<ValueKind, ParseError> {
+        self.advance();
+        let from = self.exp()?;
+
+        let init = if self.eat(TokenKind::Colon).is_some() {
+            let to = self.exp()?;
+            ArrayInitKind::Range(Box::new(from), Box::new(to))
+        } else {
+            ArrayInitKind::Sized(Box::new(from))
+        };
+        self.expect_after(TokenKind::RBracket)?;
+
+        Ok(ValueKind::ArrayInit(init))
+    }
+
+    /// function definition, "fn" followed by 0 or more arg names followed by function body,
+    /// either a block or a single expression (a lambda)
+    fn function(&mut self) -> Result<ValueKind, ParseError> {
+        self.advance();
+        self.expect(TokenKind::LParen)?;
+        let names = self.list(TokenKind::RParen, |parser| {
+            let span = parser.peek().span;
+            let name = parser.name()?;
+            let annotation = match parser.eat(TokenKind::Colon) {
+                Some(_) => Some(parser.ty()?),
+                None => None,
+            };
+            Ok((name, annotation, span))
+        })?;
+
+        // check for duplicate args
+        let mut args: Vec<String> = Vec::with_capacity(names.len());
+        let mut arg_spans = Vec::with_capacity(names.len());
+        let mut signature = Signature::default();
+        for (name, annotation, span) in names {
+            if args.contains(&name) {
+                return Err(self.error(format!("duplicate argument `{}`", name), span));
+            }
+            args.push(name);
+            arg_spans.push(span);
+            signature.args.push(annotation);
+        }
+        if self.eat(TokenKind::ThinArrow).is_some() {
+            signature.ret = Some(self.ty()?);
+        }
+
+        // we either parse the block as is, or if the function is a lambda
+        // (e.g. fn() => x) we expand it to a block returning the expression value
+        let lambda = self.peek().kind == TokenKind::Arrow;
+        let block = match self.peek().kind {
+            TokenKind::LBrace => self.block()?,
+            TokenKind::Arrow => {
+                let start = self.advance().span;
+                let exp = self.exp()?;
+                let span = start.to(exp.span);
+                Block {
+                    block: vec![Statement {
+                        statement: StatementKind::Return(exp),
+                        span,
+                    }],
+                    span,
+                }
+            }
+            _ => return Err(self.expected("`=>` or a block")),
+        };
+
+        Ok(ValueKind::FunctionDef {
+            args,
+            arg_spans,
+            signature,
+            block,
+            lambda,
+        })
+    }
+
+    /// parses a type annotation, such as `num`
+    fn ty(&mut self) -> Result<Type, ParseError> {
+        let token = self.peek();
+        let ty = match token.kind {
+            TokenKind::Name | TokenKind::Null => Type::from_name(self.text(token)),
+            _ => None,
+        };
+        match ty {
+            Some(ty) => {
+                self.advance();
+                Ok(ty)
+            }
+            None => Err(self
+                .expected("a type")
+                .with_hint("types are null, num, string, array, struct, closure, builtin, and type")),
+        }
+    }
+
+    /// parses a name, keywords can't be used as names
+    fn name(&mut self) -> Result<String, ParseError> {
+        let token = self.peek();
+        match token.kind {
+            TokenKind::Name => {
+                self.advance();
+                Ok(self.text(token).to_string())
+            }
+            kind if kind.is_keyword() => Err(self.error(
+                format!("`{}` is a keyword and can't be used as a name", self.text(token)),
+                token.span,
+            )),
+            _ => Err(self.expected("a name")),
+        }
+    }
 
-impl PuffinParser {
-    pub fn parse_program(program: &str) -> Result<Pairs<Rule>, Error<Rule>> {
-        PuffinParser::parse(Rule::program, program)
+    /// parses a list of items separated by commas until `close`, allowing a trailing comma
+    fn list<T>(
+        &mut self,
+        close: TokenKind,
+        mut item: impl FnMut(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<Vec<T>, ParseError> {
+        let mut items = Vec::new();
+        while self.eat(close).is_none() {
+            items.push(item(self)?);
+            if self.eat(TokenKind::Comma).is_none() {
+                self.expect_after(close)?;
+                break;
+            }
+        }
+
+        Ok(items)
+    }
+
+    /****************** Tokens ******************/
+
+    fn peek(&self) -> Token {
+        self.nth(0)
+    }
+
+    /// returns the token `n` tokens ahead of the next token
+    fn nth(&self, n: usize) -> Token {
+        // the last token is always Eof
+        self.tokens[(self.pos + n).min(self.tokens.len() - 1)]
+    }
+
+    fn prev(&self) -> Token {
+        self.tokens[self.pos.saturating_sub(1)]
+    }
+
+    fn prev_span(&self) -> Span {
+        self.prev().span
+    }
+
+    /// consumes the next token, returning it
+    fn advance(&mut self) -> Token {
+        let token = self.peek();
+        if token.kind != TokenKind::Eof {
+            self.pos += 1;
+        }
+        token
+    }
+
+    /// consumes the next token if it is of a given kind
+    fn eat(&mut self, kind: TokenKind) -> Option<Token> {
+        if self.peek().kind == kind {
+            return Some(self.advance());
+        }
+        None
+    }
+
+    /// consumes the next token, which must be of a given kind
+    fn expect(&mut self, kind: TokenKind) -> Result<Token, ParseError> {
+        self.eat(kind).ok_or_else(|| self.expected(kind.describe()))
+    }
+
+    /// consumes the next token, which must be of a given kind.
+    /// Errors point just after the previous token, where the token is missing
+    fn expect_after(&mut self, kind: TokenKind) -> Result<Token, ParseError> {
+        self.eat(kind)
+            .ok_or_else(|| self.expected_after(kind.describe()))
+    }
+
+    fn text(&self, token: Token) -> &'a str {
+        &self.source[token.span.start..token.span.end]
+    }
+
+    /****************** Error Helpers ******************/
+
+    fn error(&self, message: String, span: Span) -> ParseError {
+        ParseError::new(message, span, self.source)
+    }
+
+    /// error expecting `what` at the next token
+    fn expected(&self, what: &str) -> ParseError {
+        self.error(format!("expected {}", what), self.peek().span)
+    }
+
+    /// error expecting `what` just after the previous token
+    fn expected_after(&self, what: &str) -> ParseError {
+        let end = self.prev_span().end;
+        self.error(format!("expected {}", what), Span::new(self.source, end, end))
     }
 }
 
-/// Converts a pest parsing error into a more readable location.
-/// Either returns the line and column formated as `line: {line}:{col}` or the span
-pub fn line_col(err: Error<Rule>) -> String {
-    match err.line_col {
-        pest::error::LineColLocation::Pos(p) => format!("line {}:{}", p.0, p.1),
-        pest::error::LineColLocation::Span(start, end) => {
-            format!("Span: {:?} -> {:?}", start, end)
+/// Returns true if tokens of this kind can start an expression
+fn starts_exp(kind: TokenKind) -> bool {
+    matches!(
+        kind,
+        TokenKind::Num
+            | TokenKind::String
+            | TokenKind::Name
+            | TokenKind::Null
+            | TokenKind::Fn
+            | TokenKind::LParen
+            | TokenKind::LBracket
+            | TokenKind::LBrace
+            | TokenKind::Minus
+            | TokenKind::Bang
+    )
+}
+
+/// Returns the infix operator of a token
+fn infix_op(kind: TokenKind) -> Option<InfixOp> {
+    Some(match kind {
+        TokenKind::Or => InfixOp::Or,
+        TokenKind::And => InfixOp::And,
+        TokenKind::Eq => InfixOp::Eq,
+        TokenKind::Ne => InfixOp::Ne,
+        TokenKind::Lt => InfixOp::Lt,
+        TokenKind::Le => InfixOp::Le,
+        TokenKind::Gt => InfixOp::Gt,
+        TokenKind::Ge => InfixOp::Ge,
+        TokenKind::Plus => InfixOp::Plus,
+        TokenKind::Minus => InfixOp::Minus,
+        TokenKind::Star => InfixOp::Mul,
+        TokenKind::Slash => InfixOp::Div,
+        TokenKind::Percent => InfixOp::Mod,
+        _ => return None,
+    })
+}
+
+/// Returns the operator of a token that can augment an assignment, like `+=`
+fn aug_op(kind: TokenKind) -> Option<InfixOp> {
+    match kind {
+        TokenKind::Plus | TokenKind::Minus | TokenKind::Star | TokenKind::Slash | TokenKind::Percent => {
+            infix_op(kind)
         }
+        _ => None,
     }
 }
 
 #[cfg(test)]
 mod test {
 
-    use pest::Parser;
-
     use super::*;
 
+    /// parses all of `test` with `parse`, panicking on any error
+    fn parses<'a, T>(
+        test: &'a str,
+        parse: impl FnOnce(&mut PuffinParser<'a>) -> Result<T, ParseError>,
+    ) -> T {
+        let mut parser = PuffinParser::new(test);
+        let result = parse(&mut parser).unwrap_or_else(|err| panic!("{}\n{}", test, err));
+        assert!(parser.errors.is_empty(), "{}\n{:?}", test, parser.errors);
+        assert_eq!(TokenKind::Eof, parser.peek().kind, "{}", test);
+        result
+    }
+
     #[test]
     fn test_program() {
-        PuffinParser::parse(
-            Rule::program,
+        PuffinParser::parse_program(
             r#"
         x = 5;
         if (x < 5) {
@@ -66,9 +756,7 @@ mod test {
             "a",
         ];
         for test in tests {
-            let pairs = PuffinParser::parse(Rule::name, test).unwrap();
-            let last = pairs.last().unwrap();
-            assert_eq!(last.as_span().end_pos().pos(), test.len(), "{}", test);
+            parses(test, PuffinParser::name);
         }
     }
 
@@ -77,20 +765,16 @@ mod test {
         let tests = vec![r"null", r"(null)"];
 
         for test in tests {
-            let pairs = PuffinParser::parse(Rule::value, test).expect(test);
-            let last = pairs.last().unwrap();
-            assert_eq!(last.as_span().end(), test.len(), "{}", test);
+            parses(test, PuffinParser::value);
         }
     }
 
     #[test]
     fn test_return() {
-        let tests = vec![r"return 5", r"return arr[5]", r"return arr[5] + 5"];
+        let tests = vec![r"return 5;", r"return arr[5];", r"return arr[5] + 5;"];
 
         for test in tests {
-            let pairs = PuffinParser::parse(Rule::return_statement, test).expect(test);
-            let last = pairs.last().unwrap();
-            assert_eq!(last.as_span().end_pos().pos(), test.len(), "{}", test);
+            parses(test, PuffinParser::statement);
         }
     }
 
@@ -105,9 +789,7 @@ mod test {
         ];
 
         for test in tests {
-            let pairs = PuffinParser::parse(Rule::assign_statement, test).expect(test);
-            let last = pairs.last().unwrap();
-            assert_eq!(last.as_span().end_pos().pos(), test.len(), "{}", test);
+            parses(test, PuffinParser::simple_statement);
         }
     }
 
@@ -129,7 +811,7 @@ mod test {
         }"#,
         ];
         for test in tests {
-            PuffinParser::parse(Rule::block, test).expect(test);
+            parses(test, PuffinParser::block);
         }
     }
 
@@ -146,9 +828,7 @@ mod test {
         ];
 
         for test in tests {
-            let pairs = PuffinParser::parse(Rule::array_init, test).unwrap();
-            let last = pairs.last().unwrap();
-            assert_eq!(last.as_span().end(), test.len(), "{}", test);
+            parses(test, PuffinParser::value);
         }
     }
 
@@ -164,9 +844,7 @@ mod test {
         ];
 
         for test in tests {
-            let pairs = PuffinParser::parse(Rule::array_init, test).unwrap();
-            let last = pairs.last().unwrap();
-            assert_eq!(last.as_span().end(), test.len(), "{}", test);
+            parses(test, PuffinParser::value);
         }
     }
 
@@ -185,9 +863,7 @@ mod test {
         ];
 
         for test in tests {
-            let pairs = PuffinParser::parse(Rule::exp, test).expect(test);
-            let last = pairs.last().unwrap();
-            assert_eq!(last.as_span().end(), test.len(), "{}", test);
+            parses(test, PuffinParser::exp);
         }
     }
 
@@ -203,9 +879,7 @@ mod test {
         ];
 
         for test in tests {
-            let pairs = PuffinParser::parse(Rule::exp, test).expect(test);
-            let last = pairs.last().unwrap();
-            assert_eq!(last.as_span().end(), test.len(), "{}", test);
+            parses(test, PuffinParser::exp);
         }
     }
 
@@ -226,9 +900,7 @@ mod test {
         ];
 
         for test in tests {
-            let pairs = PuffinParser::parse(Rule::function, test).expect(test);
-            let last = pairs.last().unwrap();
-            assert_eq!(last.as_span().end(), test.len(), "{}", test);
+            parses(test, PuffinParser::value);
         }
     }
 
@@ -248,9 +920,7 @@ mod test {
         ];
 
         for test in tests {
-            let pairs = PuffinParser::parse(Rule::exp, test).expect(test);
-            let last = pairs.last().unwrap();
-            assert_eq!(last.as_span().end(), test.len(), "{}", test);
+            parses(test, PuffinParser::exp);
         }
     }
 
@@ -271,9 +941,7 @@ mod test {
         ];
 
         for test in tests {
-            let pairs = PuffinParser::parse(Rule::condnest, test).expect(test);
-            let last = pairs.last().unwrap();
-            assert_eq!(last.as_span().end(), test.len(), "{}", test);
+            parses(test, PuffinParser::nest);
         }
     }
 
@@ -288,9 +956,7 @@ mod test {
         ];
 
         for test in tests {
-            let pairs = PuffinParser::parse(Rule::loopnest, test).expect(test);
-            let last = pairs.last().unwrap();
-            assert_eq!(last.as_span().end(), test.len(), "{}", test);
+            parses(test, PuffinParser::nest);
         }
     }
 
@@ -303,9 +969,7 @@ mod test {
         ];
 
         for test in tests {
-            let pairs = PuffinParser::parse(Rule::statement, test).expect(test);
-            let last = pairs.last().unwrap();
-            assert_eq!(last.as_span().end(), test.len(), "{}", test);
+            parses(test, PuffinParser::statement);
         }
     }
 
@@ -322,9 +986,7 @@ mod test {
         ];
 
         for test in tests {
-            let pairs = PuffinParser::parse(Rule::exp, test).expect(test);
-            let last = pairs.last().unwrap();
-            assert_eq!(last.as_span().end(), test.len(), "{}", test);
+            parses(test, PuffinParser::exp);
         }
     }
 
@@ -342,9 +1004,7 @@ mod test {
         ];
 
         for test in tests {
-            let pairs = PuffinParser::parse(Rule::exp, test).expect(test);
-            let last = pairs.last().unwrap();
-            assert_eq!(last.as_span().end(), test.len(), "{}", test);
+            parses(test, PuffinParser::exp);
         }
     }
 
@@ -357,9 +1017,7 @@ mod test {
         ];
 
         for test in tests {
-            let pairs = PuffinParser::parse(Rule::function, test).expect(test);
-            let last = pairs.last().unwrap();
-            assert_eq!(last.as_span().end(), test.len(), "{}", test);
+            parses(test, PuffinParser::value);
         }
     }
 
@@ -373,9 +1031,277 @@ mod test {
         ];
 
         for test in tests {
-            let pairs = PuffinParser::parse(Rule::program, test).expect(test);
-            let last = pairs.last().unwrap();
-            assert_eq!(last.as_span().end(), test.len(), "{}", test);
+            PuffinParser::parse_program(test).expect(test);
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let tests = vec![
+            ("x = 1\ny = 2;", "expected `;` after expression", 1, 6),
+            ("x = (1 + 2));", "unexpected `)`", 1, 12),
+            ("x = ;", "expected an expression", 1, 5),
+            ("x = {a: 1, b};", "expected a structure field", 1, 12),
+            ("while (1) {\n\tx = 1;", "expected a statement", 2, 8),
+        ];
+
+        for (test, message, line, col) in tests {
+            let err = &PuffinParser::parse_program(test).expect_err(test)[0];
+            assert_eq!((message, line, col), (err.message.as_str(), err.span.line, err.span.col), "{}", test);
+            assert_eq!(None, err.hint, "{}", test);
+        }
+    }
+
+    #[test]
+    fn test_parse_error_prefix_hint() {
+        for test in ["x = --1;", "x = !!y;", "x = -!y;"].iter() {
+            let err = &PuffinParser::parse_program(test).expect_err(test)[0];
+            assert_eq!("expected an expression", err.message, "{}", test);
+            assert_eq!((1, 6), (err.span.line, err.span.col), "{}", test);
+            assert!(err.hint.is_some(), "{}", test);
+        }
+        // parentheses nest prefix operators
+        assert!(PuffinParser::parse_program("x = -(-1); y = !(!x); z = -x[0];").is_ok());
+    }
+
+    #[test]
+    fn test_parse_error_fn_hint() {
+        let test = "f = fn(a, b) {\n    return a;\n}\nf(1, 2);";
+        let err = &PuffinParser::parse_program(test).unwrap_err()[0];
+        assert_eq!("expected `;` after expression", err.message);
+        assert_eq!((3, 2), (err.span.line, err.span.col));
+        assert!(err.hint.is_some());
+
+        // lambdas and calls don't get the hint
+        for test in ["f = fn(a) => a\nf(1);", "x = f(1)\nf(1);"].iter() {
+            assert_eq!(None, PuffinParser::parse_program(test).unwrap_err()[0].hint, "{}", test);
+        }
+    }
+
+    #[test]
+    fn test_parse_error_display() {
+        let err = &PuffinParser::parse_program("x = 1;\n\ty = ;").unwrap_err()[0];
+        assert_eq!(
+            "expected an expression\n --> 2:6\n  |\n2 | \ty = ;\n  | \t    ^",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn test_parse_error_recovery() {
+        let test = "x = 1\ny = ;\nwhile (1) {\n    z = {a: 1, b};\n    w = 2;\n}\nv = (1;\nf = fn() { return 1 };\n}";
+        let errors = PuffinParser::parse_program(test).unwrap_err();
+        let found: Vec<(&str, usize, usize)> = errors
+            .iter()
+            .map(|err| (err.message.as_str(), err.span.line, err.span.col))
+            .collect();
+        assert_eq!(
+            vec![
+                ("expected `;` after expression", 1, 6),
+                ("expected an expression", 2, 5),
+                ("expected a structure field", 4, 16),
+                ("expected `)`", 7, 7),
+                ("expected `;` after expression", 8, 20),
+                ("expected a statement", 9, 1),
+            ],
+            found
+        );
+
+        // spans cover the offending character, or are empty where something is missing
+        assert_eq!(Span { start: 10, end: 11, line: 2, col: 5, end_line: 2 }, errors[1].span);
+        assert_eq!(errors[0].span.start, errors[0].span.end);
+    }
+
+    #[test]
+    fn test_ast_program() {
+        let tests = vec![
+            r"1;",
+            r"x = 1;",
+            r"x = x;",
+            r"x[x] = x;",
+            r"x[x] = func(x);",
+            r"x[func(x)] = func(x);",
+            r#"
+            x = [5];
+            for (i = 0; i < len(x); i = i + 1) {
+                x[i] = factorial(i+1);
+            }
+            return x;
+            "#,
+            r#"
+            x = {
+                a: "v1",
+                b: "v2",
+                c: "v3",
+                d: {
+                    a1: "v1_1",
+                    b1: [5]
+                }
+            };
+            "#,
+        ];
+
+        for test in tests {
+            PuffinParser::parse_program(test).expect(test);
+        }
+    }
+
+    /// writes an expression with every operator parenthesized
+    fn grouped(exp: &Exp) -> String {
+        match &exp.exp {
+            ExpKind::Value(ValueKind::Num(n)) => n.to_string(),
+            ExpKind::Value(ValueKind::Name(name)) => name.clone(),
+            ExpKind::Value(ValueKind::Paren(inner)) => grouped(inner),
+            ExpKind::Value(other) => format!("{:?}", other),
+            ExpKind::Unary(op, operand) => format!("({:?} {})", op, grouped(operand)),
+            ExpKind::Infix(op, left, right) => {
+                format!("({} {:?} {})", grouped(left), op, grouped(right))
+            }
+            ExpKind::Postfix(operand, PostOp::Subscript(index)) => {
+                format!("{}[{}]", grouped(operand), grouped(index))
+            }
+            ExpKind::Postfix(operand, PostOp::Dot(name)) => format!("{}.{}", grouped(operand), name),
+            ExpKind::Postfix(operand, PostOp::Call(args)) => {
+                let args: Vec<String> = args.iter().map(grouped).collect();
+                format!("{}({})", grouped(operand), args.join(", "))
+            }
+        }
+    }
+
+    #[test]
+    fn test_precedence() {
+        let tests = vec![
+            ("1 + 2 * 3", "(1 Plus (2 Mul 3))"),
+            ("1 - 2 - 3", "((1 Minus 2) Minus 3)"),
+            ("a / b % c * d", "(((a Div b) Mod c) Mul d)"),
+            ("(1 + 2) * 3", "((1 Plus 2) Mul 3)"),
+            ("-a[0] * b", "((Neg a[0]) Mul b)"),
+            ("!f(x).y == z", "((Not f(x).y) Eq z)"),
+            ("a < b == c > d", "((a Lt b) Eq (c Gt d))"),
+            ("a || b && c == d", "(a Or (b And (c Eq d)))"),
+            ("2 * -3 + 1", "((2 Mul (Neg 3)) Plus 1)"),
+            ("a.b[c](d, e + 1)", "a.b[c](d, (e Plus 1))"),
+        ];
+
+        for (test, expected) in tests {
+            assert_eq!(expected, grouped(&parses(test, PuffinParser::exp)), "{}", test);
+        }
+    }
+
+    #[test]
+    fn test_sugar() {
+        // a op= b expands to a = a op b
+        let statement = parses("a[0] *= 1 + 2;", PuffinParser::statement);
+        match statement.statement {
+            StatementKind::Assign { lhs, rhs, aug, .. } => {
+                assert_eq!(Some(InfixOp::Mul), aug);
+                assert_eq!("a", lhs.name);
+                assert_eq!(1, lhs.assignable.len());
+                assert_eq!("(a[0] Mul (1 Plus 2))", grouped(&rhs));
+            }
+            other => panic!("{:?}", other),
+        }
+
+        // lambdas expand to a block returning their expression
+        match parses("fn(a) => a + 1", PuffinParser::value).exp {
+            ExpKind::Value(ValueKind::FunctionDef { args, block, lambda, .. }) => {
+                assert!(lambda);
+                assert_eq!(vec!["a".to_string()], args);
+                assert!(matches!(block.block[..], [Statement { statement: StatementKind::Return(_), .. }]));
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_annotations() {
+        match parses("fn(a: num, b, c: null) -> string => b", PuffinParser::value).exp {
+            ExpKind::Value(ValueKind::FunctionDef { args, signature, .. }) => {
+                assert_eq!(3, args.len());
+                assert_eq!(vec![Some(Type::Num), None, Some(Type::Null)], signature.args);
+                assert_eq!(Some(Type::String), signature.ret);
+            }
+            other => panic!("{:?}", other),
+        }
+
+        match parses("x: array = [3];", PuffinParser::statement).statement {
+            StatementKind::Assign { lhs, annotation, .. } => {
+                assert_eq!("x", lhs.name);
+                assert_eq!(Some(Type::Array), annotation);
+            }
+            other => panic!("{:?}", other),
+        }
+
+        let errors = |test: &str| -> Vec<String> {
+            PuffinParser::parse_program(test)
+                .unwrap_err()
+                .into_iter()
+                .map(|err| err.message)
+                .collect()
+        };
+        assert_eq!(vec!["expected a type"], errors("x: int = 1;"));
+        assert_eq!(vec!["expected `=` after type annotation"], errors("x: num;"));
+        assert_eq!(vec!["unexpected `:`"], errors("a.b: num = 1;"));
+        assert_eq!(vec!["expected a type"], errors("f = fn(a) -> => a;"));
+    }
+
+    #[test]
+    fn test_spans() {
+        let test = "x = 1;\nif (x) {\n  y = x + 10;\n}";
+        let program = PuffinParser::parse_program(test).unwrap();
+        let spans: Vec<&str> = program.program.iter().map(|s| &test[s.span.start..s.span.end]).collect();
+        assert_eq!(vec!["x = 1;", "if (x) {\n  y = x + 10;\n}"], spans);
+
+        match &program.program[1].statement {
+            StatementKind::Nest(NestKind::CondNest(CondNestKind::If { then, .. })) => {
+                let inner = &then.block[0];
+                assert_eq!((3, 3), (inner.span.line, inner.span.col));
+                if let StatementKind::Assign { rhs, .. } = &inner.statement {
+                    assert_eq!("x + 10", &test[rhs.span.start..rhs.span.end]);
+                }
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_tokens() {
+        let lexed = lexer::tokenize("x1 += 2.5; // comment\nreturnx == \"a b\" <= !y.z;");
+        assert!(lexed.errors.is_empty());
+        assert_eq!(vec!["// comment"], lexed.comments.iter().map(|c| c.text.as_str()).collect::<Vec<_>>());
+        let tokens = lexed.tokens;
+        let kinds: Vec<TokenKind> = tokens.iter().map(|token| token.kind).collect();
+        use TokenKind::*;
+        assert_eq!(
+            vec![Name, Plus, Assign, Num, Semicolon, Name, Eq, String, Le, Bang, Name, Dot, Name, Semicolon, Eof],
+            kinds
+        );
+        assert_eq!((2, 1), (tokens[5].span.line, tokens[5].span.col));
+
+        // integer followed by a field access, not a fraction
+        assert_eq!(Dot, lexer::tokenize("1.a").tokens[1].kind);
+
+        let errors = lexer::tokenize("x = #;\nz = a & b;\ny = \"open").errors;
+        let messages: Vec<&str> = errors.iter().map(|err| err.message.as_str()).collect();
+        assert_eq!(vec!["unexpected character `#`", "expected `&&`", "unterminated string"], messages);
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        let tests = vec![
+            ("f(1) = 2;", "can't assign to this expression", 1, 1),
+            ("f = fn(a, a) => a;", "duplicate argument `a`", 1, 11),
+            ("x = {in: 1};", "`in` is a keyword and can't be used as a name", 1, 6),
+            ("x = [1 2];", "expected `]`", 1, 7),
+            ("f(1 2);", "expected `)`", 1, 4),
+            ("x = {a: 1,};", "expected a structure field", 1, 11),
+            ("x = fn(a) a;", "expected `=>` or a block", 1, 11),
+            ("for (x in) {}", "expected an expression", 1, 10),
+        ];
+
+        for (test, message, line, col) in tests {
+            let err = &PuffinParser::parse_program(test).expect_err(test)[0];
+            assert_eq!((message, line, col), (err.message.as_str(), err.span.line, err.span.col), "{}", test);
         }
     }
 
@@ -386,9 +1312,7 @@ mod test {
         let tests = vec![r"case;"];
 
         for test in tests {
-            let pairs = PuffinParser::parse(Rule::program, test).expect(test);
-            let last = pairs.last().unwrap();
-            assert_eq!(last.as_span().end(), test.len(), "{}", test);
+            PuffinParser::parse_program(test).expect(test);
         }
     }
 }
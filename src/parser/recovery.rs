@@ -0,0 +1,58 @@
+//! Author: Rafael Bayer (2021)
+//! The recovery module lets the parser continue after a syntax error, to find every error in a program.
+//!
+//! After an error, the rest of the broken statement is skipped, up to the next `;` or `}` that ends it.
+//! Blocks opened by the statement before the error are skipped along with it,
+//! while the `}` closing the enclosing block is left for the block to parse.
+
+use super::{lexer::TokenKind, PuffinParser};
+
+impl PuffinParser<'_> {
+    /// Skips the rest of the statement starting at token `start`, which failed to parse.
+    /// `in_block` is true if the statement is in a block, rather than at the top level of the program.
+    pub(super) fn synchronize(&mut self, start: usize, in_block: bool) {
+        // blocks opened (and not closed) by the broken statement before the error
+        let mut opened = 0;
+        for token in &self.tokens[start..self.pos] {
+            match token.kind {
+                TokenKind::LBrace => opened += 1,
+                TokenKind::RBrace if opened > 0 => opened -= 1,
+                _ => {}
+            }
+        }
+
+        let mut depth = opened;
+        loop {
+            match self.peek().kind {
+                TokenKind::Eof => return,
+                TokenKind::Semicolon if depth == 0 => {
+                    self.advance();
+                    return;
+                }
+                TokenKind::LBrace => depth += 1,
+                TokenKind::RBrace if depth == 0 => {
+                    // closes the enclosing block, leave it for the block.
+                    // a stray `}` at the top level is skipped
+                    if !in_block {
+                        self.advance();
+                    }
+                    return;
+                }
+                TokenKind::RBrace => {
+                    depth -= 1;
+                    // a block opened after the error ended a statement like `if`,
+                    // skip an `else` block, or `;`, following it
+                    if depth == 0 && opened == 0 {
+                        self.advance();
+                        if self.peek().kind != TokenKind::Else {
+                            self.eat(TokenKind::Semicolon);
+                            return;
+                        }
+                    }
+                }
+                _ => {}
+            }
+            self.advance();
+        }
+    }
+}
@@ -0,0 +1,245 @@
+//! Author: Rafael Bayer (2021)
+//! The profiler module defines the `Profiler`, which measures where a program spends its time.
+//!
+//! The profiler is a set of interpreter `Hooks`. Between any two hooks, the elapsed time
+//! is charged to the line of the statement executing, and to the call executing it.
+//! Closures are named after the name they were bound to, anonymous closures after
+//! the line they are defined on. The top level of the program is the `<program>` call.
+//!
+//! Besides a table of calls and lines, the profiler writes folded stacks, one line per
+//! stack of calls with the microseconds spent in it, the input of flamegraph tools.
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt::Write,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    ast::node::Statement,
+    interpreter::{
+        hooks::{Call, Hooks},
+        value::Environment,
+        InterpreterError, Value,
+    },
+};
+
+/// Time spent in, and calls of, a closure
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FunctionStats {
+    /// Number of calls
+    pub calls: u64,
+    /// Time spent in calls, including the calls they made
+    pub inclusive: Duration,
+    /// Time spent in calls, excluding the calls they made
+    pub exclusive: Duration,
+}
+
+/// Time spent on, and executions of, the statements of a line
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LineStats {
+    /// Number of statements executed
+    pub hits: u64,
+    /// Time spent executing the statements, excluding the calls they made
+    pub exclusive: Duration,
+}
+
+/// Profiler, measures the time a program spends in each closure and on each line
+#[derive(Default)]
+pub struct Profiler {
+    profile: RefCell<Profile>,
+}
+
+#[derive(Default)]
+struct Profile {
+    functions: HashMap<String, FunctionStats>,
+    lines: HashMap<usize, LineStats>,
+    /// time spent in each stack of calls, by the names of the calls joined by `;`
+    folded: HashMap<String, Duration>,
+    frames: Vec<Frame>,
+    /// time of the last hook, until which time has been charged
+    last: Option<Instant>,
+}
+
+/// Call being executed, or the program itself
+struct Frame {
+    name: String,
+    /// names of the calls leading to this one, joined by `;`
+    stack: String,
+    /// line of the statement executing in this frame
+    line: usize,
+    started: Instant,
+}
+
+impl Profiler {
+    /// Returns a Profiler for a program that hasn't started
+    pub fn new() -> Profiler {
+        Self::default()
+    }
+
+    /// Ends the profile of a program that finished,
+    /// charging the time since the last statement to the calls that were executing
+    pub fn finish(&self) {
+        let mut profile = self.profile.borrow_mut();
+        while !profile.frames.is_empty() {
+            profile.exit();
+        }
+    }
+
+    /// Returns the stats of each closure, and of the program itself, by name.
+    /// Sorted by exclusive time, the slowest first
+    pub fn functions(&self) -> Vec<(String, FunctionStats)> {
+        let mut functions: Vec<(String, FunctionStats)> = self
+            .profile
+            .borrow()
+            .functions
+            .iter()
+            .map(|(name, stats)| (name.clone(), *stats))
+            .collect();
+        functions.sort_by(|a, b| b.1.exclusive.cmp(&a.1.exclusive).then(a.0.cmp(&b.0)));
+        functions
+    }
+
+    /// Returns the stats of each line with statements that were executed.
+    /// Sorted by exclusive time, the slowest first
+    pub fn lines(&self) -> Vec<(usize, LineStats)> {
+        let mut lines: Vec<(usize, LineStats)> = self
+            .profile
+            .borrow()
+            .lines
+            .iter()
+            .map(|(line, stats)| (*line, *stats))
+            .collect();
+        lines.sort_by(|a, b| b.1.exclusive.cmp(&a.1.exclusive).then(a.0.cmp(&b.0)));
+        lines
+    }
+
+    /// Returns the profile as tables of closures and lines, for people to read
+    pub fn table(&self) -> String {
+        let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        let mut table = String::new();
+
+        let functions = self.functions();
+        let width = functions.iter().map(|(name, _)| name.len()).max().unwrap_or(0).max(8);
+        writeln!(
+            table,
+            "{:<width$} {:>10} {:>12} {:>12}",
+            "function", "calls", "total (ms)", "self (ms)",
+            width = width
+        )
+        .unwrap();
+        for (name, stats) in functions {
+            writeln!(
+                table,
+                "{:<width$} {:>10} {:>12.3} {:>12.3}",
+                name,
+                stats.calls,
+                ms(stats.inclusive),
+                ms(stats.exclusive),
+                width = width
+            )
+            .unwrap();
+        }
+
+        writeln!(table, "\n{:<8} {:>10} {:>12}", "line", "hits", "self (ms)").unwrap();
+        for (line, stats) in self.lines() {
+            writeln!(table, "{:<8} {:>10} {:>12.3}", line, stats.hits, ms(stats.exclusive)).unwrap();
+        }
+        table
+    }
+
+    /// Returns the folded stacks of the profile, a line per stack of calls
+    /// with the microseconds spent in it, sorted by stack
+    pub fn folded(&self) -> String {
+        let profile = self.profile.borrow();
+        let mut stacks: Vec<(&String, &Duration)> = profile.folded.iter().collect();
+        stacks.sort();
+        stacks
+            .into_iter()
+            .map(|(stack, time)| format!("{} {}\n", stack, time.as_micros()))
+            .collect()
+    }
+}
+
+impl Profile {
+    /// charges the time since the last hook to the executing line and calls
+    fn charge(&mut self) {
+        let now = Instant::now();
+        let elapsed = match self.last.replace(now) {
+            Some(last) => now - last,
+            None => return,
+        };
+        let frame = match self.frames.last() {
+            Some(frame) => frame,
+            None => return,
+        };
+
+        self.functions.entry(frame.name.clone()).or_default().exclusive += elapsed;
+        self.lines.entry(frame.line).or_default().exclusive += elapsed;
+        *self.folded.entry(frame.stack.clone()).or_default() += elapsed;
+    }
+
+    fn enter(&mut self, name: String, line: usize) {
+        let stack = match self.frames.last() {
+            Some(parent) => format!("{};{}", parent.stack, name),
+            None => name.clone(),
+        };
+        self.functions.entry(name.clone()).or_default().calls += 1;
+        // the call starts when time was last charged, so its inclusive time covers its exclusive time
+        let started = *self.last.get_or_insert_with(Instant::now);
+        self.frames.push(Frame {
+            name,
+            stack,
+            line,
+            started,
+        });
+    }
+
+    fn exit(&mut self) {
+        self.charge();
+        if let Some(frame) = self.frames.pop() {
+            // recursive calls are already included in the time of the outermost call
+            if self.frames.iter().all(|other| other.name != frame.name) {
+                let ended = self.last.unwrap_or_else(Instant::now);
+                self.functions.entry(frame.name).or_default().inclusive += ended - frame.started;
+            }
+        }
+    }
+}
+
+impl Hooks for Profiler {
+    fn statement(
+        &self,
+        statement: &Statement,
+        _env: &Rc<RefCell<Environment>>,
+    ) -> Result<(), InterpreterError> {
+        let mut profile = self.profile.borrow_mut();
+        profile.charge();
+        let line = statement.span.line;
+        if profile.frames.is_empty() {
+            profile.enter("<program>".to_string(), line);
+        }
+        if let Some(frame) = profile.frames.last_mut() {
+            frame.line = line;
+        }
+        profile.lines.entry(line).or_default().hits += 1;
+        Ok(())
+    }
+
+    fn enter_call(&self, call: &Call) {
+        let mut profile = self.profile.borrow_mut();
+        profile.charge();
+        let line = call.block.span.line;
+        let name = match call.name {
+            Some(name) => name.to_string(),
+            None => format!("<anonymous:{}>", line),
+        };
+        profile.enter(name, line);
+    }
+
+    fn exit_call(&self, _call: &Call, _result: &Result<Value, InterpreterError>) {
+        self.profile.borrow_mut().exit();
+    }
+}
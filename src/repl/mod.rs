@@ -1,65 +1,153 @@
-use std::io;
-use std::io::Write;
+//! Author: Rafael Bayer (2021)
+//! The repl module runs the Puffin REPL, which evaluates statements as they are entered
+//! and prints their values.
+//!
+//! Lines are edited with `rustyline`, with tab completion of the names bound in the REPL,
+//! and a history saved between sessions. Input with unclosed brackets or strings continues
+//! on the next line. Errors are printed, and the session continues.
 
-use pest::Parser;
+use std::{env, io, path::PathBuf, rc::Rc};
+
+use rustyline::{
+    completion::Completer, error::ReadlineError, highlight::Highlighter, hint::Hinter,
+    history::DefaultHistory, validate::Validator, Context, Editor, Helper,
+};
 
-use crate::ast;
 use crate::interpreter::repl::Repl;
-use crate::interpreter::Value;
-use crate::parser::Rule;
-use crate::PuffinParser;
+use crate::interpreter::{InterpreterError, Value};
+use crate::{Error, PuffinParser};
+
+/// Name of the file in the home directory the REPL history is saved to
+pub const HISTORY: &str = ".puffin_history";
 
-/// Starts the Puffin REPL
-pub fn start_repl() -> ! {
+/// Starts the Puffin REPL, returning when the input ends,
+/// or with the exit error of a program that calls `exit`
+pub fn start_repl() -> Result<(), Error> {
     println!("Welcome to the Puffin REPL!");
-    println!("(Ctrl-Z on newline to cancel input | Ctrl-C to exit)\n");
+    println!("(Ctrl-C to cancel input | Ctrl-D to exit)\n");
 
     // Repl environment
-    let repl = Repl::new();
+    let repl = Rc::new(Repl::new());
+    let mut editor: Editor<Names, DefaultHistory> = Editor::new().map_err(readline_error)?;
+    editor.set_helper(Some(Names(repl.clone())));
+    let history = env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY));
+    if let Some(path) = &history {
+        // there is no history before the first session
+        let _ = editor.load_history(path);
+    }
     let mut buffer = String::new();
 
     // REPL loop
     loop {
-        // REPL read
-        let bytes = readline(&mut buffer);
-        if bytes == 0 {
-            buffer.clear();
-            println!("\n");
+        // REPL read, until brackets and strings are closed
+        let prompt = if buffer.is_empty() { ">>> " } else { "... " };
+        match editor.readline(prompt) {
+            Ok(line) => {
+                buffer.push_str(&line);
+                buffer.push('\n');
+            }
+            Err(ReadlineError::Interrupted) => {
+                buffer.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => return Ok(()),
+            Err(err) => return Err(readline_error(err)),
+        }
+        if incomplete(&buffer) {
+            continue;
+        }
+        let input = std::mem::take(&mut buffer);
+        if input.trim().is_empty() {
             continue;
         }
 
-        if let Ok(mut stmt) = PuffinParser::parse(Rule::statement, &buffer) {
-
-            let stmt_ast = ast::build_statement(stmt.next().unwrap()).unwrap();
-            
-            // REPL evaluate
-            let res = repl
-                .repl_statement(&stmt_ast)
-                .unwrap()
-                .unwrap_or(Value::Null);
-
-            // REPL print
-            if matches!(res, Value::Null) {
-                println!();
-            } else {
-                println!("{}", res);
-            }
+        // entries are added even if they fail, so they can be fixed
+        let _ = editor.add_history_entry(input.trim_end());
+        if let Some(path) = &history {
+            let _ = editor.save_history(path);
+        }
 
-            buffer.clear();
+        // REPL evaluate and print
+        match eval_input(&repl, &input) {
+            Ok(Value::Null) => println!(),
+            Ok(value) => println!("{}", value),
+            Err(err @ Error::Runtime(InterpreterError::Exit(_))) => return Err(err),
+            Err(err) => eprintln!("{}", err),
         }
     }
 }
 
-/// reads line from stdin into buffer, returning number of bytes read
-fn readline(buffer: &mut String) -> usize {
-    // Display input prompt based on state of buffer
-    if !buffer.is_empty() {
-        print!("... ");
-    } else {
-        print!(">>> ");
+/// Evaluates the statements in `input` in the REPL environment, returning the value of the last one.
+/// Statements before an error keep their effects
+pub fn eval_input(repl: &Repl, input: &str) -> Result<Value, Error> {
+    let program = PuffinParser::parse_program(input)?;
+    let mut res = Value::Null;
+    for stmt_ast in &program.program {
+        res = repl.repl_statement(stmt_ast)?.unwrap_or(Value::Null);
     }
-    // flush stdout to display prompt
-    io::stdout().flush().expect("Output Error");
+    Ok(res)
+}
 
-    io::stdin().read_line(buffer).expect("Input Error")
+/// Returns true if `input` has brackets or a string that aren't closed,
+/// so it continues on the next line
+pub fn incomplete(input: &str) -> bool {
+    let mut depth = 0i32;
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            // strings run to their closing quote
+            '"' => {
+                let closed = chars.by_ref().any(|c| c == '"');
+                if !closed {
+                    return true;
+                }
+            }
+            // comments run to the end of the line
+            '/' if chars.peek() == Some(&'/') => {
+                chars.by_ref().find(|c| *c == '\n');
+            }
+            _ => {}
+        }
+    }
+    depth > 0
+}
+
+/// rustyline helper completing the names bound in the REPL
+struct Names(Rc<Repl>);
+
+impl Completer for Names {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        let before = &line[..pos];
+        let start = before
+            .char_indices()
+            .rev()
+            .find(|(_, c)| !(c.is_alphanumeric() || *c == '_'))
+            .map_or(0, |(i, c)| i + c.len_utf8());
+        // structure fields aren't names
+        if before[..start].ends_with('.') {
+            return Ok((start, Vec::new()));
+        }
+        Ok((start, self.0.complete(&before[start..])))
+    }
+}
+
+impl Hinter for Names {
+    type Hint = String;
+}
+
+impl Highlighter for Names {}
+
+impl Validator for Names {}
+
+impl Helper for Names {}
+
+fn readline_error(err: ReadlineError) -> Error {
+    match err {
+        ReadlineError::Io(err) => Error::Io(err),
+        err => Error::Io(io::Error::other(err.to_string())),
+    }
 }
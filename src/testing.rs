@@ -0,0 +1,118 @@
+//! Author: Rafael Bayer (2021)
+//! The testing module finds and runs the tests of Puffin programs, for `puffin test`.
+//!
+//! A test is a closure without arguments, assigned at the top level of a program
+//! to a name starting with `test_`. Each test evaluates the top-level assignments of the program
+//! in a new global `Environment`, then calls the test. Other top-level statements don't run,
+//! so the program's own side effects don't happen once per test.
+//! A test passes if the call returns without an error,
+//! such as a failed `assert`, `assert_eq`, or `assert_throws`.
+//! Tests read no input, their output is captured to be shown if they fail,
+//! and they run under a step limit, so a test that never ends fails.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use crate::{
+    ast::node::{ExpKind, Program, StatementKind, ValueKind},
+    interpreter::{
+        self,
+        hooks::Hooks,
+        runtime::{Limits, Runtime, SharedBuffer},
+        Captured,
+    },
+};
+
+/// Prefix of the names of tests
+pub const PREFIX: &str = "test_";
+
+/// Maximum number of steps of a test, including the steps of its program's assignments
+pub const MAX_STEPS: u64 = 100_000_000;
+
+/// Returns the names of the tests of `program`, in the order they are defined
+pub fn discover(program: &Program) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for statement in &program.program {
+        if let StatementKind::Assign { lhs, rhs, .. } = &statement.statement {
+            let test = lhs.assignable.is_empty()
+                && lhs.name.starts_with(PREFIX)
+                && matches!(&rhs.exp, ExpKind::Value(ValueKind::FunctionDef { args, .. }) if args.is_empty());
+            if test && !names.contains(&lhs.name) {
+                names.push(lhs.name.clone());
+            }
+        }
+    }
+    names
+}
+
+/// Returns true if the name of a test contains `filter`, or if there is no filter
+pub fn selected(name: &str, filter: Option<&str>) -> bool {
+    filter.is_none_or(|filter| name.contains(filter))
+}
+
+/// Runs the test of `program` named `name`, under `hooks` if given.
+/// Returns the result of the test's call, and everything the test wrote
+pub fn run(program: &Program, name: &str, hooks: Option<Rc<dyn Hooks>>) -> Captured {
+    let stdout = SharedBuffer::new();
+    let stderr = SharedBuffer::new();
+    let mut runtime = Runtime::new()
+        .with_limits(Limits {
+            max_steps: Some(MAX_STEPS),
+            ..Limits::default()
+        })
+        .with_stdin(io::empty())
+        .with_stdout(stdout.clone())
+        .with_stderr(stderr.clone());
+    if let Some(hooks) = hooks {
+        runtime = runtime.with_hooks(hooks);
+    }
+
+    let result = interpreter::eval_test(program, runtime, name);
+    Captured {
+        result,
+        stdout: stdout.contents(),
+        stderr: stderr.contents(),
+    }
+}
+
+/// Returns the Puffin source files to test in `paths`.
+/// Files are tested whatever their extension, directories are searched for `.puf` files
+pub fn sources(paths: &[String]) -> io::Result<Vec<PathBuf>> {
+    let mut sources = Vec::new();
+    for path in paths {
+        let path = Path::new(path);
+        if path.is_dir() {
+            find(path, &mut sources)?;
+            // files found in the current directory are named without `./`
+            for source in sources.iter_mut() {
+                if let Ok(relative) = source.strip_prefix(".") {
+                    *source = relative.to_path_buf();
+                }
+            }
+        } else {
+            // fails on missing files
+            fs::metadata(path)?;
+            sources.push(path.to_path_buf());
+        }
+    }
+    Ok(sources)
+}
+
+/// finds the `.puf` files in a directory and its subdirectories, sorted by path
+fn find(dir: &Path, sources: &mut Vec<PathBuf>) -> io::Result<()> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<_>>()?;
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir() {
+            find(&entry, sources)?;
+        } else if entry.extension().is_some_and(|extension| extension == "puf") {
+            sources.push(entry);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,147 @@
+//! Author: Rafael Bayer (2021)
+//! The tracer module defines the `Tracer`, which prints a program's execution as it runs.
+//!
+//! The tracer is a set of interpreter `Hooks`. It prints each statement with its location,
+//! each closure call with its arguments and result, and each assignment with its value,
+//! indented by the depth of the call executing them.
+//! Tracing can be limited to the calls of some closures, including the calls they make.
+
+use std::{
+    cell::{Cell, RefCell},
+    io::{self, Write},
+    rc::Rc,
+};
+
+use crate::{
+    ast::node::{Assignable, AssignableKind, Statement},
+    interpreter::{
+        hooks::{Call, Hooks},
+        value::Environment,
+        InterpreterError, Value,
+    },
+};
+
+/// Tracer, prints the statements, calls, and assignments of a program as it runs
+pub struct Tracer {
+    name: String,
+    source: String,
+    /// names of the closures to trace the calls of, or every closure if empty
+    functions: Vec<String>,
+    /// whether each call being executed is traced
+    frames: RefCell<Vec<bool>>,
+    /// number of calls being executed of the closures to trace
+    active: Cell<usize>,
+    output: RefCell<Box<dyn Write>>,
+}
+
+impl Tracer {
+    /// Returns a Tracer for the program in `source`, named `name` in locations,
+    /// writing to stderr
+    pub fn new(name: &str, source: &str) -> Tracer {
+        Self::with_output(name, source, io::stderr())
+    }
+
+    /// Returns a Tracer for the program in `source`, named `name` in locations,
+    /// writing to `output`
+    pub fn with_output<W: Write + 'static>(name: &str, source: &str, output: W) -> Tracer {
+        Tracer {
+            name: name.to_string(),
+            source: source.to_string(),
+            functions: Vec::new(),
+            frames: RefCell::new(Vec::new()),
+            active: Cell::new(0),
+            output: RefCell::new(Box::new(output)),
+        }
+    }
+
+    /// Only traces the calls of the closures named in `functions`, and the calls they make
+    pub fn with_functions(mut self, functions: Vec<String>) -> Tracer {
+        self.functions = functions;
+        self
+    }
+
+    /// returns true if the current statement is traced
+    fn tracing(&self) -> bool {
+        self.functions.is_empty() || self.active.get() > 0
+    }
+
+    /// writes a line, indented by the depth of the current call
+    fn trace(&self, line: &str) {
+        let indent = "  ".repeat(self.frames.borrow().len());
+        // a trace that can't be written shouldn't stop the program
+        let _ = writeln!(self.output.borrow_mut(), "{}{}", indent, line);
+    }
+
+    /// returns the source of an assignable, such as `a[i].b`
+    fn assignable(&self, lhs: &Assignable) -> String {
+        let mut text = lhs.name.clone();
+        for sub in &lhs.assignable {
+            match sub {
+                AssignableKind::ArrayIndex { index } => {
+                    text.push('[');
+                    text.push_str(&self.source[index.span.start..index.span.end]);
+                    text.push(']');
+                }
+                AssignableKind::StructureField { field } => {
+                    text.push('.');
+                    text.push_str(field);
+                }
+            }
+        }
+        text
+    }
+}
+
+impl Hooks for Tracer {
+    fn statement(
+        &self,
+        statement: &Statement,
+        _env: &Rc<RefCell<Environment>>,
+    ) -> Result<(), InterpreterError> {
+        if self.tracing() {
+            let span = statement.span;
+            // statements with blocks are traced by their first line
+            let text = self.source[span.start..span.end].lines().next().unwrap_or_default();
+            self.trace(&format!("{}:{}:{}: {}", self.name, span.line, span.col, text));
+        }
+        Ok(())
+    }
+
+    fn assign(&self, lhs: &Assignable, value: &Value) {
+        if self.tracing() {
+            self.trace(&format!("{} = {}", self.assignable(lhs), value));
+        }
+    }
+
+    fn enter_call(&self, call: &Call) {
+        let name = call.name.unwrap_or("<anonymous>");
+        let traced = self.functions.iter().any(|function| function == name);
+        if traced {
+            self.active.set(self.active.get() + 1);
+        }
+        if self.tracing() {
+            let args: Vec<String> = call
+                .args
+                .iter()
+                .zip(call.values)
+                .map(|(arg, value)| format!("{} = {}", arg, value))
+                .collect();
+            self.trace(&format!("-> {}({})", name, args.join(", ")));
+        }
+        self.frames.borrow_mut().push(traced);
+    }
+
+    fn exit_call(&self, call: &Call, result: &Result<Value, InterpreterError>) {
+        let traced = self.frames.borrow_mut().pop().unwrap_or_default();
+        if self.tracing() {
+            let name = call.name.unwrap_or("<anonymous>");
+            match result {
+                Ok(value) => self.trace(&format!("<- {} returned {}", name, value)),
+                Err(err) => self.trace(&format!("<- {} failed: {}", name, err)),
+            }
+        }
+        if traced {
+            self.active.set(self.active.get() - 1);
+        }
+    }
+}
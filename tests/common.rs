@@ -1,22 +1,44 @@
 //! Author: Rafael Bayer (2021)
 //! This module contains common test code for Puffin Integration tests. 
 //!
-//! `run_program` is used to easily run a program from a passed str.
+//! `run_program` is used to easily run a program from a passed str,
+//! `capture_program` runs a program with the given input and captures its output.
 
+#[allow(unused_imports)]
 pub use puffin::{
     ast::{self, node::*},
-    interpreter::{self, value::Environment, Value},
-    parser, Parser,
+    interpreter::{
+        self,
+        runtime::{Capability, Limits, Runtime, SharedBuffer, DEFAULT_MAX_DEPTH},
+        value::{heap, Environment},
+        Captured, InterpreterError, Value,
+    },
+    parser,
 };
 
 /// run_program executes a Puffin program in a given str,
 /// returning the resulting value.
-/// Panics if the parser, AST generator, or interpreter encounter any error.
+/// Panics if the parser or interpreter encounter any error.
 pub fn run_program(program: &str) -> Value {
-    let parsed = parser::PuffinParser::parse_program(program)
-        .unwrap()
-        .next()
-        .unwrap();
-    let ast = ast::build_program(parsed).unwrap();
-    interpreter::eval(&ast).unwrap()
+    interpreter::eval(&build_program(program)).unwrap()
+}
+
+/// run_program_with executes a Puffin program in a given str under a given Runtime,
+/// returning the result of the interpreter.
+/// Panics if the parser encounters any error.
+pub fn run_program_with(program: &str, runtime: Runtime) -> Result<Value, InterpreterError> {
+    interpreter::eval_with(&build_program(program), runtime)
+}
+
+/// capture_program executes a Puffin program in a given str reading `stdin` as its input,
+/// returning the result of the interpreter along with everything the program printed.
+/// Panics if the parser encounters any error.
+pub fn capture_program(program: &str, stdin: &str) -> Captured {
+    interpreter::eval_captured(&build_program(program), Runtime::new(), stdin)
+}
+
+/// build_program parses a Puffin program in a given str into its AST.
+/// Panics if the parser encounters any error.
+pub fn build_program(program: &str) -> Program {
+    parser::PuffinParser::parse_program(program).unwrap()
 }
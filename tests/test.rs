@@ -214,7 +214,7 @@ mod test {
 
         return prod;
         "#,
-        Value::Num((1_u128..25_u128).fold(1, |a, b| a * b) as f64)
+        Value::Num((1_u128..25_u128).product::<u128>() as f64)
     );
 
     test!(
@@ -228,7 +228,7 @@ mod test {
 
         return prod;
         "#,
-        Value::Num((1_u128..23_u128).fold(1, |a, b| a * b) as f64)
+        Value::Num((1_u128..23_u128).product::<u128>() as f64)
     );
 
     test!(
@@ -432,4 +432,1720 @@ mod test {
         "#,
         Value::Null
     );
+
+    #[test]
+    fn gc_collects_receiver_cycles() {
+        let counters = 10;
+        let program = format!(
+            r#"
+            counter = fn() => {{
+                count: 0,
+                inc: fn(self) {{
+                    self.count += 1;
+                }}
+            }};
+
+            for (i in [0:{}]) {{
+                c = counter();
+                c.inc();
+            }}
+
+            return gc();
+            "#,
+            counters
+        );
+
+        // every counter but the last, which `c` still holds, is a cycle between its structure
+        // and its receiver. Collecting it frees the structure and the environment of the
+        // `counter` call the receiver captured
+        let freed = (counters - 1) * 2;
+        assert_eq!(Value::from(freed as f64), run_program(&program));
+    }
+
+    #[test]
+    fn heap_stats_after_eval() {
+        run_program(
+            r#"
+            stack = fn() => {
+                inner: [0],
+                push: fn(self, e) => push(self.inner, e)
+            };
+
+            s = stack();
+            for (i in [0:100]) {
+                s.push(stack());
+            }
+            "#,
+        );
+
+        // the program's environment is kept alive by the closures bound in it
+        assert!(heap::stats().environments > 0);
+
+        heap::collect();
+        let stats = heap::stats();
+        assert_eq!(0, stats.arrays, "{:?}", stats);
+        assert_eq!(0, stats.structures, "{:?}", stats);
+        assert_eq!(0, stats.environments, "{:?}", stats);
+        assert!(stats.freed > 100, "{:?}", stats);
+    }
+
+    #[test]
+    fn step_limit() {
+        let runtime = Runtime::new().with_limits(Limits {
+            max_steps: Some(1000),
+            ..Limits::default()
+        });
+        let result = run_program_with("while (true) {}", runtime);
+        assert!(matches!(result, Err(InterpreterError::StepLimit(1000))), "{:?}", result);
+    }
+
+    #[test]
+    fn depth_limit() {
+        let runtime = Runtime::new().with_limits(Limits {
+            max_depth: Some(100),
+            ..Limits::default()
+        });
+        let result = run_program_with("f = fn(n) => f(n + 1); f(0);", runtime);
+        assert!(matches!(result, Err(InterpreterError::DepthLimit(100))), "{:?}", result);
+    }
+
+    #[test]
+    fn default_depth_limit() {
+        // deep calls grow the stack, so the default depth fits on the stack of any thread
+        let result = run_program_with("f = fn(n) => f(n + 1); return f(0);", Runtime::new());
+        assert!(matches!(result, Err(InterpreterError::DepthLimit(DEFAULT_MAX_DEPTH))), "{:?}", result);
+
+        let program = "f = fn(n) { if (n == 0) { return 0; } return 1 + f(n - 1); }; return f(990);";
+        assert_eq!(Value::Num(990f64), run_program_with(program, Runtime::new()).unwrap());
+    }
+
+    #[test]
+    fn memory_limit() {
+        let limits = Limits {
+            max_memory: Some(1000),
+            ..Limits::default()
+        };
+
+        let programs = [
+            "a = [0]; while (true) { push(a, 1); }",
+            "a = [100000000000];",
+            "s = \"puffin\"; while (true) { s += s; }",
+            // the limit is a total, values freed in each iteration still count
+            "while (true) { s = str(12345); }",
+            "while (true) { s = \"puffin\"; }",
+            "while (true) { p = {x: 1, y: 2}; }",
+        ];
+
+        for program in programs {
+            let result = run_program_with(program, Runtime::new().with_limits(limits.clone()));
+            assert!(matches!(result, Err(InterpreterError::MemoryLimit(1000))), "{}: {:?}", program, result);
+        }
+    }
+
+    #[test]
+    fn time_limit() {
+        let limits = Limits {
+            max_time: Some(std::time::Duration::from_millis(50)),
+            ..Limits::default()
+        };
+
+        // loops with empty bodies still check the limit on every iteration
+        let programs = [
+            "while (true) {}",
+            "for (i = 0; true; i += 0) {}",
+            "for (x in [0:3000000]) {}",
+        ];
+
+        for program in programs {
+            let result = run_program_with(program, Runtime::new().with_limits(limits.clone()));
+            assert!(matches!(result, Err(InterpreterError::TimeLimit(_))), "{}: {:?}", program, result);
+        }
+    }
+
+    #[test]
+    fn within_limits() {
+        let runtime = Runtime::new().with_limits(Limits {
+            max_steps: Some(100_000),
+            max_depth: Some(100),
+            max_memory: Some(1000),
+            max_time: Some(std::time::Duration::from_secs(10)),
+        });
+        let result = run_program_with("f = fn(n) { if (n < 2) { return 1; } return n * f(n - 1); }; return f(10);", runtime);
+        assert_eq!(Value::from(3628800f64), result.unwrap());
+    }
+
+    test!(time_is_positive, r#"return time() > 0;"#, Value::from(1f64));
+
+    #[test]
+    fn missing_capability() {
+        let runtime = Runtime::new().with_capabilities(vec![Capability::Random]);
+        let result = run_program_with(r#"x = rand(); println(x);"#, runtime);
+        assert!(
+            matches!(
+                &result,
+                Err(InterpreterError::MissingCapability { name, capability: Capability::Console }) if name == "println"
+            ),
+            "{:?}",
+            result
+        );
+
+        // error writes to the error output, so it needs the console too
+        let stderr = SharedBuffer::new();
+        let runtime = Runtime::new()
+            .with_capabilities(vec![Capability::Random])
+            .with_stderr(stderr.clone());
+        let result = run_program_with(r#"error("leak");"#, runtime);
+        assert!(
+            matches!(
+                &result,
+                Err(InterpreterError::MissingCapability { name, capability: Capability::Console }) if name == "error"
+            ),
+            "{:?}",
+            result
+        );
+        assert_eq!("", stderr.contents());
+    }
+
+    #[test]
+    fn no_capabilities() {
+        // builtins without side effects are always available
+        let runtime = Runtime::new().with_capabilities(vec![]);
+        let result = run_program_with(r#"return sqrt(len([16]));"#, runtime);
+        assert_eq!(Value::from(4f64), result.unwrap());
+
+        // disabled builtins remain reserved names
+        let runtime = Runtime::new().with_capabilities(vec![]);
+        let result = run_program_with(r#"rand = fn() => 4;"#, runtime);
+        assert!(matches!(result, Err(InterpreterError::BuiltinRebinding(_))), "{:?}", result);
+    }
+
+    #[test]
+    fn captured_output() {
+        let captured = capture_program(r#"print("a", 1); a = [1:3]; a[0] = "b"; println(a); println("");"#, "");
+        assert_eq!(Value::Null, captured.result.unwrap());
+        assert_eq!("a 1 ['b', 2]\n\n", captured.stdout);
+        assert_eq!("", captured.stderr);
+    }
+
+    #[test]
+    fn captured_input() {
+        let program = r#"
+        name = input_str("name?");
+        n = input_num("n?");
+        println("hello", name, n * 2);
+        return n;
+        "#;
+        let captured = capture_program(program, "puffin\n21\n");
+        assert_eq!(Value::from(21f64), captured.result.unwrap());
+        assert_eq!("name? n? hello puffin 42\n", captured.stdout);
+    }
+
+    #[test]
+    fn captured_error() {
+        let captured = capture_program(r#"println("before"); error("oops", 1); println("after");"#, "");
+        assert!(matches!(captured.result, Err(InterpreterError::Error)));
+        assert_eq!("before\n", captured.stdout);
+        assert_eq!("ERR: oops 1 \n", captured.stderr);
+    }
+
+    #[test]
+    fn shared_buffer_stdout() {
+        let buffer = SharedBuffer::new();
+        let runtime = Runtime::new().with_stdout(buffer.clone());
+        run_program_with(r#"println("first"); print("second");"#, runtime).unwrap();
+        assert_eq!("first\nsecond ", buffer.contents());
+    }
+
+    #[test]
+    fn seeded_random() {
+        let program = r#"
+        sample = fn() => str(rand()) + str(rand_int(1, 100)) + str(choice([0:10])) + str(shuffle([0:10]));
+        seed(42);
+        a = sample();
+        seed(42);
+        return a == sample();
+        "#;
+        assert_eq!(Value::from(1f64), run_program(program));
+    }
+
+    #[test]
+    fn seeded_runtime() {
+        let program = r#"
+        x = rand_int(-1000, 1000);
+        seed(7);
+        return x == rand_int(-1000, 1000);
+        "#;
+        // seeding the runtime and calling seed() produce the same sequence
+        let result = run_program_with(program, Runtime::new().with_seed(7));
+        assert_eq!(Value::from(1f64), result.unwrap());
+    }
+
+    test!(rand_int_inclusive, r#"
+    seen = [3];
+    for (i = 0; i < 200; i += 1) {
+        n = rand_int(1, 3);
+        seen[n - 1] = 1;
+    }
+    return seen[0] && seen[1] && seen[2] && rand_int(5, 5) == 5;
+    "#, Value::from(1f64));
+
+    test!(shuffle_permutes, r#"
+    a = shuffle([0:10]);
+    sum = 0;
+    for (i = 0; i < len(a); i += 1) {
+        sum += a[i];
+    }
+    return sum;
+    "#, Value::from(45f64));
+
+    #[test]
+    fn random_errors() {
+        let result = run_program_with(r#"return rand_int(3, 1);"#, Runtime::new());
+        assert!(matches!(result, Err(InterpreterError::RangeError { from: 3, to: 1 })), "{:?}", result);
+
+        let result = run_program_with(r#"return choice([0]);"#, Runtime::new());
+        assert!(matches!(result, Err(InterpreterError::BoundsError { .. })), "{:?}", result);
+    }
+
+    #[test]
+    fn guessing_game_seeded() {
+        let program = include_str!("../samples/guessing_game.puf");
+        let input: String = std::iter::once(10)
+            .chain(0..=10)
+            .map(|n| format!("{}\n", n))
+            .collect();
+        let first = interpreter::eval_captured(&build_program(program), Runtime::new().with_seed(3), &input);
+        let second = interpreter::eval_captured(&build_program(program), Runtime::new().with_seed(3), &input);
+        assert!(first.result.is_ok(), "{:?}", first.result);
+        assert!(first.stdout.contains("You got it!"));
+        assert_eq!(first.stdout, second.stdout);
+    }
+
+    /// returns a fresh scratch directory for filesystem tests
+    fn scratch_dir(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("puffin-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn builtin_signatures() {
+        let builtin = |name: &str| match run_program(&format!("return {};", name)) {
+            Value::Builtin(builtin) => builtin,
+            other => panic!("{:?}", other),
+        };
+
+        let stringify = builtin("json_stringify");
+        assert_eq!(&["a", "indent?"], stringify.params());
+        assert_eq!(Some((1, 2)), stringify.arity());
+        assert!(stringify.doc().contains("JSON"));
+        assert_eq!(Some((0, 0)), builtin("rand").arity());
+        assert_eq!(Some((2, 3)), builtin("assert_eq").arity());
+        // prints take any number of arguments
+        assert_eq!(None, builtin("println").arity());
+        assert_eq!(Some(Capability::Console), builtin("println").capability());
+        assert_eq!(None, builtin("len").capability());
+    }
+
+    #[test]
+    fn filesystem_builtins() {
+        let dir = scratch_dir("fs");
+        let program = format!(r#"
+        dir = "{}";
+        mkdir(dir + "/nested/deeper");
+        file = dir + "/report.txt";
+        write_file(file, "first");
+        append_file(file, "
+second ");
+        append_file(file, 3);
+        write_file(dir + "/a.txt", "");
+        lines = read_lines(file);
+        contents = read_file(file);
+        listing = list_dir(dir);
+        removed = remove_file(dir + "/a.txt");
+        return contents + str(len(lines)) + lines[1] + str(exists(file)) + str(exists(dir + "/missing"))
+            + str(listing) + str(removed) + str(exists(dir + "/a.txt"));
+        "#, dir);
+        let result = run_program(&program);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(Value::String("first\nsecond 32second 310['a.txt', 'nested', 'report.txt']null0".to_string()), result);
+    }
+
+    #[test]
+    fn filesystem_errors() {
+        let dir = scratch_dir("fs-errors");
+        let result = run_program_with(&format!(r#"return read_file("{}/missing.txt");"#, dir), Runtime::new());
+        assert!(matches!(result, Err(InterpreterError::IOError(_))), "{:?}", result);
+
+        let runtime = Runtime::new().with_capabilities(vec![Capability::Console]);
+        let result = run_program_with(r#"return exists(".");"#, runtime);
+        assert!(
+            matches!(
+                result,
+                Err(InterpreterError::MissingCapability { capability: Capability::Filesystem, .. })
+            ),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn json_stringify() {
+        let program = r#"
+        user = {
+            name: "Rafi",
+            age: 22,
+            score: 0.5,
+            tags: [2],
+            contact: { github: "github.com/rafibayer" }
+        };
+        user.tags[0] = "a";
+        return json_stringify(user);
+        "#;
+        assert_eq!(
+            Value::String(r#"{"age":22,"contact":{"github":"github.com/rafibayer"},"name":"Rafi","score":0.5,"tags":["a",null]}"#.to_string()),
+            run_program(program)
+        );
+
+        let program = r#"
+        return json_stringify({ a: [1:3], b: null }, 2);
+        "#;
+        assert_eq!(
+            Value::String("{\n  \"a\": [\n    1,\n    2\n  ],\n  \"b\": null\n}".to_string()),
+            run_program(program)
+        );
+    }
+
+    test!(json_roundtrip, r#"
+    shared = { n: 1 };
+    data = { list: [0:4], a: shared, b: shared, text: "hello world" };
+    copy = json_parse(json_stringify(data));
+    same = json_stringify(copy) == json_stringify(data);
+    // shared containers are written once per reference, and parse as separate copies
+    copy.a.n = 2;
+    return same && (copy.b.n == 1) && (copy.list[3] == 3) && (copy.text == "hello world");
+    "#, Value::from(1f64));
+
+    #[test]
+    fn json_parse() {
+        let dir = scratch_dir("json");
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = format!("{}/data.json", dir);
+        std::fs::write(&file, r#"{"ok": true, "bad": false, "items": [1, 2.5, "three", null], "nested": {"x": -1e3}}"#).unwrap();
+
+        let program = format!(r#"
+        data = json_parse(read_file("{}"));
+        return str(data.ok) + str(data.bad) + str(len(data.items)) + str(data.items[1]) + data.items[2] + str(data.items[3]) + str(data.nested.x);
+        "#, file);
+        let result = run_program(&program);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(Value::String("1042.5threenull-1000".to_string()), result);
+    }
+
+    #[test]
+    fn json_errors() {
+        let programs = [
+            r#"return json_parse("{");"#,
+            r#"return json_stringify({ f: fn() => 1 });"#,
+            r#"return json_stringify(sqrt);"#,
+            r#"a = [1]; a[0] = a; return json_stringify(a);"#,
+            r#"s = {}; s.self = s; return json_stringify({ inner: s });"#,
+            r#"return json_stringify(1 / 0);"#,
+        ];
+        for program in programs.iter() {
+            let result = run_program_with(program, Runtime::new());
+            assert!(matches!(result, Err(InterpreterError::JsonError(_))), "{}: {:?}", program, result);
+        }
+
+        // the indent is optional
+        let result = run_program_with(r#"return json_stringify(1, 2, 3);"#, Runtime::new());
+        assert_eq!(
+            "expected 1 or 2 arguments, got 3 when calling `json_stringify`",
+            result.unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn script_args() {
+        let runtime = Runtime::new().with_args(vec!["input.txt".to_string(), "-v".to_string()]);
+        let result = run_program_with(r#"return str(len(ARGS)) + ARGS[0] + ARGS[1];"#, runtime);
+        assert_eq!(Value::String("2input.txt-v".to_string()), result.unwrap());
+
+        assert_eq!(Value::from(0f64), run_program(r#"return len(ARGS);"#));
+
+        let result = run_program_with(r#"ARGS = 1;"#, Runtime::new());
+        assert!(matches!(result, Err(InterpreterError::BuiltinRebinding(_))), "{:?}", result);
+    }
+
+    #[test]
+    fn config_args() {
+        let args = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<String>>();
+
+        let config = puffin::Config::new(&args(&["puffin", "s.puf", "-ast", "input.txt", "-ast"])).unwrap();
+        assert!(config.show_ast);
+        assert_eq!(args(&["input.txt", "-ast"]), config.args);
+
+        let config = puffin::Config::new(&args(&["puffin", "s.puf", "--seed", "1", "--", "-x"])).unwrap();
+        assert_eq!(Some(1), config.seed);
+        assert_eq!(args(&["-x"]), config.args);
+
+        let config = puffin::Config::new(&args(&["puffin", "s.puf", "--profile", "out.folded", "x"])).unwrap();
+        assert_eq!(Some("out.folded".to_string()), config.profile);
+        assert_eq!(args(&["x"]), config.args);
+
+        let config = puffin::Config::new(&args(&["puffin", "s.puf", "--coverage", "lcov.info"])).unwrap();
+        assert_eq!(Some("lcov.info".to_string()), config.coverage);
+        assert_eq!(None, config.profile);
+        assert!(!config.trace);
+
+        let config = puffin::Config::new(&args(&["puffin", "s.puf", "--trace-fn", "f", "--trace-fn", "g"])).unwrap();
+        assert!(config.trace);
+        assert_eq!(args(&["f", "g"]), config.trace_functions);
+
+        let config = puffin::Config::new(&args(&[
+            "puffin", "s.puf", "--max-steps", "10", "--max-depth", "20", "--max-memory", "30", "--timeout", "1.5",
+        ]))
+        .unwrap();
+        assert_eq!(Some(10), config.limits.max_steps);
+        assert_eq!(Some(20), config.limits.max_depth);
+        assert_eq!(Some(30), config.limits.max_memory);
+        assert_eq!(Some(std::time::Duration::from_millis(1500)), config.limits.max_time);
+
+        let config = puffin::Config::new(&args(&["puffin", "s.puf"])).unwrap();
+        assert_eq!(Some(DEFAULT_MAX_DEPTH), config.limits.max_depth);
+        assert_eq!(None, config.limits.max_steps);
+
+        assert!(puffin::Config::new(&args(&["puffin", "s.puf", "-x"])).is_err());
+        assert!(puffin::Config::new(&args(&["puffin", "s.puf", "--profile"])).is_err());
+        assert!(puffin::Config::new(&args(&["puffin", "s.puf", "--coverage"])).is_err());
+        assert!(puffin::Config::new(&args(&["puffin", "s.puf", "--trace-fn"])).is_err());
+        assert!(puffin::Config::new(&args(&["puffin", "s.puf", "--max-steps"])).is_err());
+        assert!(puffin::Config::new(&args(&["puffin", "s.puf", "--max-depth", "deep"])).is_err());
+        assert!(puffin::Config::new(&args(&["puffin", "s.puf", "--timeout", "-1"])).is_err());
+    }
+
+    #[test]
+    fn env_lookup() {
+        std::env::set_var("PUFFIN_TEST_ENV", "quack");
+        let program = r#"return env("PUFFIN_TEST_ENV") + str(env("PUFFIN_TEST_UNSET"));"#;
+        assert_eq!(Value::String("quacknull".to_string()), run_program(program));
+
+        let runtime = Runtime::new().with_capabilities(vec![Capability::Console]);
+        let result = run_program_with(r#"return env("PATH");"#, runtime);
+        assert!(matches!(result, Err(InterpreterError::MissingCapability { .. })), "{:?}", result);
+    }
+
+    #[test]
+    fn exit_code() {
+        let captured = capture_program(r#"println("bye"); exit(3); println("unreachable");"#, "");
+        assert!(matches!(captured.result, Err(InterpreterError::Exit(3))), "{:?}", captured.result);
+        assert_eq!("bye\n", captured.stdout);
+    }
+
+    #[test]
+    fn cli_commands() {
+        use puffin::cli::{Command, Source};
+        let command = |args: &[&str]| Command::new(&args.iter().map(|a| a.to_string()).collect::<Vec<String>>());
+
+        assert_eq!(Ok(Command::Repl), command(&["puffin"]));
+        assert_eq!(Ok(Command::Repl), command(&["puffin", "repl"]));
+        assert_eq!(Ok(Command::Help), command(&["puffin", "--help"]));
+        let helps = [
+            &["puffin", "run", "--help"][..],
+            &["puffin", "debug", "-h"],
+            &["puffin", "fmt", "a.puf", "--help"],
+        ];
+        for args in helps.iter() {
+            assert_eq!(Ok(Command::Help), command(args), "{:?}", args);
+        }
+        for subcommand in ["repl", "check", "ast", "tokens", "test"].iter() {
+            assert_eq!(Ok(Command::Help), command(&["puffin", subcommand, "-h"]), "{}", subcommand);
+        }
+        // after the source, arguments are passed to the program
+        match command(&["puffin", "run", "a.puf", "--", "--help"]) {
+            Ok(Command::Run(config)) => assert_eq!(vec!["--help".to_string()], config.args),
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(Ok(Command::Version), command(&["puffin", "-V"]));
+        assert_eq!(Ok(Command::Check(Source::Stdin)), command(&["puffin", "check", "-"]));
+        assert_eq!(Ok(Command::Ast(Source::File("a.puf".to_string()))), command(&["puffin", "ast", "a.puf"]));
+        assert_eq!(
+            Ok(Command::Tokens(Source::Inline("x = 1;".to_string()))),
+            command(&["puffin", "tokens", "-e", "x = 1;"])
+        );
+
+        // run, with or without the subcommand
+        for args in [&["puffin", "run", "a.puf", "x"][..], &["puffin", "a.puf", "x"][..]].iter() {
+            match command(args) {
+                Ok(Command::Run(config)) => {
+                    assert_eq!(Source::File("a.puf".to_string()), config.source);
+                    assert_eq!(vec!["x".to_string()], config.args);
+                }
+                other => panic!("{:?}", other),
+            }
+        }
+        match command(&["puffin", "-e", "return 1;", "--seed", "3"]) {
+            Ok(Command::Run(config)) => {
+                assert_eq!(Source::Inline("return 1;".to_string()), config.source);
+                assert_eq!(Some(3), config.seed);
+            }
+            other => panic!("{:?}", other),
+        }
+
+        match command(&["puffin", "debug", "a.puf", "--check-types", "x"]) {
+            Ok(Command::Debug(config)) => {
+                assert_eq!(Source::File("a.puf".to_string()), config.source);
+                assert!(config.check_types);
+                assert_eq!(vec!["x".to_string()], config.args);
+            }
+            other => panic!("{:?}", other),
+        }
+
+        assert_eq!(
+            Ok(Command::Fmt {
+                sources: vec![Source::File("a.puf".to_string()), Source::Stdin],
+                check: true
+            }),
+            command(&["puffin", "fmt", "a.puf", "--check", "-"])
+        );
+
+        assert_eq!(
+            Ok(Command::Test {
+                paths: vec![".".to_string()],
+                filter: None,
+                coverage: None,
+            }),
+            command(&["puffin", "test"])
+        );
+        assert_eq!(
+            Ok(Command::Test {
+                paths: vec!["a.puf".to_string(), "lib".to_string()],
+                filter: Some("add".to_string()),
+                coverage: Some("lcov.info".to_string()),
+            }),
+            command(&["puffin", "test", "a.puf", "--filter", "add", "lib", "--coverage", "lcov.info"])
+        );
+
+        assert!(command(&["puffin", "check"]).is_err());
+        assert!(command(&["puffin", "test", "--filter"]).is_err());
+        assert!(command(&["puffin", "test", "--bogus"]).is_err());
+        assert!(command(&["puffin", "fmt", "--check"]).is_err());
+        assert!(command(&["puffin", "fmt", "--write", "a.puf"]).is_err());
+        assert!(command(&["puffin", "check", "a.puf", "b.puf"]).is_err());
+        assert!(command(&["puffin", "-e"]).is_err());
+        assert!(command(&["puffin", "--bogus"]).is_err());
+    }
+
+    #[test]
+    fn error_messages() {
+        let message = |program: &str| run_program_with(program, Runtime::new()).unwrap_err().to_string();
+
+        assert_eq!("expected 2 arguments, got 3 when calling `add`", message("add = fn(a, b) => a + b; add(1, 2, 3);"));
+        assert_eq!("expected 1 argument, got 0 when calling `sqrt`", message("sqrt();"));
+        assert_eq!("expected 1 argument, got 2", message("x = (fn(a) => a)(1, 2);"));
+        assert_eq!("unbound name `y`", message("x = y;"));
+        assert_eq!("can't rebind builtin `PI`", message("PI = 3;"));
+        assert_eq!("index 5 out of bounds for length 2", message("a = [2]; return a[5];"));
+        assert_eq!("program exited with code 4", message("exit(4);"));
+
+        let runtime = Runtime::new().with_capabilities(vec![]);
+        assert_eq!(
+            "`rand` requires the Random capability, which is unavailable",
+            run_program_with("return rand();", runtime).unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn errors_propagate() {
+        fn run(program: &str) -> Result<Value, Box<dyn std::error::Error>> {
+            let program = puffin::parse(program)?;
+            Ok(interpreter::eval(&program)?)
+        }
+
+        assert_eq!(Value::from(2f64), run("return 1 + 1;").unwrap());
+        assert_eq!("unbound name `nope`", run("return nope;").unwrap_err().to_string());
+    }
+
+    #[test]
+    fn library_entry_points() {
+        assert_eq!(Value::from(3f64), puffin::run_source("return 1 + 2;").unwrap());
+
+        let runtime = Runtime::new().with_limits(Limits {
+            max_steps: Some(10),
+            ..Limits::default()
+        });
+        let result = puffin::run_source_with("while (1) {}", runtime);
+        assert!(matches!(result, Err(puffin::Error::Runtime(InterpreterError::StepLimit(10)))), "{:?}", result);
+
+        assert!(matches!(puffin::run_source("x = ;"), Err(puffin::Error::Parse(_))));
+        assert!(matches!(puffin::run_source("return y;"), Err(puffin::Error::Runtime(InterpreterError::UnboundName(_)))));
+        assert!(matches!(puffin::run_file("does/not/exist.puf"), Err(puffin::Error::Io(_))));
+
+        let dir = scratch_dir("run-file");
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = format!("{}/main.puf", dir);
+        std::fs::write(&file, "return len(ARGS);").unwrap();
+        let result = puffin::run_file(&file);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(Value::from(0f64), result.unwrap());
+    }
+
+    #[test]
+    fn library_error_chain() {
+        use std::error::Error;
+
+        let err = puffin::run_source("exit(2);").unwrap_err();
+        assert_eq!("Runtime Error: program exited with code 2", err.to_string());
+        assert_eq!("program exited with code 2", err.source().unwrap().to_string());
+    }
+
+    #[test]
+    fn library_parse_errors() {
+        match puffin::run_source("x = ;\ny = 1\nz = 2;") {
+            Err(puffin::Error::Parse(errors)) => {
+                let lines: Vec<usize> = errors.iter().map(|err| err.span.line).collect();
+                assert_eq!(vec![1, 2], lines);
+            }
+            other => panic!("expected parse errors, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn format_program() {
+        let source = r#"// leading comment
+x=1;y = x+2*3 ; // trailing
+f=fn(a,b,)=>a-b;
+
+
+if(x<y){return   f(x,y);}else{
+    // only a comment
+}
+obj = {a:1 b:"s"};
+multi = {
+    a: [3], // the first field
+    b: [1:x]
+};
+for(i=0;i<3;i+=1){while !x {x=-(x+1);}}
+for (v in [0:2]) {}
+"#;
+        let expected = r#"// leading comment
+x = 1;
+y = x + 2 * 3; // trailing
+f = fn(a, b) => a - b;
+
+if (x < y) {
+    return f(x, y);
+} else {
+    // only a comment
+}
+obj = {a: 1, b: "s"};
+multi = {
+    a: [3], // the first field
+    b: [1:x]
+};
+for (i = 0; i < 3; i += 1) {
+    while !x {
+        x = -(x + 1);
+    }
+}
+for (v in [0:2]) {}
+"#;
+        let formatted = puffin::format_source(source).unwrap();
+        assert_eq!(expected, formatted);
+        // formatting is stable, and keeps the meaning of the program
+        assert_eq!(formatted, puffin::format_source(&formatted).unwrap());
+        assert_eq!(puffin::run_source(source).unwrap(), puffin::run_source(&formatted).unwrap());
+
+        assert_eq!("", puffin::format_source("").unwrap());
+        assert!(matches!(puffin::format_source("x = ;"), Err(puffin::Error::Parse(_))));
+    }
+
+    #[test]
+    fn format_comments() {
+        // comments before an `else` or a closing bracket stay where they are
+        let sources = [
+            "if (x) {\n    a = 1;\n} // after if\nelse {\n    a = 2;\n}\n",
+            "if (x) {\n    a = 1;\n}\n// own line\nelse {}\n",
+            "f = fn(a, // arg comment\n    b) {\n    return a;\n};\n",
+            "f(1, // one\n    2);\n",
+            "f(a, g(1, 2) // trailing\n);\n",
+            "h = { // empty\n};\n",
+            "h = {\n    // own line\n};\n",
+            "f( // no args\n);\n",
+        ];
+        for source in sources.iter() {
+            assert_eq!(*source, puffin::format_source(source).unwrap());
+        }
+    }
+
+    #[test]
+    fn format_check() {
+        use puffin::cli::Source;
+
+        for sample in [include_str!("../samples/hashmap.puf"), include_str!("../samples/guessing_game.puf")].iter() {
+            assert_eq!(*sample, puffin::format_source(sample).unwrap());
+        }
+
+        let dir = scratch_dir("fmt");
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = format!("{}/main.puf", dir);
+        std::fs::write(&file, "x=1;").unwrap();
+        let sources = vec![Source::File(file.clone())];
+
+        let unformatted = puffin::fmt(&sources, true).unwrap();
+        let unchanged = std::fs::read_to_string(&file).unwrap();
+        let written = puffin::fmt(&sources, false).unwrap();
+        let formatted = puffin::fmt(&sources, true).unwrap();
+        let contents = std::fs::read_to_string(&file).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(!unformatted);
+        assert_eq!("x=1;", unchanged);
+        assert!(written && formatted);
+        assert_eq!("x = 1;\n", contents);
+    }
+
+    /// returns the diagnostics of a program as `(line, message)`
+    fn diagnostics(program: &str) -> Vec<(usize, String)> {
+        puffin::checker::check_program(&build_program(program))
+            .into_iter()
+            .map(|diagnostic| (diagnostic.span.line, diagnostic.message))
+            .collect()
+    }
+
+    #[test]
+    fn checker_errors() {
+        use puffin::checker::{check_program, Severity};
+
+        let program = r#"add = fn(a, b) => a + b;
+add(1, 2, 3);
+PI = 3;
+f = fn() {
+    return missing + add(1, 2);
+};
+f();
+x = sqrt(1, 2) + json_stringify(1, 2) + json_stringify();
+(fn(a) => a)();
+"#;
+        assert_eq!(
+            vec![
+                (2, "expected 2 arguments, got 3 when calling `add`".to_string()),
+                (3, "can't rebind builtin `PI`".to_string()),
+                (5, "unbound name `missing`".to_string()),
+                (8, "expected 1 argument, got 2 when calling `sqrt`".to_string()),
+                (8, "expected 1 or 2 arguments, got 0 when calling `json_stringify`".to_string()),
+                (9, "expected 1 argument, got 0".to_string()),
+            ],
+            diagnostics(program)
+        );
+        let severities: Vec<Severity> = check_program(&build_program(program))
+            .into_iter()
+            .map(|diagnostic| diagnostic.severity)
+            .collect();
+        assert_eq!(6, severities.len());
+        assert!(severities.iter().all(|s| *s == Severity::Error));
+    }
+
+    #[test]
+    fn checker_scopes() {
+        // functions see names bound later in the scopes they were defined in,
+        // blocks don't have their own scope, and builtins can be shadowed in functions
+        let program = r#"is_even = fn(n) {
+    if (n == 0) {
+        return true;
+    }
+    return is_odd(n - 1);
+};
+is_odd = fn(n) => n != 0 && is_even(n - 1);
+if (is_even(4)) {
+    result = 1;
+}
+len = 0;
+g = fn(len) {
+    str = len;
+    return str;
+};
+for (i in [0:3]) {
+    result += g(i);
+}
+return result;
+"#;
+        assert_eq!(vec![(11, "can't rebind builtin `len`".to_string())], diagnostics(program));
+
+        // a function bound more than once has no known arity
+        assert_eq!(Vec::<(usize, String)>::new(), diagnostics("f = fn(a) => a; f = fn() => 1; return f();"));
+        // names assigned through a field or index must be bound
+        assert_eq!(vec![(1, "unbound name `s`".to_string())], diagnostics("s.x = 1;"));
+    }
+
+    #[test]
+    fn checker_warnings() {
+        let program = r#"f = fn(a) {
+    if (a) {
+        return 1;
+    } else {
+        return 2;
+    }
+    a = 3;
+    b = 4;
+    return a;
+};
+return f(1);
+"#;
+        assert_eq!(
+            vec![
+                (7, "unreachable code".to_string()),
+                (8, "unused variable `b`".to_string()),
+            ],
+            diagnostics(program)
+        );
+
+        // top-level variables may be used by tests or the REPL, so only local ones are unused
+        let program = "x = 1;\nf = fn() {\n    y = 2;\n};\n";
+        assert_eq!(vec![(3, "unused variable `y`".to_string())], diagnostics(program));
+    }
+
+    #[test]
+    fn type_annotations() {
+        let program = r#"
+        add = fn(a: num, b: num) -> num => a + b;
+        greet = fn(who: string) -> string => "hi " + who;
+        s = {f: fn(self, k: num) -> num => k};
+        total: num = add(1, 2) + s.f(3);
+        return greet(str(total));
+        "#;
+        let checked = Runtime::new().with_type_checks(true);
+        assert_eq!(Value::from("hi 6".to_string()), run_program_with(program, checked).unwrap());
+
+        let message = |program: &str| {
+            let runtime = Runtime::new().with_type_checks(true);
+            run_program_with(program, runtime).unwrap_err().to_string()
+        };
+        assert_eq!("expected argument `a` to be num, got string", message(r#"f = fn(a: num) => a; f("1");"#));
+        assert_eq!("expected argument `k` to be num, got null", message("s = {f: fn(self, k: num) => k}; s.f(null);"));
+        assert_eq!("expected return value of `f` to be num, got null", message("f = fn() -> num {}; f();"));
+        assert_eq!("expected return value to be array, got num", message("x = (fn() -> array => 1)();"));
+        assert_eq!("expected `x` to be string, got struct", message("x: string = {};"));
+
+        // annotations are only checked when enabled
+        assert_eq!(Value::Null, run_program_with(r#"x: num = "1"; return null;"#, Runtime::new()).unwrap());
+    }
+
+    #[test]
+    fn checker_types() {
+        let program = r#"add = fn(a: num, b: num) -> num => a + b;
+x: string = add(1, 2);
+name = "n";
+add(name, len(name));
+greet = fn(who: string) -> string {
+    if (who == "") {
+        return 5;
+    }
+};
+y: array = [0:3];
+return x + greet("a") + y;
+"#;
+        assert_eq!(
+            vec![
+                (2, "expected `x` to be string, got num".to_string()),
+                (4, "expected argument `a` to be num, got string".to_string()),
+                (5, "function may end without returning a string".to_string()),
+                (7, "expected return value of `greet` to be string, got num".to_string()),
+            ],
+            diagnostics(program)
+        );
+
+        // annotations are kept when formatting
+        let source = "f = fn(a: num, b) -> array => [a];\nx: null = null;\n";
+        assert_eq!(source, puffin::format_source(source).unwrap());
+    }
+
+    /// frames messages with `Content-Length` headers, as the LSP and DAP servers read them
+    fn frame(messages: Vec<serde_json::Value>) -> Vec<u8> {
+        let mut framed = Vec::new();
+        for message in messages {
+            let body = message.to_string();
+            framed.extend(format!("Content-Length: {}\r\n\r\n{}", body.len(), body).bytes());
+        }
+        framed
+    }
+
+    /// reads the messages framed in `output`
+    fn unframe(output: &[u8]) -> Vec<serde_json::Value> {
+        let mut messages = Vec::new();
+        let mut rest = output;
+        while !rest.is_empty() {
+            let text = std::str::from_utf8(rest).unwrap();
+            let header_end = text.find("\r\n\r\n").unwrap();
+            let length: usize = text["Content-Length: ".len()..header_end].parse().unwrap();
+            let body = &rest[header_end + 4..header_end + 4 + length];
+            messages.push(serde_json::from_slice(body).unwrap());
+            rest = &rest[header_end + 4 + length..];
+        }
+        messages
+    }
+
+    /// runs the language server on `requests`, returning the messages it sent
+    fn lsp(requests: Vec<serde_json::Value>) -> Vec<serde_json::Value> {
+        let input = frame(requests);
+        let mut output = Vec::new();
+        puffin::lsp::serve(&input[..], &mut output).unwrap();
+        unframe(&output)
+    }
+
+    #[test]
+    fn language_server() {
+        use serde_json::json;
+
+        let uri = "file:///main.puf";
+        let doc = json!({ "uri": uri });
+        let source = "add = fn(a: num, b) -> num {\n    return a + b;\n};\npoint = {x: 1, y: 2};\nreturn add(point.x, len(\"s\"));\n";
+        let at = |id: u32, method: &str, line: u32, character: u32| {
+            json!({"jsonrpc": "2.0", "id": id, "method": method, "params": {
+                "textDocument": doc, "position": {"line": line, "character": character},
+            }})
+        };
+        let messages = lsp(vec![
+            json!({"jsonrpc": "2.0", "id": 0, "method": "initialize", "params": {}}),
+            json!({"jsonrpc": "2.0", "method": "initialized", "params": {}}),
+            json!({"jsonrpc": "2.0", "method": "textDocument/didOpen", "params": {
+                "textDocument": {"uri": uri, "languageId": "puffin", "version": 1, "text": source},
+            }}),
+            at(1, "textDocument/definition", 4, 8),
+            at(2, "textDocument/hover", 4, 21),
+            at(3, "textDocument/hover", 1, 11),
+            at(4, "textDocument/completion", 4, 17),
+            at(5, "textDocument/completion", 4, 8),
+            json!({"jsonrpc": "2.0", "id": 6, "method": "textDocument/documentSymbol", "params": {"textDocument": doc}}),
+            json!({"jsonrpc": "2.0", "method": "textDocument/didChange", "params": {
+                "textDocument": {"uri": uri, "version": 2},
+                "contentChanges": [{"text": "x = 1;\nreturn x +;\n"}],
+            }}),
+            at(7, "textDocument/definition", 3, 2),
+            json!({"jsonrpc": "2.0", "id": 8, "method": "unknown/method", "params": {}}),
+            json!({"jsonrpc": "2.0", "id": 9, "method": "shutdown"}),
+            json!({"jsonrpc": "2.0", "method": "exit"}),
+            json!({"jsonrpc": "2.0", "id": 10, "method": "shutdown"}),
+        ]);
+        let response = |id: u32| messages.iter().find(|message| message["id"] == id).unwrap();
+        let result = |id: u32| response(id)["result"].clone();
+        let range = |line: u32, start: u32, end_line: u32, end: u32| {
+            json!({"start": {"line": line, "character": start}, "end": {"line": end_line, "character": end}})
+        };
+
+        assert_eq!(true, result(0)["capabilities"]["hoverProvider"]);
+
+        // the first document has no errors
+        assert_eq!("textDocument/publishDiagnostics", messages[1]["method"]);
+        assert_eq!(json!([]), messages[1]["params"]["diagnostics"]);
+
+        assert_eq!(json!({"uri": uri, "range": range(0, 0, 0, 3)}), result(1));
+        assert_eq!(
+            "```puffin\nfn len(a)\n```\n\nReturns the length of the array, string, or structure `a`",
+            result(2)["contents"]["value"]
+        );
+        assert_eq!("```puffin\n(argument) a: num\n```", result(3)["contents"]["value"]);
+
+        let labels = |id: u32| -> Vec<String> {
+            result(id)
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|item| item["label"].as_str().unwrap().to_string())
+                .collect()
+        };
+        assert_eq!(vec!["x", "y"], labels(4));
+        let names = labels(5);
+        for name in &["add", "point", "len", "print", "while"] {
+            assert!(names.contains(&name.to_string()), "missing completion {}", name);
+        }
+        assert!(!names.contains(&"a".to_string()));
+
+        assert_eq!(
+            json!([{
+                "name": "add",
+                "detail": "fn add(a: num, b) -> num",
+                "kind": 12,
+                "range": range(0, 0, 2, 2),
+                "selectionRange": range(0, 0, 0, 3),
+            }]),
+            result(6)
+        );
+
+        // parse errors are published, and the analysis of the last version that parsed is kept
+        let diagnostics = &messages
+            .iter()
+            .filter(|message| message["method"] == "textDocument/publishDiagnostics")
+            .nth(1)
+            .unwrap()["params"]["diagnostics"];
+        assert_eq!(1, diagnostics[0]["severity"]);
+        assert_eq!(1, diagnostics[0]["range"]["start"]["line"]);
+        assert_eq!(json!({"uri": uri, "range": range(3, 0, 3, 5)}), result(7));
+
+        assert_eq!(-32601, response(8)["error"]["code"]);
+        assert_eq!(serde_json::Value::Null, result(9));
+        // nothing is handled after exit
+        assert!(messages.iter().all(|message| message["id"] != 10));
+    }
+
+    #[test]
+    fn language_server_non_ascii() {
+        use serde_json::json;
+
+        let uri = "file:///main.puf";
+        let change = |text: &str| {
+            json!({"jsonrpc": "2.0", "method": "textDocument/didChange", "params": {
+                "textDocument": {"uri": uri, "version": 2},
+                "contentChanges": [{"text": text}],
+            }})
+        };
+        let complete = |id: u32, line: u32, character: u32| {
+            json!({"jsonrpc": "2.0", "id": id, "method": "textDocument/completion", "params": {
+                "textDocument": {"uri": uri}, "position": {"line": line, "character": character},
+            }})
+        };
+        let messages = lsp(vec![
+            json!({"jsonrpc": "2.0", "method": "textDocument/didOpen", "params": {
+                "textDocument": {"uri": uri, "languageId": "puffin", "version": 1, "text": "point = {x: 1};\n"},
+            }}),
+            change("point = {x: 1};\ncafé."),
+            complete(1, 1, 5),
+            change("point = {x: 1};\néépoint."),
+            complete(2, 1, 8),
+        ]);
+        let result = |id: u32| messages.iter().find(|message| message["id"] == id).unwrap()["result"].clone();
+
+        // identifiers are ascii, so nothing before the dot is a name
+        assert_eq!(json!([]), result(1));
+        assert_eq!(json!([{"label": "x", "kind": 5}]), result(2));
+    }
+
+    #[test]
+    fn language_server_bad_message() {
+        use serde_json::json;
+
+        let mut input = b"Content-Length: 9\r\n\r\n{\"id\": 1,".to_vec();
+        input.extend(frame(vec![json!({"jsonrpc": "2.0", "id": 2, "method": "shutdown"})]));
+        let mut output = Vec::new();
+        puffin::lsp::serve(&input[..], &mut output).unwrap();
+        let messages = unframe(&output);
+
+        // the server answers the message it couldn't parse, and keeps serving
+        assert_eq!(2, messages.len());
+        assert_eq!(-32700, messages[0]["error"]["code"]);
+        assert_eq!(serde_json::Value::Null, messages[0]["id"]);
+        assert_eq!(json!({"jsonrpc": "2.0", "id": 2, "result": null}), messages[1]);
+    }
+
+    #[test]
+    fn debugger() {
+        use puffin::{debugger::Debugger, interpreter::runtime::SharedBuffer};
+        use std::{io::Cursor, rc::Rc};
+
+        let source = r#"add = fn(a, b) {
+    sum = a + b;
+    return sum;
+};
+total = 0;
+for (i in [0:3]) {
+    total = add(total, i);
+}
+print(total);
+return total;
+"#;
+        let commands = [
+            "break 2", "c", "bt", "locals", "p a", "p nope", "n", "p sum", "",
+            "delete 2", "break 9", "s", "o", "locals", "bogus", "q",
+        ];
+        let output = SharedBuffer::new();
+        let debugger = Debugger::with_io(source, Cursor::new(commands.join("\n")), output.clone());
+        let stdout = SharedBuffer::new();
+        let runtime = Runtime::new()
+            .with_hooks(Rc::new(debugger))
+            .with_stdout(stdout.clone());
+
+        let result = puffin::run_source_with(source, runtime);
+        assert_eq!("Runtime Error: program was interrupted", result.unwrap_err().to_string());
+        // the program didn't reach its print
+        assert_eq!("", stdout.contents());
+
+        let output = output.contents();
+        let expected = [
+            // stops before the first statement
+            "stopped at line 1",
+            ">    1 | add = fn(a, b) {",
+            "(debug) breakpoint set at line 2",
+            "(debug) breakpoint at line 2",
+            ">    2 |     sum = a + b;",
+            "(debug) #0 add at line 2\n#1 <program> at line 7\n",
+            // named closures bind themselves in their calls, for recursion
+            "(debug) locals:\n    a = 0\n    add = <add fn(a, b)> \n    b = 0\nglobals:\n    add = <add fn(a, b)> \n    i = 0\n    total = 0\n",
+            "(debug) a = 0\n",
+            "(debug) unbound name `nope`\n",
+            // next stays in the call
+            "(debug) stopped at line 3",
+            "(debug) sum = 0\n",
+            // an empty command repeats the last one
+            "(debug) sum = 0\n",
+            "(debug) breakpoint removed from line 2\n",
+            "(debug) breakpoint set at line 9\n",
+            // step leaves the call, since it returned, into the next iteration
+            "(debug) stopped at line 7",
+            // out of the program runs to the breakpoint
+            "(debug) breakpoint at line 9",
+            "(debug) globals:\n    add = <add fn(a, b)> \n    i = 2\n    total = 3\n",
+            "(debug) unknown command `bogus`, try `help`\n",
+        ];
+        let mut rest = &output[..];
+        for part in expected.iter() {
+            let found = rest.find(part).unwrap_or_else(|| panic!("missing {:?} in {}", part, output));
+            rest = &rest[found + part.len()..];
+        }
+    }
+
+    #[test]
+    fn debugger_loop_breakpoint() {
+        use puffin::{debugger::Debugger, interpreter::runtime::SharedBuffer};
+        use std::{io::Cursor, rc::Rc};
+
+        let source = r#"total = 0;
+for (i in [0:3]) {
+    x = i; total += x;
+}
+return total;
+"#;
+        let commands = ["break 3", "c", "p i", "c", "p i", "c", "p i", "c"];
+        let output = SharedBuffer::new();
+        let debugger = Debugger::with_io(source, Cursor::new(commands.join("\n")), output.clone());
+        let runtime = Runtime::new().with_hooks(Rc::new(debugger));
+        assert_eq!(Value::Num(3.0), puffin::run_source_with(source, runtime).unwrap());
+
+        // a breakpoint in a loop stops once each iteration, at the first statement on its line
+        let output = output.contents();
+        assert_eq!(3, output.matches("breakpoint at line 3").count(), "{}", output);
+        let expected = ["i = 0\n", "i = 1\n", "i = 2\n"];
+        let mut rest = &output[..];
+        for part in expected.iter() {
+            let found = rest.find(part).unwrap_or_else(|| panic!("missing {:?} in {}", part, output));
+            rest = &rest[found + part.len()..];
+        }
+    }
+
+    #[test]
+    fn debug_adapter() {
+        use puffin::interpreter::runtime::SharedBuffer;
+        use serde_json::json;
+        use std::io::Cursor;
+
+        let path = std::env::temp_dir().join(format!("puffin_dap_{}.puf", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let source = r#"point = {x: 1, tags: [2]};
+scale = fn(p, k) {
+    p.x = p.x * k;
+    return p;
+};
+scale(point, 3);
+print(point.x);
+"#;
+        std::fs::write(&path, source).unwrap();
+
+        let request = |seq: u32, command: &str, arguments: serde_json::Value| {
+            json!({"seq": seq, "type": "request", "command": command, "arguments": arguments})
+        };
+        let input = frame(vec![
+            request(1, "initialize", json!({"adapterID": "puffin"})),
+            request(2, "launch", json!({"program": path})),
+            request(3, "setBreakpoints", json!({"source": {"path": path}, "breakpoints": [{"line": 3}]})),
+            request(4, "configurationDone", json!({})),
+            request(5, "stackTrace", json!({"threadId": 1})),
+            request(6, "scopes", json!({"frameId": 2})),
+            request(7, "variables", json!({"variablesReference": 1})),
+            request(8, "variables", json!({"variablesReference": 3})),
+            request(9, "variables", json!({"variablesReference": 4})),
+            request(10, "evaluate", json!({"expression": "k", "frameId": 2})),
+            request(11, "next", json!({"threadId": 1})),
+            request(12, "stackTrace", json!({"threadId": 1})),
+            request(13, "continue", json!({"threadId": 1})),
+            request(14, "threads", json!({})),
+            request(15, "bogus", json!({})),
+        ]);
+        let output = SharedBuffer::new();
+        puffin::debugger::dap::serve(Cursor::new(input), output.clone()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let messages = unframe(output.contents().as_bytes());
+
+        let response = |seq: u32| {
+            messages
+                .iter()
+                .find(|message| message["request_seq"] == seq)
+                .unwrap_or_else(|| panic!("no response to {}", seq))
+        };
+        let body = |seq: u32| response(seq)["body"].clone();
+        let events: Vec<&serde_json::Value> = messages
+            .iter()
+            .filter(|message| message["type"] == "event")
+            .collect();
+        let named = |variables: serde_json::Value| -> Vec<(String, String, u64)> {
+            variables["variables"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|variable| {
+                    let name = variable["name"].as_str().unwrap().to_string();
+                    let value = variable["value"].as_str().unwrap().to_string();
+                    (name, value, variable["variablesReference"].as_u64().unwrap())
+                })
+                .collect()
+        };
+
+        assert_eq!(true, body(1)["supportsConfigurationDoneRequest"]);
+        assert_eq!("initialized", events[0]["event"]);
+        assert_eq!(true, response(2)["success"]);
+        assert_eq!(json!([{"verified": true, "line": 3}]), body(3)["breakpoints"]);
+
+        // stopped at the breakpoint in the call
+        assert_eq!("stopped", events[1]["event"]);
+        assert_eq!("breakpoint", events[1]["body"]["reason"]);
+        let frames = body(5)["stackFrames"].clone();
+        assert_eq!(2, frames.as_array().unwrap().len());
+        assert_eq!(("scale", 3), (frames[0]["name"].as_str().unwrap(), frames[0]["line"].as_u64().unwrap()));
+        assert_eq!(("<program>", 6), (frames[1]["name"].as_str().unwrap(), frames[1]["line"].as_u64().unwrap()));
+        assert_eq!(path, frames[0]["source"]["path"]);
+
+        let scopes: Vec<String> = body(6)["scopes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|scope| scope["name"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(vec!["Locals", "Globals"], scopes);
+
+        // structures and arrays expand into their fields and elements
+        let locals = named(body(7));
+        assert_eq!(("k".to_string(), "3".to_string(), 0), locals[0]);
+        assert_eq!(("p".to_string(), 3), (locals[1].0.clone(), locals[1].2));
+        assert_eq!("scale", locals[2].0);
+        assert_eq!(
+            vec![("tags".to_string(), "[null, null]".to_string(), 4), ("x".to_string(), "1".to_string(), 0)],
+            named(body(8))
+        );
+        assert_eq!(
+            vec![("0".to_string(), "null".to_string(), 0), ("1".to_string(), "null".to_string(), 0)],
+            named(body(9))
+        );
+        assert_eq!("3", body(10)["result"]);
+
+        // next stops at the following statement of the call
+        assert_eq!("step", events[2]["body"]["reason"]);
+        assert_eq!(4, body(12)["stackFrames"][0]["line"]);
+
+        // the program's output is sent as events, then it exits
+        let rest: Vec<(&str, &serde_json::Value)> = events[3..]
+            .iter()
+            .map(|event| (event["event"].as_str().unwrap(), &event["body"]))
+            .collect();
+        assert_eq!(
+            vec![
+                ("output", &json!({"category": "stdout", "output": "3 "})),
+                ("exited", &json!({"exitCode": 0})),
+                ("terminated", &json!({})),
+            ],
+            rest
+        );
+        assert_eq!("main", body(14)["threads"][0]["name"]);
+        assert_eq!(false, response(15)["success"]);
+        assert_eq!("unsupported request `bogus`", response(15)["message"]);
+
+        // every message is numbered
+        for (i, message) in messages.iter().enumerate() {
+            assert_eq!(i as u64 + 1, message["seq"].as_u64().unwrap());
+        }
+    }
+
+    #[test]
+    fn profiler() {
+        use puffin::profiler::Profiler;
+        use std::rc::Rc;
+
+        let source = r#"fib = fn(n) {
+    if (n < 2) {
+        return n;
+    }
+    return fib(n - 1) + fib(n - 2);
+};
+twice = fn(f) => fn(x) => f(f(x));
+inc = twice(fn(x) => x + 1);
+return fib(6) + inc(0);
+"#;
+        let profiler = Rc::new(Profiler::new());
+        let result = puffin::run_source_with(source, Runtime::new().with_hooks(profiler.clone()));
+        assert_eq!(Value::Num(10.0), result.unwrap());
+        profiler.finish();
+
+        let functions: HashMap<String, puffin::profiler::FunctionStats> = profiler.functions().into_iter().collect();
+        let calls: HashMap<&str, u64> = functions.iter().map(|(name, stats)| (name.as_str(), stats.calls)).collect();
+        let expected: HashMap<&str, u64> = vec![
+            ("<program>", 1),
+            ("fib", 25),
+            ("twice", 1),
+            // closures returned by a call are named after the name they were bound to
+            ("inc", 1),
+            // anonymous closures are named after the line they are defined on
+            ("<anonymous:8>", 2),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(expected, calls);
+        for (name, stats) in &functions {
+            assert!(stats.inclusive >= stats.exclusive, "{}: {:?}", name, stats);
+        }
+        // recursive calls are counted once in the inclusive time
+        assert!(functions["<program>"].inclusive >= functions["fib"].inclusive);
+
+        let hits: HashMap<usize, u64> = profiler.lines().into_iter().map(|(line, stats)| (line, stats.hits)).collect();
+        assert_eq!(Some(&25), hits.get(&2));
+        assert_eq!(Some(&13), hits.get(&3));
+        assert_eq!(Some(&12), hits.get(&5));
+        assert_eq!(Some(&1), hits.get(&9));
+        assert_eq!(None, hits.get(&4));
+
+        let folded = profiler.folded();
+        let stacks: Vec<&str> = folded.lines().map(|line| line.rsplit_once(' ').unwrap().0).collect();
+        assert!(stacks.contains(&"<program>;fib;fib;fib;fib;fib;fib"), "{}", folded);
+        assert!(stacks.contains(&"<program>;inc;<anonymous:8>"), "{}", folded);
+        assert!(!stacks.contains(&"<program>;fib;fib;fib;fib;fib;fib;fib"), "{}", folded);
+        for line in folded.lines() {
+            assert!(line.rsplit_once(' ').unwrap().1.parse::<u128>().is_ok(), "{}", line);
+        }
+
+        let table = profiler.table();
+        assert!(table.starts_with("function"), "{}", table);
+        assert!(table.contains("\nline "), "{}", table);
+    }
+
+    #[test]
+    fn ast_visitor() {
+        use puffin::ast::visit::{self, Binding, Function, Visitor};
+
+        /// records the names bound in each scope, and the names of the functions
+        #[derive(Default)]
+        struct Names {
+            bound: Vec<String>,
+            functions: Vec<String>,
+        }
+
+        impl<'a> Visitor<'a> for Names {
+            fn function(&mut self, function: Function<'a>) {
+                let name = match function.name {
+                    Some(name) => name,
+                    None if function.is_receiver() => "<receiver>",
+                    None => "<anonymous>",
+                };
+                self.functions.push(name.to_string());
+                visit::walk_function(self, function);
+            }
+
+            fn bind(&mut self, binding: Binding<'a>) {
+                let name = match binding {
+                    Binding::Argument { name, .. } => name,
+                    Binding::Assign { name, .. } | Binding::Loop { name, .. } => name,
+                };
+                self.bound.push(name.to_string());
+            }
+        }
+
+        let program = build_program(
+            r#"f = fn(a) {
+    return g(a);
+};
+if (true) {
+    for (i in [0:2]) {
+        x = f(i);
+    }
+}
+g = fn(b) => b;
+s = { f: fn(self) => self, g: fn() => 1 };
+"#,
+        );
+        let mut names = Names::default();
+        visit::walk_program(&mut names, &program);
+
+        // names bound anywhere in a scope are bound before its statements are visited
+        assert_eq!(vec!["f", "i", "x", "g", "s", "a", "b", "self"], names.bound);
+        assert_eq!(vec!["f", "g", "<receiver>", "<anonymous>"], names.functions);
+    }
+
+    #[test]
+    fn coverage() {
+        use puffin::coverage::{Branch, Coverage};
+        use std::rc::Rc;
+
+        let source = r#"sign = fn(n) {
+    if (n < 0) {
+        return -1;
+    }
+    if (n == 0) {
+        return 0;
+    } else {
+        return 1;
+    }
+};
+unused = fn() {
+    return 0;
+};
+total = 0;
+for (i = 0; i < 3; i += 1) {
+    total += sign(i);
+}
+return total;
+"#;
+        let program = puffin::parse(source).unwrap();
+        let coverage = Rc::new(Coverage::new(&program));
+        let result = puffin::interpreter::eval_with(&program, Runtime::new().with_hooks(coverage.clone()));
+        assert_eq!(Value::Num(2.0), result.unwrap());
+
+        let lines: HashMap<usize, u64> = coverage.lines().into_iter().collect();
+        let expected: HashMap<usize, u64> = vec![
+            (1, 1),
+            (2, 3),
+            // lines that never executed are reported too
+            (3, 0),
+            (5, 3),
+            (6, 1),
+            (8, 2),
+            (11, 1),
+            (12, 0),
+            (14, 1),
+            // the loop, its initialization, and each advance
+            (15, 5),
+            (16, 3),
+            (18, 1),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(expected, lines);
+
+        let branches = vec![
+            Branch { line: 2, taken: 0, not_taken: 3 },
+            Branch { line: 5, taken: 1, not_taken: 2 },
+        ];
+        assert_eq!(branches, coverage.branches());
+
+        let lcov = coverage.lcov("sign.puf");
+        assert!(lcov.starts_with("TN:\nSF:sign.puf\n"), "{}", lcov);
+        assert!(lcov.contains("BRDA:2,0,0,0\nBRDA:2,0,1,3\nBRDA:5,1,0,1\nBRDA:5,1,1,2\n"), "{}", lcov);
+        assert!(lcov.contains("BRF:4\nBRH:3\n"), "{}", lcov);
+        assert!(lcov.contains("DA:3,0\n"), "{}", lcov);
+        assert!(lcov.contains("DA:16,3\n"), "{}", lcov);
+        assert!(lcov.contains("LF:12\nLH:10\n"), "{}", lcov);
+        assert!(lcov.ends_with("end_of_record\n"), "{}", lcov);
+
+        // branches of an `if` that was never reached aren't counted
+        let program = puffin::parse("f = fn(n) {\n    if (n) {\n        return 1;\n    }\n};\n").unwrap();
+        let lcov = Coverage::new(&program).lcov("f.puf");
+        assert!(lcov.contains("BRDA:2,0,0,-\nBRDA:2,0,1,-\nBRF:2\nBRH:0\n"), "{}", lcov);
+    }
+
+    #[test]
+    fn assertions() {
+        let message = |program: &str| run_program_with(program, Runtime::new()).unwrap_err().to_string();
+
+        assert_eq!(Value::Null, run_program("assert(1); assert_eq([1:3], [1:3], \"ranges\"); return assert(2 > 1);"));
+        assert_eq!("assertion failed: condition was false", message("assert(1 > 2);"));
+        assert_eq!("assertion failed: too small", message("assert(0, \"too small\");"));
+        assert_eq!("assertion failed: 'a' != 'b'", message("assert_eq(\"a\", \"b\");"));
+        assert_eq!("assertion failed: sums (3 != 4)", message("assert_eq(1 + 2, 4, \"sums\");"));
+        assert_eq!("expected 2 or 3 arguments, got 1 when calling `assert_eq`", message("assert_eq(1);"));
+
+        // assert_throws returns the message of the error
+        assert_eq!(
+            Value::String("unbound name `y`".to_string()),
+            run_program("return assert_throws(fn() => y);")
+        );
+        assert_eq!(
+            Value::String("assertion failed: condition was false".to_string()),
+            run_program("return assert_throws(fn() { assert(0); });")
+        );
+        assert_eq!("assertion failed: expected an error", message("assert_throws(fn() => 1);"));
+        assert_eq!("assertion failed: no error (expected an error)", message("assert_throws(fn() => 1, \"no error\");"));
+        assert_eq!("expected 1 or 2 arguments, got 0 when calling `assert_throws`", message("assert_throws();"));
+        // `f` must be callable without arguments
+        assert_eq!("unexpected type of value 5", message("assert_throws(5);"));
+        assert_eq!(
+            "expected 1 argument, got 0 when calling `<anonymous>`",
+            message("assert_throws(fn(x) => x);")
+        );
+        assert_eq!(
+            "expected 2 arguments, got 0 when calling `add`",
+            message("add = fn(a, b) => a + b; assert_throws(add);")
+        );
+        assert!(matches!(
+            run_program_with("assert_throws(fn() => exit(3));", Runtime::new()),
+            Err(InterpreterError::Exit(3))
+        ));
+    }
+
+    #[test]
+    fn test_runner() {
+        use puffin::testing;
+
+        let program = build_program(
+            r#"seen = [1];
+add = fn(a, b) => a + b;
+test_add = fn() {
+    push(seen, 1);
+    assert_eq(len(seen), 2);
+    assert_eq(add(1, 2), 3);
+};
+test_fails = fn() {
+    assert_eq(add(1, 1), 3, "one and one");
+};
+test_with_args = fn(a) {
+    return a;
+};
+helper = fn() => 1;
+test_throws = fn() {
+    push(seen, 1);
+    assert_eq(len(seen), 2);
+    assert_throws(fn() => add(1));
+};
+"#,
+        );
+
+        // tests are closures without arguments, named test_*
+        assert_eq!(vec!["test_add", "test_fails", "test_throws"], testing::discover(&program));
+        assert!(testing::selected("test_add", None));
+        assert!(testing::selected("test_add", Some("add")));
+        assert!(!testing::selected("test_throws", Some("add")));
+
+        // each test has its own global environment, so both see `seen` with one element
+        assert!(testing::run(&program, "test_add", None).result.is_ok());
+        assert!(testing::run(&program, "test_throws", None).result.is_ok());
+        match testing::run(&program, "test_fails", None).result {
+            Err(err) => assert_eq!("assertion failed: one and one (2 != 3)", err.to_string()),
+            other => panic!("{:?}", other),
+        }
+        assert!(matches!(
+            testing::run(&program, "test_missing", None).result,
+            Err(InterpreterError::UnboundName(_))
+        ));
+
+        // only the top-level assignments run before a test, so the program's
+        // other side effects don't happen once per test
+        let program = build_program(
+            r#"println("top");
+exit(1);
+total = 0;
+for (i in [0:3]) { total += i; }
+test_total = fn() {
+    assert_eq(total, 0);
+};
+"#,
+        );
+        let captured = testing::run(&program, "test_total", None);
+        assert!(captured.result.is_ok(), "{:?}", captured.result);
+        assert_eq!("", captured.stdout);
+
+        // tests read no input, their output is captured, and they can't run forever
+        let program = build_program(
+            r#"test_output = fn() {
+    println("quack");
+    assert_eq(input_str(), "");
+    error("honk");
+};
+test_forever = fn() {
+    while (true) {}
+};
+"#,
+        );
+        let captured = testing::run(&program, "test_output", None);
+        assert_eq!("quack\n ", captured.stdout);
+        assert_eq!("ERR: honk \n", captured.stderr);
+        assert!(matches!(captured.result, Err(InterpreterError::Error)), "{:?}", captured.result);
+        assert!(matches!(
+            testing::run(&program, "test_forever", None).result,
+            Err(InterpreterError::StepLimit(testing::MAX_STEPS))
+        ));
+    }
+
+    #[test]
+    fn tracer() {
+        use puffin::{interpreter::runtime::SharedBuffer, tracer::Tracer};
+        use std::rc::Rc;
+
+        let source = r#"add = fn(a, b) {
+    return a + b;
+};
+twice = fn(x) => add(x, x);
+s = { items: [2] };
+s.items[1 - 1] = twice(2);
+return s.items[0];
+"#;
+        let trace = |tracer: Tracer| {
+            let runtime = Runtime::new().with_hooks(Rc::new(tracer));
+            assert_eq!(Value::Num(4.0), puffin::run_source_with(source, runtime).unwrap());
+        };
+
+        let output = SharedBuffer::new();
+        trace(Tracer::with_output("t.puf", source, output.clone()));
+        let expected = "\
+t.puf:1:1: add = fn(a, b) {
+add = <add fn(a, b)> 
+t.puf:4:1: twice = fn(x) => add(x, x);
+twice = <twice fn(x)> 
+t.puf:5:1: s = { items: [2] };
+s = {items: [null, null]}
+t.puf:6:1: s.items[1 - 1] = twice(2);
+-> twice(x = 2)
+  t.puf:4:15: => add(x, x)
+  -> add(a = 2, b = 2)
+    t.puf:2:5: return a + b;
+  <- add returned 4
+<- twice returned 4
+s.items[1 - 1] = 4
+t.puf:7:1: return s.items[0];
+";
+        assert_eq!(expected, output.contents());
+
+        // only the calls of `add` are traced
+        let output = SharedBuffer::new();
+        trace(Tracer::with_output("t.puf", source, output.clone()).with_functions(vec!["add".to_string()]));
+        let expected = "  -> add(a = 2, b = 2)\n    t.puf:2:5: return a + b;\n  <- add returned 4\n";
+        assert_eq!(expected, output.contents());
+    }
+
+    #[test]
+    fn repl() {
+        use puffin::{interpreter::repl::Repl, repl};
+
+        // input continues while brackets and strings are open
+        assert!(repl::incomplete("f = fn(a) {\n"));
+        assert!(repl::incomplete("x = [1:\n"));
+        assert!(repl::incomplete("s = \"first line\n"));
+        assert!(!repl::incomplete("f = fn(a) {\n    return a;\n};\n"));
+        assert!(!repl::incomplete("s = \"{\";\n"));
+        assert!(!repl::incomplete("x = 1; // {\n"));
+        assert!(!repl::incomplete("x = 1);\n"));
+
+        // errors are returned, and the session continues
+        let session = Repl::new();
+        assert_eq!(Value::Null, repl::eval_input(&session, "double = fn(a) => a * 2;").unwrap());
+        let err = repl::eval_input(&session, "x = 1; y = z; w = 2;").unwrap_err();
+        assert_eq!("Runtime Error: unbound name `z`", err.to_string());
+        assert!(matches!(repl::eval_input(&session, "x = "), Err(puffin::Error::Parse(_))));
+        // statements before the error keep their effects
+        assert_eq!(Value::Num(2.0), repl::eval_input(&session, "double(x);").unwrap());
+        assert!(repl::eval_input(&session, "w;").is_err());
+        // exits are returned too, for the binary to exit with their code
+        assert!(matches!(
+            repl::eval_input(&session, "exit(3);"),
+            Err(puffin::Error::Runtime(InterpreterError::Exit(3)))
+        ));
+
+        // names bound in the session complete, along with builtins
+        assert_eq!(vec!["double".to_string()], session.complete("dou"));
+        assert_eq!(vec!["x".to_string()], session.complete("x"));
+        assert!(session.complete("json_").contains(&"json_parse".to_string()));
+        assert!(session.complete("").contains(&"double".to_string()));
+    }
 }